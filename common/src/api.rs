@@ -0,0 +1,241 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::birthday::{BirthdayEngine, LeapDayPolicy};
+use crate::calendars::{parse_calendar_form, CalendarDate};
+use crate::events::{event_title, parse_event_form, EventType};
+use crate::notice::normalize_notice_offsets;
+use crate::recurrence::Recurrence;
+use crate::{DbReminder, DbUser, SUPPORTED_TIMEZONES, UNKNOWN_BIRTH_YEAR};
+
+/// Furthest ahead `/api/v1/upcoming` looks
+pub const MAX_UPCOMING_DAYS: i64 = 366;
+
+/// A request field that failed validation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidField {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl InvalidField {
+    fn new(field: &'static str, message: impl Into<String>) -> Self {
+        InvalidField {
+            field,
+            message: message.into(),
+        }
+    }
+}
+
+/// Body for creating or replacing a reminder. Only `name` and `date` are
+/// required; the rest default as on the add form.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReminderInput {
+    pub name: String,
+    /// YYYY-MM-DD, or --MM-DD when the year isn't known
+    pub date: String,
+    #[serde(default)]
+    pub event_type: Option<String>,
+    #[serde(default)]
+    pub event_label: Option<String>,
+    /// Overrides the user's default offsets
+    #[serde(default)]
+    pub notice_offsets: Option<Vec<i64>>,
+    #[serde(default)]
+    pub rrule: Option<String>,
+    /// "gregorian" (the default), "chinese" or "hebrew"
+    #[serde(default)]
+    pub calendar: Option<String>,
+}
+
+/// A reminder that passed validation, ready to store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidReminder {
+    pub name: String,
+    /// In `UNKNOWN_BIRTH_YEAR` when the year isn't known
+    pub birthdate: NaiveDate,
+    pub year_known: bool,
+    pub event_type: EventType,
+    pub notice_offsets: Option<Vec<i64>>,
+    pub recurrence: Option<Recurrence>,
+    pub calendar_date: Option<CalendarDate>,
+}
+
+impl ReminderInput {
+    pub fn validate(&self) -> Result<ValidReminder, InvalidField> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err(InvalidField::new("name", "Name is required"));
+        }
+
+        let (birthdate, year_known) = parse_api_date(&self.date).ok_or_else(|| {
+            InvalidField::new(
+                "date",
+                "Use YYYY-MM-DD, or --MM-DD when the year isn't known",
+            )
+        })?;
+
+        let event_type = parse_event_form(
+            self.event_type.as_deref().unwrap_or("birthday"),
+            self.event_label.as_deref().unwrap_or(""),
+        )
+        .map_err(|e| InvalidField::new("event_type", e))?;
+
+        let notice_offsets = self
+            .notice_offsets
+            .clone()
+            .map(normalize_notice_offsets)
+            .transpose()
+            .map_err(|e| InvalidField::new("notice_offsets", e))?;
+
+        let recurrence = match self.rrule.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(rule) => Some(Recurrence::parse(rule).map_err(|e| InvalidField::new("rrule", e))?),
+        };
+
+        let calendar_date = parse_calendar_form(
+            self.calendar.as_deref().unwrap_or("gregorian"),
+            birthdate,
+            year_known,
+            recurrence.is_some(),
+        )
+        .map_err(|e| InvalidField::new("calendar", e))?;
+
+        Ok(ValidReminder {
+            name: name.to_string(),
+            birthdate,
+            year_known,
+            event_type,
+            notice_offsets,
+            recurrence,
+            calendar_date,
+        })
+    }
+}
+
+/// YYYY-MM-DD, or --MM-DD in the placeholder year
+fn parse_api_date(date: &str) -> Option<(NaiveDate, bool)> {
+    let date = date.trim();
+    match date.strip_prefix("--") {
+        Some(month_day) => {
+            NaiveDate::parse_from_str(&format!("{}-{}", UNKNOWN_BIRTH_YEAR, month_day), "%Y-%m-%d")
+                .ok()
+                .map(|date| (date, false))
+        }
+        None => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .ok()
+            .map(|date| (date, true)),
+    }
+}
+
+/// Body for updating settings; fields left out keep their current value.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SettingsInput {
+    #[serde(default)]
+    pub notice_offsets: Option<Vec<i64>>,
+    #[serde(default)]
+    pub send_hour: Option<i64>,
+    #[serde(default)]
+    pub iana_tz: Option<String>,
+    /// "feb28" or "mar1"
+    #[serde(default)]
+    pub leap_day_policy: Option<String>,
+}
+
+/// The user's settings after applying a `SettingsInput`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidSettings {
+    pub notice_offsets: Vec<i64>,
+    pub send_hour: i64,
+    pub iana_tz: String,
+    pub leap_day_policy: LeapDayPolicy,
+}
+
+impl SettingsInput {
+    pub fn apply(&self, user: &DbUser) -> Result<ValidSettings, InvalidField> {
+        let notice_offsets = match &self.notice_offsets {
+            Some(offsets) => normalize_notice_offsets(offsets.clone())
+                .map_err(|e| InvalidField::new("notice_offsets", e))?,
+            None => user.notice_offsets.clone(),
+        };
+
+        let send_hour = self.send_hour.unwrap_or(user.send_hour);
+        if !(0..=23).contains(&send_hour) {
+            return Err(InvalidField::new(
+                "send_hour",
+                "Send hour must be between 0 and 23",
+            ));
+        }
+
+        let iana_tz = self.iana_tz.clone().unwrap_or_else(|| user.iana_tz.clone());
+        if !SUPPORTED_TIMEZONES.contains(&iana_tz.as_str()) {
+            return Err(InvalidField::new(
+                "iana_tz",
+                format!("Timezone must be one of {}", SUPPORTED_TIMEZONES.join(", ")),
+            ));
+        }
+
+        let leap_day_policy = match self.leap_day_policy.as_deref() {
+            Some(policy) => LeapDayPolicy::parse(policy).ok_or_else(|| {
+                InvalidField::new("leap_day_policy", "Leap day policy must be feb28 or mar1")
+            })?,
+            None => user.leap_day_policy,
+        };
+
+        Ok(ValidSettings {
+            notice_offsets,
+            send_hour,
+            iana_tz,
+            leap_day_policy,
+        })
+    }
+}
+
+/// A coming occurrence, as `/api/v1/upcoming` lists it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UpcomingEvent {
+    pub reminder_id: i64,
+    pub name: String,
+    pub event_type: String,
+    pub event_label: Option<String>,
+    /// YYYY-MM-DD
+    pub date: String,
+    /// e.g. "Fri"
+    pub weekday: String,
+    pub days_until: i64,
+    /// Age turned or years since, when the year is known
+    pub years: Option<i32>,
+    /// e.g. "Jane's 34th"
+    pub title: String,
+}
+
+/// Occurrences in the next `within_days` days (0 = today only), soonest
+/// first.
+pub fn upcoming_events(
+    engine: &BirthdayEngine,
+    mut reminders: Vec<DbReminder>,
+    within_days: i64,
+) -> Vec<UpcomingEvent> {
+    engine.sort_upcoming(&mut reminders);
+
+    reminders
+        .into_iter()
+        .filter_map(|reminder| {
+            let info = engine.info(&reminder)?;
+            if info.days_until > within_days {
+                return None;
+            }
+            Some(UpcomingEvent {
+                reminder_id: reminder.id,
+                title: event_title(&reminder.name, &reminder.event_type, info.age_turning),
+                name: reminder.name,
+                event_type: reminder.event_type.as_str().to_string(),
+                event_label: reminder.event_type.label().map(str::to_string),
+                date: info.next_date.format("%Y-%m-%d").to_string(),
+                weekday: info.weekday.to_string(),
+                days_until: info.days_until,
+                years: info.age_turning,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,189 @@
+use rand::RngCore;
+use sqlx::{
+    sqlite::{SqlitePool, SqliteRow},
+    Row,
+};
+
+use crate::hash_secret;
+
+/// Every token starts with this, so a leaked one is easy to recognize
+pub const TOKEN_PREFIX: &str = "hbd_";
+
+pub const MAX_TOKEN_NAME_LEN: usize = 60;
+
+/// Tokens a user can hold at once, counting only unrevoked ones
+pub const MAX_ACTIVE_TOKENS: i64 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenScope {
+    /// GET requests
+    Read,
+    /// Everything that changes data
+    Write,
+}
+
+impl TokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Write => "write",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "read" => Some(TokenScope::Read),
+            "write" => Some(TokenScope::Write),
+            _ => None,
+        }
+    }
+}
+
+pub struct DbApiToken {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+impl DbApiToken {
+    pub fn has_scope(&self, scope: TokenScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+const API_TOKEN_COLUMNS: &str = "id, user_id, name, scopes, created_at, last_used_at, revoked_at";
+
+fn api_token_from_row(row: &SqliteRow) -> DbApiToken {
+    let scopes: String = row.get("scopes");
+    DbApiToken {
+        id: row.get("id"),
+        user_id: row.get("user_id"),
+        name: row.get("name"),
+        scopes: scopes
+            .split_whitespace()
+            .filter_map(TokenScope::parse)
+            .collect(),
+        created_at: row.get("created_at"),
+        last_used_at: row.get("last_used_at"),
+        revoked_at: row.get("revoked_at"),
+    }
+}
+
+/// Checks a new token's name and scopes, returning the trimmed name.
+pub fn validate_token_request(name: &str, scopes: &[TokenScope]) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Please name the token".to_string());
+    }
+    if name.chars().count() > MAX_TOKEN_NAME_LEN {
+        return Err(format!(
+            "Token names can be at most {} characters",
+            MAX_TOKEN_NAME_LEN
+        ));
+    }
+    if scopes.is_empty() {
+        return Err("Pick at least one scope".to_string());
+    }
+    Ok(name.to_string())
+}
+
+/// Creates a token, returning it alongside the secret. The secret can't be
+/// recovered later, so show it to the user now.
+pub async fn create_api_token(
+    pool: &SqlitePool,
+    user_id: i64,
+    name: &str,
+    scopes: &[TokenScope],
+) -> Result<(DbApiToken, String), sqlx::Error> {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let secret = format!("{}{}", TOKEN_PREFIX, hex::encode(bytes));
+
+    let mut scope_names: Vec<&str> = Vec::new();
+    for scope in [TokenScope::Read, TokenScope::Write] {
+        if scopes.contains(&scope) {
+            scope_names.push(scope.as_str());
+        }
+    }
+
+    let row = sqlx::query(&format!(
+        "INSERT INTO api_tokens (user_id, name, token_hash, scopes) VALUES (?, ?, ?, ?) RETURNING {}",
+        API_TOKEN_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(name)
+    .bind(hash_secret(&secret))
+    .bind(scope_names.join(" "))
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .next()
+    .ok_or(sqlx::Error::RowNotFound)?;
+
+    Ok((api_token_from_row(&row), secret))
+}
+
+/// The user's tokens, newest first, revoked ones included.
+pub async fn get_api_tokens_by_user_id(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> Result<Vec<DbApiToken>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM api_tokens WHERE user_id = ? ORDER BY id DESC",
+        API_TOKEN_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.iter().map(api_token_from_row).collect())
+}
+
+pub async fn count_active_api_tokens(pool: &SqlitePool, user_id: i64) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM api_tokens WHERE user_id = ? AND revoked_at IS NULL")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+}
+
+/// Revokes one of the user's tokens. False if it isn't theirs or was already
+/// revoked.
+pub async fn revoke_api_token(
+    pool: &SqlitePool,
+    user_id: i64,
+    token_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE api_tokens SET revoked_at = CURRENT_TIMESTAMP WHERE id = ? AND user_id = ? AND revoked_at IS NULL",
+    )
+    .bind(token_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// The unrevoked token a request presented, recording that it was used.
+pub async fn authenticate_api_token(
+    pool: &SqlitePool,
+    secret: &str,
+) -> Result<Option<DbApiToken>, sqlx::Error> {
+    if !secret.starts_with(TOKEN_PREFIX) {
+        return Ok(None);
+    }
+
+    let rows = sqlx::query(&format!(
+        "UPDATE api_tokens SET last_used_at = CURRENT_TIMESTAMP WHERE token_hash = ? AND revoked_at IS NULL RETURNING {}",
+        API_TOKEN_COLUMNS
+    ))
+    .bind(hash_secret(secret))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.first().map(api_token_from_row))
+}
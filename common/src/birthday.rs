@@ -0,0 +1,167 @@
+use chrono::{DateTime, Datelike, NaiveDate, Utc, Weekday};
+use chrono_tz::Tz;
+use log::warn;
+
+use crate::clock::Clock;
+use crate::{DbReminder, DbUser};
+
+/// When a Feb 29 birthday is celebrated in years without a Feb 29.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LeapDayPolicy {
+    #[default]
+    Feb28,
+    Mar1,
+}
+
+impl LeapDayPolicy {
+    /// Value stored in `users.leap_day_policy`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LeapDayPolicy::Feb28 => "feb28",
+            LeapDayPolicy::Mar1 => "mar1",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "feb28" => Some(LeapDayPolicy::Feb28),
+            "mar1" => Some(LeapDayPolicy::Mar1),
+            _ => None,
+        }
+    }
+}
+
+/// The date a birthday falls on in `year`, moving Feb 29 to Feb 28 or Mar 1
+/// in common years.
+pub fn birthday_in_year(birthdate: NaiveDate, year: i32, policy: LeapDayPolicy) -> NaiveDate {
+    if let Some(date) = birthdate.with_year(year) {
+        return date;
+    }
+
+    // Only Feb 29 lacks a date in some years
+    match policy {
+        LeapDayPolicy::Feb28 => NaiveDate::from_ymd_opt(year, 2, 28),
+        LeapDayPolicy::Mar1 => NaiveDate::from_ymd_opt(year, 3, 1),
+    }
+    .expect("Feb 28 and Mar 1 exist in every year")
+}
+
+/// The next time the birthday is celebrated on or after `today`, and the age
+/// turned on that day.
+pub fn next_birthday(
+    birthdate: NaiveDate,
+    today: NaiveDate,
+    policy: LeapDayPolicy,
+) -> (NaiveDate, i32) {
+    let this_year = birthday_in_year(birthdate, today.year(), policy);
+    let next = if this_year >= today {
+        this_year
+    } else {
+        birthday_in_year(birthdate, today.year() + 1, policy)
+    };
+
+    (next, next.year() - birthdate.year())
+}
+
+/// Turns a stored `reminders.birthdate` (milliseconds at UTC midnight) back
+/// into the date it represents.
+pub fn birthdate_from_timestamp(birthdate_timestamp: i64) -> Option<NaiveDate> {
+    DateTime::from_timestamp_millis(birthdate_timestamp).map(|dt| dt.date_naive())
+}
+
+/// The stored `reminders.birthdate` for a date, the inverse of
+/// `birthdate_from_timestamp`.
+pub fn birthdate_to_timestamp(birthdate: NaiveDate) -> i64 {
+    birthdate
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc()
+        .timestamp_millis()
+}
+
+/// The user's timezone. An unparseable zone falls back to the column default,
+/// America/New_York, so the user still gets their reminders.
+pub fn user_timezone(user: &DbUser) -> Tz {
+    user.iana_tz.parse().unwrap_or_else(|_| {
+        warn!("Invalid timezone for user {}: {}", user.id, user.iana_tz);
+        chrono_tz::America::New_York
+    })
+}
+
+/// A reminder's next birthday as seen from the user's local date.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BirthdayInfo {
+    pub next_date: NaiveDate,
+    pub days_until: i64,
+    /// `None` when the birth year isn't known
+    pub age_turning: Option<i32>,
+    pub weekday: Weekday,
+}
+
+/// Birthday math for one user: works out "today" in the user's timezone from
+/// a clock, so the dashboard, SMS commands and the sweeper all agree on what
+/// "today" and "tomorrow" mean.
+#[derive(Debug, Clone)]
+pub struct BirthdayEngine {
+    today: NaiveDate,
+    policy: LeapDayPolicy,
+}
+
+impl BirthdayEngine {
+    pub fn new(tz: Tz, policy: LeapDayPolicy, clock: &dyn Clock) -> Self {
+        Self::at(tz, policy, clock.now())
+    }
+
+    pub fn at(tz: Tz, policy: LeapDayPolicy, now: DateTime<Utc>) -> Self {
+        Self {
+            today: now.with_timezone(&tz).date_naive(),
+            policy,
+        }
+    }
+
+    /// Uses the user's zone and leap-day policy.
+    pub fn for_user(user: &DbUser, clock: &dyn Clock) -> Self {
+        Self::new(user_timezone(user), user.leap_day_policy, clock)
+    }
+
+    /// The user's local date
+    pub fn today(&self) -> NaiveDate {
+        self.today
+    }
+
+    /// Follows the reminder's recurrence rule or calendar when it has one.
+    /// `None` for an invalid birthdate, or a recurrence rule that has ended.
+    pub fn info(&self, reminder: &DbReminder) -> Option<BirthdayInfo> {
+        let birthdate = birthdate_from_timestamp(reminder.birthdate)?;
+        let (next_date, age_turning) = match (&reminder.recurrence, &reminder.calendar_date) {
+            // Ages don't line up with arbitrary repeats, so none is given
+            (Some(recurrence), _) => (recurrence.next_on_or_after(birthdate, self.today)?, None),
+            (None, Some(calendar_date)) => {
+                let (next_date, age_turning) =
+                    calendar_date.next_occurrence(birthdate, self.today)?;
+                (next_date, reminder.year_known.then_some(age_turning))
+            }
+            (None, None) => {
+                let (next_date, age_turning) = next_birthday(birthdate, self.today, self.policy);
+                (next_date, reminder.year_known.then_some(age_turning))
+            }
+        };
+
+        Some(BirthdayInfo {
+            next_date,
+            days_until: (next_date - self.today).num_days(),
+            age_turning,
+            weekday: next_date.weekday(),
+        })
+    }
+
+    /// Orders reminders soonest birthday first, then by name.
+    pub fn sort_upcoming(&self, reminders: &mut [DbReminder]) {
+        reminders.sort_by_cached_key(|reminder| {
+            (
+                self.info(reminder).map_or(i64::MAX, |info| info.days_until),
+                reminder.name.to_lowercase(),
+            )
+        });
+    }
+}
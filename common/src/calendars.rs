@@ -0,0 +1,251 @@
+use chrono::{Datelike, NaiveDate};
+use icu_calendar::chinese::Chinese;
+use icu_calendar::hebrew::Hebrew;
+use icu_calendar::types::{Era, MonthCode};
+use icu_calendar::{AsCalendar, Date, Iso};
+
+/// The calendar a reminder's date is kept in. Lunar and lunisolar dates land
+/// on a different Gregorian date every year.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CalendarSystem {
+    #[default]
+    Gregorian,
+    /// The Chinese lunisolar calendar, as used for lunar birthdays
+    Chinese,
+    Hebrew,
+}
+
+impl CalendarSystem {
+    /// Value stored in `reminders.calendar`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CalendarSystem::Gregorian => "gregorian",
+            CalendarSystem::Chinese => "chinese",
+            CalendarSystem::Hebrew => "hebrew",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "gregorian" => Some(CalendarSystem::Gregorian),
+            "chinese" => Some(CalendarSystem::Chinese),
+            "hebrew" => Some(CalendarSystem::Hebrew),
+            _ => None,
+        }
+    }
+}
+
+/// A month and day in a non-Gregorian calendar, stored in
+/// `reminders.calendar_month` and `reminders.calendar_day`. Months use ICU
+/// month codes: "M01".."M12", with an "L" suffix for leap months (Chinese
+/// leap months, and Adar I as "M05L" in the Hebrew calendar).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalendarDate {
+    pub system: CalendarSystem,
+    pub month_code: String,
+    pub day: u8,
+}
+
+const HEBREW_MONTHS: [(&str, &str); 13] = [
+    ("M01", "Tishrei"),
+    ("M02", "Cheshvan"),
+    ("M03", "Kislev"),
+    ("M04", "Tevet"),
+    ("M05", "Shevat"),
+    ("M05L", "Adar I"),
+    ("M06", "Adar"),
+    ("M07", "Nisan"),
+    ("M08", "Iyar"),
+    ("M09", "Sivan"),
+    ("M10", "Tammuz"),
+    ("M11", "Av"),
+    ("M12", "Elul"),
+];
+
+impl CalendarDate {
+    /// The `system` month and day that `date` falls on, or `None` for
+    /// Gregorian.
+    pub fn from_gregorian(system: CalendarSystem, date: NaiveDate) -> Option<Self> {
+        let iso = to_iso(date)?;
+        let (month_code, day) = match system {
+            CalendarSystem::Gregorian => return None,
+            CalendarSystem::Chinese => month_and_day(&iso.to_calendar(Chinese::new())),
+            CalendarSystem::Hebrew => {
+                let (month_code, day) = month_and_day(&iso.to_calendar(Hebrew));
+                // ICU reports Adar II as "M06L" but only accepts "M06" back,
+                // which is Adar II in leap years and Adar otherwise
+                let month_code = if month_code == "M06L" {
+                    "M06".to_string()
+                } else {
+                    month_code
+                };
+                (month_code, day)
+            }
+        };
+
+        Some(CalendarDate {
+            system,
+            month_code,
+            day,
+        })
+    }
+
+    /// Builds a stored date, checking the month code and day are plausible.
+    pub fn parse(system: CalendarSystem, month_code: &str, day: i64) -> Option<Self> {
+        let valid_month = match system {
+            CalendarSystem::Gregorian => false,
+            CalendarSystem::Chinese => {
+                let number = month_code
+                    .strip_prefix('M')
+                    .map(|rest| rest.strip_suffix('L').unwrap_or(rest));
+                number
+                    .and_then(|number| number.parse::<u8>().ok())
+                    .is_some_and(|number| (1..=12).contains(&number))
+                    && month_code.len() <= 4
+            }
+            CalendarSystem::Hebrew => HEBREW_MONTHS.iter().any(|(code, _)| *code == month_code),
+        };
+        if !valid_month || !(1..=30).contains(&day) {
+            return None;
+        }
+
+        Some(CalendarDate {
+            system,
+            month_code: month_code.to_string(),
+            day: day as u8,
+        })
+    }
+
+    /// The first Gregorian date on or after `today` that this month and day
+    /// fall on, and how many calendar years later that is than `birthdate`.
+    pub fn next_occurrence(
+        &self,
+        birthdate: NaiveDate,
+        today: NaiveDate,
+    ) -> Option<(NaiveDate, i32)> {
+        let birth_year = self.calendar_year(birthdate)?;
+        let this_year = self.calendar_year(today)?;
+
+        (this_year..=this_year + 1)
+            .filter_map(|year| Some((self.in_year(year)?, year - birth_year)))
+            .find(|(date, _)| *date >= today)
+    }
+
+    /// e.g. "15 Nisan" or "Month 8, day 15"
+    pub fn describe(&self) -> String {
+        match self.system {
+            CalendarSystem::Gregorian => String::new(),
+            CalendarSystem::Chinese => {
+                let leap = self.month_code.ends_with('L');
+                let number = self
+                    .month_code
+                    .trim_start_matches('M')
+                    .trim_end_matches('L')
+                    .trim_start_matches('0');
+                format!(
+                    "{}onth {}, day {}",
+                    if leap { "Leap m" } else { "M" },
+                    number,
+                    self.day
+                )
+            }
+            CalendarSystem::Hebrew => {
+                let month = HEBREW_MONTHS
+                    .iter()
+                    .find(|(code, _)| *code == self.month_code)
+                    .map_or(self.month_code.as_str(), |(_, name)| name);
+                format!("{} {}", self.day, month)
+            }
+        }
+    }
+
+    fn calendar_year(&self, date: NaiveDate) -> Option<i32> {
+        let iso = to_iso(date)?;
+        match self.system {
+            CalendarSystem::Gregorian => Some(date.year()),
+            CalendarSystem::Chinese => Some(iso.to_calendar(Chinese::new()).year().number),
+            CalendarSystem::Hebrew => Some(iso.to_calendar(Hebrew).year().number),
+        }
+    }
+
+    /// The Gregorian date of this month and day in calendar year `year`.
+    ///
+    /// A leap month the year doesn't have falls back to its regular month
+    /// (Adar I to Adar), and a 30th in a 29-day month moves to the 29th.
+    fn in_year(&self, year: i32) -> Option<NaiveDate> {
+        let regular_month = self.month_code.trim_end_matches('L');
+        let month_codes = [self.month_code.as_str(), regular_month];
+        let month_codes = match (self.system, self.month_code.as_str()) {
+            // Adar I's fallback is Adar ("M06"), not Shevat ("M05")
+            (CalendarSystem::Hebrew, "M05L") => ["M05L", "M06"],
+            _ => month_codes,
+        };
+
+        for month_code in month_codes {
+            for day in (1..=self.day).rev().take(2) {
+                let date = match self.system {
+                    CalendarSystem::Gregorian => return None,
+                    CalendarSystem::Chinese => {
+                        from_codes(year, "chinese", month_code, day, Chinese::new())
+                    }
+                    CalendarSystem::Hebrew => from_codes(year, "hebrew", month_code, day, Hebrew),
+                };
+                if date.is_some() {
+                    return date;
+                }
+            }
+        }
+
+        None
+    }
+}
+
+fn to_iso(date: NaiveDate) -> Option<Date<Iso>> {
+    Date::try_new_iso_date(date.year(), date.month() as u8, date.day() as u8).ok()
+}
+
+fn month_and_day<A: AsCalendar>(date: &Date<A>) -> (String, u8) {
+    (date.month().code.0.to_string(), date.day_of_month().0 as u8)
+}
+
+fn from_codes<A: AsCalendar>(
+    year: i32,
+    era: &str,
+    month_code: &str,
+    day: u8,
+    calendar: A,
+) -> Option<NaiveDate> {
+    let era: Era = era.parse().ok()?;
+    let month_code: MonthCode = month_code.parse().ok()?;
+    let iso = Date::try_new_from_codes(era, year, month_code, day, calendar)
+        .ok()?
+        .to_iso();
+    NaiveDate::from_ymd_opt(iso.year().number, iso.month().ordinal, iso.day_of_month().0)
+}
+
+/// Validates the calendar chosen on the add/edit forms for a reminder dated
+/// `date`, returning the calendar date to store.
+pub fn parse_calendar_form(
+    calendar: &str,
+    date: NaiveDate,
+    year_known: bool,
+    has_recurrence: bool,
+) -> Result<Option<CalendarDate>, String> {
+    let system =
+        CalendarSystem::parse(calendar).ok_or_else(|| "Invalid calendar selected".to_string())?;
+    if system == CalendarSystem::Gregorian {
+        return Ok(None);
+    }
+    if !year_known {
+        return Err(
+            "Lunar and Hebrew dates need the year, so the date can be converted".to_string(),
+        );
+    }
+    if has_recurrence {
+        return Err("Repeat options only work with the Gregorian calendar".to_string());
+    }
+
+    CalendarDate::from_gregorian(system, date)
+        .map(Some)
+        .ok_or_else(|| "That date can't be converted to the chosen calendar".to_string())
+}
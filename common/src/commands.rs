@@ -0,0 +1,445 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, Utc};
+use sqlx::{sqlite::SqlitePool, Row};
+
+use crate::birthday::{birthdate_to_timestamp, BirthdayEngine};
+use crate::clock::FixedClock;
+use crate::events::EventType;
+use crate::ledger::get_latest_digest_entries;
+use crate::notice::{describe_notice_offsets, parse_notice_offsets};
+use crate::occurrences::{mark_occurrence_done, snooze_occurrence};
+use crate::sms::{fit_lines, SMS_MAX_LEN};
+use crate::{
+    delete_reminder, format_db_timestamp, get_reminders_by_user_id, save_reminder,
+    update_user_settings, DbReminder, DbUser, ReminderFields, DB_TIMESTAMP_FORMAT,
+    UNKNOWN_BIRTH_YEAR,
+};
+
+/// How long a numbered disambiguation reply stays answerable
+pub const PENDING_CHOICE_TTL_MINUTES: i64 = 10;
+
+pub const USAGE_REPLY: &str =
+    "Commands: ADD Jane 3/14/1990, LIST, NEXT, DELETE Jane, DONE Jane, SNOOZE 2, NOTICE 14,3,0. Reply STOP to unsubscribe.";
+
+/// Longest SNOOZE we accept
+pub const MAX_SNOOZE_DAYS: i64 = 30;
+
+const ACTION_DELETE: &str = "delete";
+const ACTION_DONE: &str = "done";
+
+/// A reminder-management command texted in by a user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SmsCommand {
+    /// `ADD Jane 3/14/1990`, or `ADD Jane 3/14` when the year isn't known
+    Add {
+        name: String,
+        birthdate: NaiveDate,
+        year_known: bool,
+    },
+    List,
+    Next,
+    Delete {
+        name: String,
+    },
+    /// Replace the user's reminder offsets, e.g. `NOTICE 14,3,0`
+    Notice {
+        offsets: Vec<i64>,
+    },
+    /// Hold off on the reminders in the latest digest for this many days
+    Snooze {
+        days: i64,
+    },
+    /// Already wished them: no more reminders for this birthday
+    Done {
+        name: String,
+    },
+    /// A numbered answer to an earlier disambiguation reply (1-based)
+    Choose(usize),
+}
+
+/// Parses a command, ignoring case and surrounding whitespace. On failure
+/// returns the reply explaining what went wrong.
+pub fn parse_command(body: &str) -> Result<SmsCommand, String> {
+    let body = body.trim();
+    let (verb, rest) = match body.split_once(char::is_whitespace) {
+        Some((verb, rest)) => (verb, rest.trim()),
+        None => (body, ""),
+    };
+
+    match verb.to_ascii_uppercase().as_str() {
+        "ADD" => {
+            let (name, date) = rest
+                .rsplit_once(char::is_whitespace)
+                .ok_or("Usage: ADD Jane 3/14/1990")?;
+            let name = name.trim();
+            if name.is_empty() {
+                return Err("Usage: ADD Jane 3/14/1990".to_string());
+            }
+            let (birthdate, year_known) = parse_date(date)
+                .ok_or_else(|| format!("Couldn't read the date '{}'. Use M/D/YYYY.", date))?;
+            Ok(SmsCommand::Add {
+                name: name.to_string(),
+                birthdate,
+                year_known,
+            })
+        }
+        "LIST" if rest.is_empty() => Ok(SmsCommand::List),
+        "NEXT" if rest.is_empty() => Ok(SmsCommand::Next),
+        "DELETE" | "DEL" | "REMOVE" => {
+            if rest.is_empty() {
+                return Err("Usage: DELETE Jane".to_string());
+            }
+            Ok(SmsCommand::Delete {
+                name: rest.to_string(),
+            })
+        }
+        "NOTICE" => match parse_notice_offsets(rest) {
+            Ok(offsets) => Ok(SmsCommand::Notice { offsets }),
+            Err(e) => Err(format!("{}. Usage: NOTICE 14,3,0 (days before)", e)),
+        },
+        "SNOOZE" if rest.is_empty() => Ok(SmsCommand::Snooze { days: 1 }),
+        "SNOOZE" => match rest.parse::<i64>() {
+            Ok(days) if (1..=MAX_SNOOZE_DAYS).contains(&days) => Ok(SmsCommand::Snooze { days }),
+            _ => Err(format!("Usage: SNOOZE 2 (days, 1-{})", MAX_SNOOZE_DAYS)),
+        },
+        "DONE" => {
+            if rest.is_empty() {
+                return Err("Usage: DONE Jane".to_string());
+            }
+            Ok(SmsCommand::Done {
+                name: rest.to_string(),
+            })
+        }
+        _ if rest.is_empty() => match verb.parse::<usize>() {
+            Ok(choice) if choice > 0 => Ok(SmsCommand::Choose(choice)),
+            _ => Err(USAGE_REPLY.to_string()),
+        },
+        _ => Err(USAGE_REPLY.to_string()),
+    }
+}
+
+/// Accepts M/D/YYYY (the US order people text in), YYYY-MM-DD, or M/D when
+/// the year isn't known. Returns the date and whether it has a real year.
+pub(crate) fn parse_date(date: &str) -> Option<(NaiveDate, bool)> {
+    if let Ok(date) =
+        NaiveDate::parse_from_str(&format!("{}/{}", date, UNKNOWN_BIRTH_YEAR), "%m/%d/%Y")
+    {
+        return Some((date, false));
+    }
+
+    NaiveDate::parse_from_str(date, "%m/%d/%Y")
+        .or_else(|_| NaiveDate::parse_from_str(date, "%Y-%m-%d"))
+        .ok()
+        .filter(|date| date.year() >= 1900)
+        .map(|date| (date, true))
+}
+
+/// Builds a reply from `lines` that fits in one SMS, dropping lines from the
+/// end if needed.
+pub fn format_reply(lines: &[String]) -> String {
+    fit_lines(lines, "")
+        .map(|(reply, _)| reply)
+        .unwrap_or_else(|| {
+            // Cut on a char boundary so names with accents or emoji don't panic
+            let first = lines.first().map(String::as_str).unwrap_or_default();
+            let mut end = SMS_MAX_LEN.min(first.len());
+            while !first.is_char_boundary(end) {
+                end -= 1;
+            }
+            first[..end].to_string()
+        })
+}
+
+/// Runs `command` for `user` and returns the reply to text back.
+pub async fn execute_command(
+    pool: &SqlitePool,
+    user: &DbUser,
+    command: SmsCommand,
+    current_utc: DateTime<Utc>,
+) -> Result<String, sqlx::Error> {
+    match command {
+        SmsCommand::Add {
+            name,
+            birthdate,
+            year_known,
+        } => {
+            let timestamp = birthdate_to_timestamp(birthdate).to_string();
+            save_reminder(
+                pool,
+                user.id,
+                None,
+                &ReminderFields {
+                    name: &name,
+                    birthdate: &timestamp,
+                    year_known,
+                    notice_offsets: None,
+                    event_type: &EventType::Birthday,
+                    recurrence: None,
+                    calendar_date: None,
+                },
+            )
+            .await?;
+
+            let date_format = if year_known { "%-m/%-d/%Y" } else { "%-m/%-d" };
+            Ok(format_reply(&[format!(
+                "Added {} ({}).",
+                name,
+                birthdate.format(date_format)
+            )]))
+        }
+        SmsCommand::List => {
+            let reminders = get_reminders_by_user_id(pool, user.id).await?;
+            if reminders.is_empty() {
+                return Ok("No reminders yet. Text ADD Jane 3/14/1990 to add one.".to_string());
+            }
+            let mut reminders = reminders;
+            BirthdayEngine::for_user(user, &FixedClock(current_utc)).sort_upcoming(&mut reminders);
+            let lines: Vec<String> = reminders.iter().map(reminder_label).collect();
+            Ok(format_reply(&lines))
+        }
+        SmsCommand::Next => {
+            let reminders = get_reminders_by_user_id(pool, user.id).await?;
+            let engine = BirthdayEngine::for_user(user, &FixedClock(current_utc));
+            let upcoming: Vec<(i64, String)> = reminders
+                .iter()
+                .filter_map(|reminder| {
+                    let info = engine.info(reminder)?;
+                    let when = match info.days_until {
+                        0 => "today".to_string(),
+                        1 => "tomorrow".to_string(),
+                        n => format!("in {} days", n),
+                    };
+                    Some((
+                        info.days_until,
+                        format!(
+                            "{} {} ({})",
+                            reminder.name,
+                            when,
+                            info.next_date.format("%-m/%-d")
+                        ),
+                    ))
+                })
+                .collect();
+
+            let Some(soonest) = upcoming.iter().map(|(days, _)| *days).min() else {
+                return Ok("No reminders yet. Text ADD Jane 3/14/1990 to add one.".to_string());
+            };
+            let lines: Vec<String> = upcoming
+                .into_iter()
+                .filter(|(days, _)| *days == soonest)
+                .map(|(_, line)| line)
+                .collect();
+            Ok(format_reply(&lines))
+        }
+        SmsCommand::Delete { name } => {
+            act_on_name(pool, user, ACTION_DELETE, &name, current_utc).await
+        }
+        SmsCommand::Done { name } => act_on_name(pool, user, ACTION_DONE, &name, current_utc).await,
+        SmsCommand::Snooze { days } => {
+            let entries = get_latest_digest_entries(pool, user.id).await?;
+            if entries.is_empty() {
+                return Ok("Nothing to snooze yet.".to_string());
+            }
+
+            let today = BirthdayEngine::for_user(user, &FixedClock(current_utc)).today();
+            let reminders = get_reminders_by_user_id(pool, user.id).await?;
+            let mut names = Vec::new();
+            for entry in entries {
+                let Some(reminder) = reminders.iter().find(|r| r.id == entry.reminder_id) else {
+                    continue;
+                };
+                // Never snooze past the birthday itself
+                let until = (today + Duration::days(days)).min(entry.birthday_date);
+                if until <= today {
+                    continue;
+                }
+                snooze_occurrence(pool, reminder.id, entry.birthday_date, until).await?;
+                names.push(reminder.name.clone());
+            }
+
+            if names.is_empty() {
+                return Ok("Those birthdays are today, so there's nothing to snooze.".to_string());
+            }
+            Ok(format_reply(&[format!(
+                "Snoozed {} for {} day{}.",
+                names.join(", "),
+                days,
+                if days == 1 { "" } else { "s" }
+            )]))
+        }
+        SmsCommand::Notice { offsets } => {
+            update_user_settings(pool, user.id, &offsets, user.send_hour, &user.iana_tz).await?;
+            Ok(format!(
+                "Reminders set for {}.",
+                describe_notice_offsets(&offsets)
+            ))
+        }
+        SmsCommand::Choose(choice) => {
+            let Some((action, ids)) = take_pending_choice(pool, user.id, current_utc).await? else {
+                return Ok(USAGE_REPLY.to_string());
+            };
+            let Some(&reminder_id) = ids.get(choice - 1) else {
+                // Keep the question open so they can answer again
+                save_pending_choice(pool, user.id, &action, &ids, current_utc).await?;
+                return Ok(format!("Reply with a number from 1 to {}.", ids.len()));
+            };
+
+            let reminders = get_reminders_by_user_id(pool, user.id).await?;
+            let Some(reminder) = reminders.iter().find(|reminder| reminder.id == reminder_id)
+            else {
+                return Ok("That reminder no longer exists.".to_string());
+            };
+
+            apply_action(pool, user, &action, reminder, current_utc).await
+        }
+    }
+}
+
+/// Runs a name-targeted command, asking the user to pick by number when the
+/// name matches more than one reminder.
+async fn act_on_name(
+    pool: &SqlitePool,
+    user: &DbUser,
+    action: &str,
+    name: &str,
+    current_utc: DateTime<Utc>,
+) -> Result<String, sqlx::Error> {
+    let reminders = get_reminders_by_user_id(pool, user.id).await?;
+    let matches = match_reminders(&reminders, name);
+    match matches.as_slice() {
+        [] => Ok(format_reply(&[format!("No reminder named {}.", name)])),
+        [reminder] => apply_action(pool, user, action, reminder, current_utc).await,
+        _ => {
+            let ids: Vec<i64> = matches.iter().map(|reminder| reminder.id).collect();
+            save_pending_choice(pool, user.id, action, &ids, current_utc).await?;
+
+            let mut lines = vec![format!("Which {}? Reply with a number:", name)];
+            lines.extend(
+                matches
+                    .iter()
+                    .enumerate()
+                    .map(|(i, reminder)| format!("{}) {}", i + 1, reminder_label(reminder))),
+            );
+            Ok(format_reply(&lines))
+        }
+    }
+}
+
+async fn apply_action(
+    pool: &SqlitePool,
+    user: &DbUser,
+    action: &str,
+    reminder: &DbReminder,
+    current_utc: DateTime<Utc>,
+) -> Result<String, sqlx::Error> {
+    if action == ACTION_DONE {
+        let engine = BirthdayEngine::for_user(user, &FixedClock(current_utc));
+        let Some(occurrence_date) = engine.info(reminder).map(|info| info.next_date) else {
+            return Ok(format_reply(&[format!(
+                "Couldn't find {}'s next birthday.",
+                reminder.name
+            )]));
+        };
+        mark_occurrence_done(pool, reminder.id, occurrence_date).await?;
+        return Ok(format_reply(&[format!(
+            "Nice! No more reminders for {} until next year.",
+            reminder.name
+        )]));
+    }
+
+    delete_reminder(pool, reminder.id).await?;
+    Ok(format_reply(&[format!("Deleted {}.", reminder.name)]))
+}
+
+fn reminder_label(reminder: &DbReminder) -> String {
+    let label = match DateTime::from_timestamp_millis(reminder.birthdate) {
+        Some(birthdate) => format!("{} {}", reminder.name, birthdate.format("%-m/%-d")),
+        None => reminder.name.clone(),
+    };
+
+    // Birthdays are the common case, so only other occasions are tagged
+    match reminder.event_type {
+        EventType::Birthday => label,
+        ref event_type => format!("{} ({})", label, event_type.display_name().to_lowercase()),
+    }
+}
+
+/// Exact (case-insensitive) name matches if there are any, otherwise every
+/// reminder whose name contains `name`.
+fn match_reminders<'a>(reminders: &'a [DbReminder], name: &str) -> Vec<&'a DbReminder> {
+    let needle = name.to_lowercase();
+    let exact: Vec<&DbReminder> = reminders
+        .iter()
+        .filter(|reminder| reminder.name.to_lowercase() == needle)
+        .collect();
+    if exact.len() == 1 {
+        return exact;
+    }
+
+    reminders
+        .iter()
+        .filter(|reminder| reminder.name.to_lowercase().contains(&needle))
+        .collect()
+}
+
+async fn save_pending_choice(
+    pool: &SqlitePool,
+    user_id: i64,
+    action: &str,
+    reminder_ids: &[i64],
+    current_utc: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let reminder_ids: Vec<String> = reminder_ids.iter().map(|id| id.to_string()).collect();
+
+    sqlx::query(
+        "INSERT OR REPLACE INTO sms_pending_choices (user_id, action, reminder_ids, created_at)
+         VALUES (?, ?, ?, ?)",
+    )
+    .bind(user_id)
+    .bind(action)
+    .bind(reminder_ids.join(","))
+    .bind(format_db_timestamp(current_utc))
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Removes and returns the user's pending choice, unless it has expired.
+async fn take_pending_choice(
+    pool: &SqlitePool,
+    user_id: i64,
+    current_utc: DateTime<Utc>,
+) -> Result<Option<(String, Vec<i64>)>, sqlx::Error> {
+    let row = sqlx::query(
+        "DELETE FROM sms_pending_choices WHERE user_id = ?
+         RETURNING action, reminder_ids, created_at",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .next();
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let created_at: String = row.get("created_at");
+    let expired = match NaiveDateTime::parse_from_str(&created_at, DB_TIMESTAMP_FORMAT) {
+        Ok(created_at) => {
+            current_utc - created_at.and_utc() > Duration::minutes(PENDING_CHOICE_TTL_MINUTES)
+        }
+        Err(_) => true,
+    };
+    if expired {
+        return Ok(None);
+    }
+
+    let reminder_ids: String = row.get("reminder_ids");
+    let reminder_ids = reminder_ids
+        .split(',')
+        .filter_map(|id| id.parse().ok())
+        .collect();
+
+    Ok(Some((row.get("action"), reminder_ids)))
+}
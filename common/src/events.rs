@@ -0,0 +1,136 @@
+/// Longest label we accept for a custom occasion
+pub const MAX_EVENT_LABEL_LEN: usize = 40;
+
+/// What a reminder's annual date marks. Stored in `reminders.event_type`, with
+/// the custom label in `reminders.event_label`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum EventType {
+    #[default]
+    Birthday,
+    WeddingAnniversary,
+    WorkAnniversary,
+    Memorial,
+    /// A user-named occasion, e.g. "adoption day"
+    Custom(String),
+}
+
+impl EventType {
+    /// Value stored in `reminders.event_type`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventType::Birthday => "birthday",
+            EventType::WeddingAnniversary => "wedding_anniversary",
+            EventType::WorkAnniversary => "work_anniversary",
+            EventType::Memorial => "memorial",
+            EventType::Custom(_) => "custom",
+        }
+    }
+
+    /// Value stored in `reminders.event_label`
+    pub fn label(&self) -> Option<&str> {
+        match self {
+            EventType::Custom(label) => Some(label),
+            _ => None,
+        }
+    }
+
+    /// Builds the type from its stored columns. A custom event needs a
+    /// non-blank label.
+    pub fn parse(kind: &str, label: Option<&str>) -> Option<Self> {
+        match kind {
+            "birthday" => Some(EventType::Birthday),
+            "wedding_anniversary" => Some(EventType::WeddingAnniversary),
+            "work_anniversary" => Some(EventType::WorkAnniversary),
+            "memorial" => Some(EventType::Memorial),
+            "custom" => label
+                .map(str::trim)
+                .filter(|label| !label.is_empty())
+                .map(|label| EventType::Custom(label.to_string())),
+            _ => None,
+        }
+    }
+
+    /// Short name for the dashboard, e.g. "Anniversary"
+    pub fn display_name(&self) -> &str {
+        match self {
+            EventType::Birthday => "Birthday",
+            EventType::WeddingAnniversary => "Anniversary",
+            EventType::WorkAnniversary => "Work anniversary",
+            EventType::Memorial => "Memorial",
+            EventType::Custom(label) => label,
+        }
+    }
+}
+
+/// Validates the event type and label submitted by the add/edit forms.
+pub fn parse_event_form(kind: &str, label: &str) -> Result<EventType, String> {
+    let label = label.trim();
+    if kind == "custom" {
+        if label.is_empty() {
+            return Err("Please name the occasion".to_string());
+        }
+        if label.chars().count() > MAX_EVENT_LABEL_LEN {
+            return Err(format!(
+                "Occasion names can be at most {} characters",
+                MAX_EVENT_LABEL_LEN
+            ));
+        }
+    }
+
+    EventType::parse(kind, Some(label)).ok_or_else(|| "Invalid event type selected".to_string())
+}
+
+/// How the event is named in a text, e.g. "Sam & Alex's 10th anniversary" or
+/// "Remembering Dad - 5 years". `years` is the age turned or the number of
+/// years since the original date, when the year is known.
+pub fn event_title(name: &str, event_type: &EventType, years: Option<i32>) -> String {
+    // A zero-year anniversary is the original date itself
+    let years = match event_type {
+        EventType::Birthday => years,
+        _ => years.filter(|years| *years > 0),
+    };
+
+    match (event_type, years) {
+        (EventType::Birthday, Some(age)) => format!("{}'s {}", name, ordinal(age)),
+        (EventType::Birthday, None) => format!("{}'s birthday", name),
+        (EventType::WeddingAnniversary, Some(years)) => {
+            format!("{}'s {} anniversary", name, ordinal(years))
+        }
+        (EventType::WeddingAnniversary, None) => format!("{}'s anniversary", name),
+        (EventType::WorkAnniversary, Some(years)) => {
+            format!("{}'s {} work anniversary", name, ordinal(years))
+        }
+        (EventType::WorkAnniversary, None) => format!("{}'s work anniversary", name),
+        (EventType::Memorial, Some(1)) => format!("Remembering {} - 1 year", name),
+        (EventType::Memorial, Some(years)) => format!("Remembering {} - {} years", name, years),
+        (EventType::Memorial, None) => format!("Remembering {}", name),
+        (EventType::Custom(label), Some(years)) => {
+            format!("{}'s {} {}", name, ordinal(years), label)
+        }
+        (EventType::Custom(label), None) => format!("{}'s {}", name, label),
+    }
+}
+
+/// A digest line, e.g. "Jane's 34th is today" or "Remembering Dad - 5 years
+/// tomorrow". `when` is "today", "tomorrow" or "in N days".
+pub fn event_line(name: &str, event_type: &EventType, years: Option<i32>, when: &str) -> String {
+    let title = event_title(name, event_type, years);
+    match event_type {
+        EventType::Memorial => format!("{} {}", title, when),
+        _ => format!("{} is {}", title, when),
+    }
+}
+
+/// 1st, 2nd, 3rd, 11th, 22nd, ...
+pub fn ordinal(n: i32) -> String {
+    let suffix = match n % 100 {
+        11..=13 => "th",
+        _ => match n % 10 {
+            1 => "st",
+            2 => "nd",
+            3 => "rd",
+            _ => "th",
+        },
+    };
+    format!("{}{}", n, suffix)
+}
@@ -0,0 +1,198 @@
+use chrono::{DateTime, Utc};
+use csv::Writer;
+use serde::Serialize;
+
+use crate::birthday::birthdate_from_timestamp;
+use crate::{DbReminder, DbUser};
+
+/// Everything we hold about a user, as offered for download.
+#[derive(Debug, Serialize)]
+pub struct UserExport {
+    pub exported_at: String,
+    pub settings: ExportSettings,
+    pub reminders: Vec<ExportReminder>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportSettings {
+    pub phone_number: String,
+    pub created_at: String,
+    pub notice_offsets: Vec<i64>,
+    pub send_hour: i64,
+    pub iana_tz: String,
+    pub leap_day_policy: String,
+    pub sms_opted_out_at: Option<String>,
+    pub last_digest_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportReminder {
+    pub id: i64,
+    pub name: String,
+    /// YYYY-MM-DD, or --MM-DD when the year isn't known
+    pub date: String,
+    pub year_known: bool,
+    pub event_type: String,
+    pub event_label: Option<String>,
+    /// `None` means the user's default offsets
+    pub notice_offsets: Option<Vec<i64>>,
+    pub rrule: Option<String>,
+    pub calendar: String,
+    pub calendar_month: Option<String>,
+    pub calendar_day: Option<u8>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+pub fn build_export(user: &DbUser, reminders: &[DbReminder], now: DateTime<Utc>) -> UserExport {
+    UserExport {
+        exported_at: now.to_rfc3339(),
+        settings: export_settings(user),
+        reminders: reminders.iter().map(export_reminder).collect(),
+    }
+}
+
+/// The user's settings, as exported and as the API returns them
+pub fn export_settings(user: &DbUser) -> ExportSettings {
+    ExportSettings {
+        phone_number: user.phone_number.clone(),
+        created_at: user.created_at.clone(),
+        notice_offsets: user.notice_offsets.clone(),
+        send_hour: user.send_hour,
+        iana_tz: user.iana_tz.clone(),
+        leap_day_policy: user.leap_day_policy.as_str().to_string(),
+        sms_opted_out_at: user.sms_opted_out_at.clone(),
+        last_digest_at: user.last_digest_at.clone(),
+    }
+}
+
+/// A reminder, as exported and as the API returns it
+pub fn export_reminder(reminder: &DbReminder) -> ExportReminder {
+    let date = birthdate_from_timestamp(reminder.birthdate)
+        .map(|date| match reminder.year_known {
+            true => date.format("%Y-%m-%d").to_string(),
+            false => date.format("--%m-%d").to_string(),
+        })
+        .unwrap_or_default();
+
+    ExportReminder {
+        id: reminder.id,
+        name: reminder.name.clone(),
+        date,
+        year_known: reminder.year_known,
+        event_type: reminder.event_type.as_str().to_string(),
+        event_label: reminder.event_type.label().map(str::to_string),
+        notice_offsets: reminder.notice_offsets.clone(),
+        rrule: reminder.recurrence.as_ref().map(|rule| rule.to_string()),
+        calendar: reminder
+            .calendar_date
+            .as_ref()
+            .map_or("gregorian", |date| date.system.as_str())
+            .to_string(),
+        calendar_month: reminder
+            .calendar_date
+            .as_ref()
+            .map(|date| date.month_code.clone()),
+        calendar_day: reminder.calendar_date.as_ref().map(|date| date.day),
+        created_at: reminder.created_at.clone(),
+        updated_at: reminder.updated_at.clone(),
+    }
+}
+
+pub fn export_json(export: &UserExport) -> String {
+    serde_json::to_string_pretty(export).expect("export serializes")
+}
+
+/// One row per reminder. The "Name" and "Birthday" columns match the
+/// import's defaults, so the file can be imported again as is.
+pub fn reminders_csv(export: &UserExport) -> String {
+    let mut writer = Writer::from_writer(Vec::new());
+    writer
+        .write_record([
+            "Name",
+            "Birthday",
+            "Occasion",
+            "Occasion Label",
+            "Notice Days",
+            "Repeat Rule",
+            "Calendar",
+            "Calendar Month",
+            "Calendar Day",
+            "Created At",
+            "Updated At",
+        ])
+        .expect("writing to memory");
+
+    for reminder in &export.reminders {
+        writer
+            .write_record([
+                reminder.name.as_str(),
+                &reminder.date,
+                &reminder.event_type,
+                reminder.event_label.as_deref().unwrap_or(""),
+                &reminder
+                    .notice_offsets
+                    .as_deref()
+                    .map(join_offsets)
+                    .unwrap_or_default(),
+                reminder.rrule.as_deref().unwrap_or(""),
+                &reminder.calendar,
+                reminder.calendar_month.as_deref().unwrap_or(""),
+                &reminder
+                    .calendar_day
+                    .map(|day| day.to_string())
+                    .unwrap_or_default(),
+                &reminder.created_at,
+                &reminder.updated_at,
+            ])
+            .expect("writing to memory");
+    }
+
+    csv_string(writer)
+}
+
+/// The account's settings as "Setting,Value" rows.
+pub fn settings_csv(export: &UserExport) -> String {
+    let settings = &export.settings;
+    let mut writer = Writer::from_writer(Vec::new());
+    let rows = [
+        ("Phone Number", settings.phone_number.clone()),
+        ("Created At", settings.created_at.clone()),
+        ("Notice Days", join_offsets(&settings.notice_offsets)),
+        ("Send Hour", settings.send_hour.to_string()),
+        ("Timezone", settings.iana_tz.clone()),
+        ("Leap Day Policy", settings.leap_day_policy.clone()),
+        (
+            "SMS Opted Out At",
+            settings.sms_opted_out_at.clone().unwrap_or_default(),
+        ),
+        (
+            "Last Digest At",
+            settings.last_digest_at.clone().unwrap_or_default(),
+        ),
+    ];
+
+    writer
+        .write_record(["Setting", "Value"])
+        .expect("writing to memory");
+    for (setting, value) in rows {
+        writer
+            .write_record([setting, value.as_str()])
+            .expect("writing to memory");
+    }
+
+    csv_string(writer)
+}
+
+fn join_offsets(offsets: &[i64]) -> String {
+    offsets
+        .iter()
+        .map(|offset| offset.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn csv_string(writer: Writer<Vec<u8>>) -> String {
+    let bytes = writer.into_inner().expect("flushing to memory");
+    String::from_utf8(bytes).expect("CSV of UTF-8 fields")
+}
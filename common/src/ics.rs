@@ -0,0 +1,273 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use rand::RngCore;
+use sqlx::{sqlite::SqlitePool, Row};
+
+use crate::birthday::{birthdate_from_timestamp, birthday_in_year, BirthdayEngine, LeapDayPolicy};
+use crate::clock::FixedClock;
+use crate::events::{event_title, EventType};
+use crate::{DbReminder, DbUser};
+
+/// How many upcoming occurrences get their own event (with the age in the
+/// summary) alongside each yearly event
+pub const FEED_YEARS: usize = 3;
+
+/// RFC 5545 caps content lines at 75 octets
+const MAX_LINE_OCTETS: usize = 75;
+
+/// The user's feed token, creating one the first time it's asked for.
+pub async fn get_or_create_ics_token(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> Result<String, sqlx::Error> {
+    let token: Option<String> = sqlx::query("SELECT ics_token FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?
+        .get("ics_token");
+
+    match token {
+        Some(token) => Ok(token),
+        None => rotate_ics_token(pool, user_id).await,
+    }
+}
+
+/// Replaces the user's feed token, so the old URL stops working.
+pub async fn rotate_ics_token(pool: &SqlitePool, user_id: i64) -> Result<String, sqlx::Error> {
+    let mut bytes = [0u8; 24];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+
+    sqlx::query("UPDATE users SET ics_token = ? WHERE id = ?")
+        .bind(&token)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(token)
+}
+
+/// The user a feed URL belongs to.
+pub async fn get_user_id_by_ics_token(
+    pool: &SqlitePool,
+    token: &str,
+) -> Result<Option<i64>, sqlx::Error> {
+    let row = sqlx::query("SELECT id FROM users WHERE ics_token = ?")
+        .bind(token)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(row.map(|row| row.get("id")))
+}
+
+/// Renders the user's reminders as an iCalendar feed.
+///
+/// Plain reminders become a yearly all-day event, plus an override for each
+/// of the next `FEED_YEARS` occurrences naming the age. Reminders with a
+/// recurrence rule carry that rule instead, and lunar/Hebrew reminders (which
+/// no RRULE can express) are listed one occurrence at a time. Each event has
+/// a VALARM per notice offset, at the user's send hour.
+pub fn render_feed(user: &DbUser, reminders: &[DbReminder], now: DateTime<Utc>) -> String {
+    let today = BirthdayEngine::for_user(user, &FixedClock(now)).today();
+    let dtstamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//hbd.bot//Birthday Reminders//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        "X-WR-CALNAME:HBD Bot".to_string(),
+    ];
+
+    for reminder in reminders {
+        let Some(birthdate) = birthdate_from_timestamp(reminder.birthdate) else {
+            continue;
+        };
+        let uid = format!("reminder-{}@hbd.bot", reminder.id);
+        let offsets = reminder
+            .notice_offsets
+            .as_deref()
+            .unwrap_or(&user.notice_offsets);
+        let event = FeedEvent {
+            dtstamp: &dtstamp,
+            offsets,
+            send_hour: user.send_hour,
+        };
+
+        match (&reminder.recurrence, &reminder.calendar_date) {
+            (Some(recurrence), _) => {
+                let summary = event_title(&reminder.name, &reminder.event_type, None);
+                event.push(
+                    &mut lines,
+                    &uid,
+                    birthdate,
+                    &summary,
+                    &[format!("RRULE:{}", recurrence)],
+                );
+            }
+            (None, Some(calendar_date)) => {
+                let mut from = today;
+                for _ in 0..FEED_YEARS {
+                    let Some((date, years)) = calendar_date.next_occurrence(birthdate, from) else {
+                        break;
+                    };
+                    let years = reminder.year_known.then_some(years);
+                    event.push(
+                        &mut lines,
+                        &format!("reminder-{}-{}@hbd.bot", reminder.id, date.year()),
+                        date,
+                        &feed_summary(reminder, years),
+                        &[],
+                    );
+                    from = date + Duration::days(1);
+                }
+            }
+            (None, None) => {
+                let summary = event_title(&reminder.name, &reminder.event_type, None);
+                let rrule = yearly_rule(birthdate, user.leap_day_policy);
+                event.push(&mut lines, &uid, birthdate, &summary, &[rrule]);
+
+                if !reminder.year_known {
+                    continue;
+                }
+                // Name the age on the next few occurrences
+                let first_year =
+                    match birthday_in_year(birthdate, today.year(), user.leap_day_policy) {
+                        date if date >= today => today.year(),
+                        _ => today.year() + 1,
+                    };
+                for year in (first_year..).take(FEED_YEARS) {
+                    let date = birthday_in_year(birthdate, year, user.leap_day_policy);
+                    if date <= birthdate {
+                        continue;
+                    }
+                    let recurrence_id = format!("RECURRENCE-ID;VALUE=DATE:{}", ics_date(date));
+                    event.push(
+                        &mut lines,
+                        &uid,
+                        date,
+                        &feed_summary(reminder, Some(year - birthdate.year())),
+                        &[recurrence_id],
+                    );
+                }
+            }
+        }
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut feed = String::new();
+    for line in lines {
+        fold_line(&line, &mut feed);
+    }
+    feed
+}
+
+/// Settings shared by every event in a feed
+struct FeedEvent<'a> {
+    dtstamp: &'a str,
+    offsets: &'a [i64],
+    send_hour: i64,
+}
+
+impl FeedEvent<'_> {
+    fn push(
+        &self,
+        lines: &mut Vec<String>,
+        uid: &str,
+        date: NaiveDate,
+        summary: &str,
+        extra: &[String],
+    ) {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", uid));
+        lines.push(format!("DTSTAMP:{}", self.dtstamp));
+        lines.push(format!("DTSTART;VALUE=DATE:{}", ics_date(date)));
+        lines.extend(extra.iter().cloned());
+        lines.push(format!("SUMMARY:{}", escape_text(summary)));
+        lines.push("TRANSP:TRANSPARENT".to_string());
+        for offset in self.offsets {
+            lines.push("BEGIN:VALARM".to_string());
+            lines.push("ACTION:DISPLAY".to_string());
+            lines.push(format!("DESCRIPTION:{}", escape_text(summary)));
+            lines.push(format!(
+                "TRIGGER:{}",
+                alarm_trigger(*offset, self.send_hour)
+            ));
+            lines.push("END:VALARM".to_string());
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+}
+
+/// Summary naming the age or year count, e.g. "Jane's 34th birthday"
+fn feed_summary(reminder: &DbReminder, years: Option<i32>) -> String {
+    let title = event_title(&reminder.name, &reminder.event_type, years);
+    match (&reminder.event_type, years) {
+        (EventType::Birthday, Some(_)) => format!("{} birthday", title),
+        _ => title,
+    }
+}
+
+/// A yearly RRULE for the date. Feb 29 follows the leap-day policy: the last
+/// day of February, or the 60th day of the year (Mar 1 in common years).
+fn yearly_rule(birthdate: NaiveDate, policy: LeapDayPolicy) -> String {
+    if (birthdate.month(), birthdate.day()) != (2, 29) {
+        return "RRULE:FREQ=YEARLY".to_string();
+    }
+    match policy {
+        LeapDayPolicy::Feb28 => "RRULE:FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1".to_string(),
+        LeapDayPolicy::Mar1 => "RRULE:FREQ=YEARLY;BYYEARDAY=60".to_string(),
+    }
+}
+
+/// The alarm's offset from the start of the all-day event: `days_before`
+/// days early, at `send_hour`. E.g. 7 days at 9am is `-P6DT15H`.
+pub fn alarm_trigger(days_before: i64, send_hour: i64) -> String {
+    let hours = send_hour - days_before * 24;
+    let sign = if hours < 0 { "-" } else { "" };
+    let (days, hours) = (hours.abs() / 24, hours.abs() % 24);
+
+    match (days, hours) {
+        (0, 0) => "PT0S".to_string(),
+        (0, hours) => format!("{}PT{}H", sign, hours),
+        (days, 0) => format!("{}P{}D", sign, days),
+        (days, hours) => format!("{}P{}DT{}H", sign, days, hours),
+    }
+}
+
+fn ics_date(date: NaiveDate) -> String {
+    date.format("%Y%m%d").to_string()
+}
+
+/// Escapes a TEXT value (RFC 5545 §3.3.11).
+pub fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Appends `line` with a CRLF, folding it onto continuation lines so none
+/// exceeds 75 octets (without splitting a UTF-8 character).
+fn fold_line(line: &str, out: &mut String) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > MAX_LINE_OCTETS {
+            out.push_str("\r\n ");
+            // The leading space counts toward the continuation line
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
@@ -0,0 +1,489 @@
+use chrono::{Datelike, NaiveDate};
+use csv::{ReaderBuilder, StringRecord, Trim};
+use sqlx::sqlite::SqlitePool;
+use std::collections::HashSet;
+
+use crate::birthday::{birthdate_from_timestamp, birthdate_to_timestamp};
+use crate::commands::parse_date;
+use crate::events::EventType;
+use crate::{reminder_from_row, DbReminder, REMINDER_COLUMNS, UNKNOWN_BIRTH_YEAR};
+
+/// Most rows a single upload may add
+pub const MAX_IMPORT_ROWS: usize = 1000;
+
+/// Year Apple Contacts writes for birthdays saved without one
+const APPLE_OMITTED_YEAR: i32 = 1604;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    Csv,
+    VCard,
+    Ics,
+}
+
+impl ImportFormat {
+    /// Value used by the upload form
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportFormat::Csv => "csv",
+            ImportFormat::VCard => "vcard",
+            ImportFormat::Ics => "ics",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "csv" => Some(ImportFormat::Csv),
+            "vcard" => Some(ImportFormat::VCard),
+            "ics" => Some(ImportFormat::Ics),
+            _ => None,
+        }
+    }
+}
+
+/// Which CSV columns hold the name and date. Each is a header name
+/// (case-insensitive) or a 1-based column number, and the name may join
+/// several columns with "+", e.g. "First Name + Last Name".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvColumns {
+    pub name: String,
+    pub date: String,
+}
+
+impl Default for CsvColumns {
+    fn default() -> Self {
+        CsvColumns {
+            name: "Name".to_string(),
+            date: "Birthday".to_string(),
+        }
+    }
+}
+
+/// A reminder read from an uploaded file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportRow {
+    pub name: String,
+    /// In `UNKNOWN_BIRTH_YEAR` when the year isn't known
+    pub birthdate: NaiveDate,
+    pub year_known: bool,
+    pub event_type: EventType,
+}
+
+/// The rows read from a file, and a note for each entry that couldn't be read
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ParsedImport {
+    pub rows: Vec<ImportRow>,
+    pub skipped: Vec<String>,
+}
+
+/// Reads reminders from an uploaded file. Entries without a date (contacts
+/// with no birthday, one-off events) are passed over silently; entries with a
+/// date we can't read are listed in `skipped`.
+pub fn parse_import(
+    format: ImportFormat,
+    content: &str,
+    columns: &CsvColumns,
+) -> Result<ParsedImport, String> {
+    let parsed = match format {
+        ImportFormat::Csv => parse_csv(content, columns)?,
+        ImportFormat::VCard => parse_vcards(content),
+        ImportFormat::Ics => parse_ics_events(content),
+    };
+
+    if parsed.rows.len() > MAX_IMPORT_ROWS {
+        return Err(format!(
+            "That file has {} dates; please import at most {} at a time",
+            parsed.rows.len(),
+            MAX_IMPORT_ROWS
+        ));
+    }
+    Ok(parsed)
+}
+
+pub fn parse_csv(content: &str, columns: &CsvColumns) -> Result<ParsedImport, String> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(Trim::All)
+        .from_reader(content.as_bytes());
+    let records = reader
+        .records()
+        .collect::<Result<Vec<StringRecord>, _>>()
+        .map_err(|e| format!("Couldn't read the CSV file: {}", e))?;
+    let Some(first) = records.first() else {
+        return Ok(ParsedImport::default());
+    };
+
+    let name_columns = columns
+        .name
+        .split('+')
+        .map(|spec| column_index(spec, first))
+        .collect::<Result<Vec<_>, _>>()?;
+    let date_column = column_index(&columns.date, first)?;
+
+    // Skip a header row, whether the columns were picked by name or not
+    let has_header = name_columns.iter().any(|(_, by_name)| *by_name)
+        || date_column.1
+        || parse_import_date(first.get(date_column.0).unwrap_or("")).is_none();
+
+    let mut parsed = ParsedImport::default();
+    for (index, record) in records.iter().enumerate().skip(has_header as usize) {
+        let date = record.get(date_column.0).unwrap_or("");
+        if date.is_empty() {
+            continue;
+        }
+        let name = name_columns
+            .iter()
+            .filter_map(|(column, _)| record.get(*column))
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let row_number = index + 1;
+
+        if name.is_empty() {
+            parsed
+                .skipped
+                .push(format!("Row {}: no name for {}", row_number, date));
+            continue;
+        }
+        match parse_import_date(date) {
+            Some((birthdate, year_known)) => parsed.rows.push(ImportRow {
+                name,
+                birthdate,
+                year_known,
+                event_type: EventType::Birthday,
+            }),
+            None => parsed.skipped.push(format!(
+                "Row {}: couldn't read the date \"{}\" for {}",
+                row_number, date, name
+            )),
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Resolves a column spec against the first row, returning the index and
+/// whether it was found by name.
+fn column_index(spec: &str, first: &StringRecord) -> Result<(usize, bool), String> {
+    let spec = spec.trim();
+    if let Ok(number) = spec.parse::<usize>() {
+        return match number {
+            0 => Err("Column numbers start at 1".to_string()),
+            number => Ok((number - 1, false)),
+        };
+    }
+
+    first
+        .iter()
+        .position(|header| header.eq_ignore_ascii_case(spec))
+        .map(|index| (index, true))
+        .ok_or_else(|| format!("No \"{}\" column in the first row", spec))
+}
+
+/// Reads the BDAY (and vCard 4 ANNIVERSARY) of each contact.
+pub fn parse_vcards(content: &str) -> ParsedImport {
+    let mut parsed = ParsedImport::default();
+    let mut card: Vec<ContentLine> = Vec::new();
+
+    for line in content_lines(content) {
+        match (line.name.as_str(), line.value.to_ascii_uppercase().as_str()) {
+            ("BEGIN", "VCARD") => card.clear(),
+            ("END", "VCARD") => {
+                let name = card
+                    .iter()
+                    .find(|line| line.name == "FN")
+                    .map(|line| unescape_text(&line.value))
+                    .filter(|name| !name.trim().is_empty())
+                    .or_else(|| {
+                        card.iter()
+                            .find(|line| line.name == "N")
+                            .map(structured_name)
+                    });
+
+                for line in &card {
+                    let event_type = match line.name.as_str() {
+                        "BDAY" => EventType::Birthday,
+                        "ANNIVERSARY" | "X-ANNIVERSARY" => EventType::WeddingAnniversary,
+                        _ => continue,
+                    };
+                    push_dated(&mut parsed, name.as_deref(), &line.value, event_type);
+                }
+                card.clear();
+            }
+            _ => card.push(line),
+        }
+    }
+
+    parsed
+}
+
+/// Reads each yearly event from a calendar export, such as Google
+/// Calendar's birthdays calendar. Summaries like "Jane's birthday" become
+/// the name.
+pub fn parse_ics_events(content: &str) -> ParsedImport {
+    let mut parsed = ParsedImport::default();
+    let mut event: Option<Vec<ContentLine>> = None;
+
+    for line in content_lines(content) {
+        match (line.name.as_str(), line.value.to_ascii_uppercase().as_str()) {
+            ("BEGIN", "VEVENT") => event = Some(Vec::new()),
+            ("END", "VEVENT") => {
+                let Some(lines) = event.take() else {
+                    continue;
+                };
+                let property = |name: &str| lines.iter().find(|line| line.name == name);
+                let yearly = property("RRULE").is_some_and(|rule| {
+                    rule.value
+                        .to_ascii_uppercase()
+                        .split(';')
+                        .any(|part| part == "FREQ=YEARLY")
+                });
+                // Overrides of a single occurrence repeat their parent event
+                if !yearly || property("RECURRENCE-ID").is_some() {
+                    continue;
+                }
+                let Some(start) = property("DTSTART") else {
+                    continue;
+                };
+
+                let summary = property("SUMMARY")
+                    .map(|line| unescape_text(&line.value))
+                    .unwrap_or_default();
+                let (name, event_type) = name_from_summary(&summary);
+                // Only the date part of a DATE-TIME start
+                let date: String = start.value.chars().take(8).collect();
+                push_dated(&mut parsed, Some(&name), &date, event_type);
+            }
+            _ => {
+                if let Some(lines) = event.as_mut() {
+                    lines.push(line);
+                }
+            }
+        }
+    }
+
+    parsed
+}
+
+fn push_dated(parsed: &mut ParsedImport, name: Option<&str>, date: &str, event_type: EventType) {
+    let name = name.map(str::trim).unwrap_or_default();
+    if name.is_empty() {
+        parsed
+            .skipped
+            .push(format!("An entry dated {} has no name", date));
+        return;
+    }
+    match parse_import_date(date) {
+        Some((birthdate, year_known)) => parsed.rows.push(ImportRow {
+            name: name.to_string(),
+            birthdate,
+            year_known,
+            event_type,
+        }),
+        None => parsed
+            .skipped
+            .push(format!("Couldn't read the date \"{}\" for {}", date, name)),
+    }
+}
+
+/// "Jane Doe's birthday" -> ("Jane Doe", Birthday); "Sam & Alex's
+/// anniversary" -> ("Sam & Alex", WeddingAnniversary). Anything else is kept
+/// whole as a birthday.
+fn name_from_summary(summary: &str) -> (String, EventType) {
+    let summary = summary.trim();
+
+    for (suffix, event_type) in [
+        ("'s birthday", EventType::Birthday),
+        ("’s birthday", EventType::Birthday),
+        (" birthday", EventType::Birthday),
+        ("'s anniversary", EventType::WeddingAnniversary),
+        ("’s anniversary", EventType::WeddingAnniversary),
+    ] {
+        let split = summary.len().saturating_sub(suffix.len());
+        if summary.is_char_boundary(split) && summary[split..].eq_ignore_ascii_case(suffix) {
+            return (summary[..split].trim().to_string(), event_type);
+        }
+    }
+    for prefix in ["birthday:", "birthday -", "birthday of "] {
+        if summary.is_char_boundary(prefix.len())
+            && summary[..prefix.len()].eq_ignore_ascii_case(prefix)
+        {
+            return (
+                summary[prefix.len()..].trim().to_string(),
+                EventType::Birthday,
+            );
+        }
+    }
+
+    (summary.to_string(), EventType::Birthday)
+}
+
+/// Accepts the formats exports use: YYYY-MM-DD, YYYYMMDD, M/D/YYYY, and
+/// the year-less M/D, --MM-DD and --MMDD. Apple's placeholder year 1604
+/// counts as unknown.
+pub fn parse_import_date(date: &str) -> Option<(NaiveDate, bool)> {
+    let date = date.trim();
+    if let Some(month_day) = date.strip_prefix("--") {
+        let month_day = month_day.replace('-', "");
+        return compact_date(&format!("{}{}", UNKNOWN_BIRTH_YEAR, month_day))
+            .map(|date| (date, false));
+    }
+
+    let parsed = compact_date(date).or_else(|| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok());
+    match parsed {
+        Some(parsed) if parsed.year() == APPLE_OMITTED_YEAR => {
+            Some((parsed.with_year(UNKNOWN_BIRTH_YEAR)?, false))
+        }
+        Some(parsed) if date.len() == 8 => Some((parsed, true)),
+        _ => parse_date(date),
+    }
+}
+
+/// YYYYMMDD, which chrono's greedy `%Y` won't split
+fn compact_date(date: &str) -> Option<NaiveDate> {
+    if date.len() != 8 || !date.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(
+        date[..4].parse().ok()?,
+        date[4..6].parse().ok()?,
+        date[6..].parse().ok()?,
+    )
+}
+
+/// Marks each row that matches an existing reminder, or an earlier row in
+/// the same file: the same name (ignoring case and spacing) on the same
+/// month and day.
+pub fn find_duplicates(existing: &[DbReminder], rows: &[ImportRow]) -> Vec<bool> {
+    let mut seen: HashSet<(String, u32, u32)> = existing
+        .iter()
+        .filter_map(|reminder| {
+            let date = birthdate_from_timestamp(reminder.birthdate)?;
+            Some((normalize_name(&reminder.name), date.month(), date.day()))
+        })
+        .collect();
+
+    rows.iter()
+        .map(|row| {
+            let key = (
+                normalize_name(&row.name),
+                row.birthdate.month(),
+                row.birthdate.day(),
+            );
+            !seen.insert(key)
+        })
+        .collect()
+}
+
+fn normalize_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Adds the rows as reminders in one transaction, so a failed upload leaves
+/// nothing half-imported. Rows that duplicate one of the user's reminders as
+/// of the import, or an earlier row, are skipped. Returns how many were added.
+pub async fn import_reminders(
+    pool: &SqlitePool,
+    user_id: i64,
+    rows: &[ImportRow],
+) -> Result<usize, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    // Checked again here: the preview's check is stale by the time the user
+    // confirms, e.g. after submitting the same import twice
+    let existing: Vec<DbReminder> = sqlx::query(&format!(
+        "SELECT {} FROM reminders WHERE user_id = ?",
+        REMINDER_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(&mut *tx)
+    .await?
+    .iter()
+    .map(reminder_from_row)
+    .collect();
+    let duplicates = find_duplicates(&existing, rows);
+
+    let mut added = 0;
+    for (row, duplicate) in rows.iter().zip(duplicates) {
+        if duplicate {
+            continue;
+        }
+        let birthdate = birthdate_to_timestamp(row.birthdate);
+        sqlx::query(
+            "INSERT INTO reminders (user_id, name, birthdate, year_known, event_type, event_label)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(user_id)
+        .bind(&row.name)
+        .bind(birthdate)
+        .bind(row.year_known)
+        .bind(row.event_type.as_str())
+        .bind(row.event_type.label())
+        .execute(&mut *tx)
+        .await?;
+        added += 1;
+    }
+
+    tx.commit().await?;
+    Ok(added)
+}
+
+/// An unfolded vCard/iCalendar line, e.g. `BDAY;VALUE=date:1990-03-14`
+struct ContentLine {
+    /// Upper-cased, without any "item1." group prefix
+    name: String,
+    value: String,
+}
+
+fn content_lines(content: &str) -> Vec<ContentLine> {
+    let mut unfolded: Vec<String> = Vec::new();
+    for line in content.lines() {
+        match (line.strip_prefix([' ', '\t']), unfolded.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => unfolded.push(line.to_string()),
+        }
+    }
+
+    unfolded
+        .into_iter()
+        .filter_map(|line| {
+            let (head, value) = line.split_once(':')?;
+            let name = head.split(';').next().unwrap_or(head);
+            let name = name.rsplit('.').next().unwrap_or(name);
+            Some(ContentLine {
+                name: name.trim().to_ascii_uppercase(),
+                value: value.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// "Doe;Jane;;;" -> "Jane Doe"
+fn structured_name(line: &ContentLine) -> String {
+    let parts: Vec<String> = line.value.split(';').map(unescape_text).collect();
+    let family = parts.first().map(String::as_str).unwrap_or("");
+    let given = parts.get(1).map(String::as_str).unwrap_or("");
+    format!("{} {}", given, family).trim().to_string()
+}
+
+fn unescape_text(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        // A name shouldn't span lines, so an escaped newline becomes a space
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push(' '),
+            Some(next) => unescaped.push(next),
+            None => {}
+        }
+    }
+    unescaped
+}
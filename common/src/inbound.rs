@@ -0,0 +1,98 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How far a signature timestamp may drift from our clock before we reject it
+pub const SIGNATURE_TOLERANCE_SECS: i64 = 5 * 60;
+
+pub const HELP_REPLY: &str =
+    "hbd.bot: birthday reminders by text. Manage reminders at https://hbd.bot. Reply STOP to unsubscribe, START to resubscribe.";
+pub const STOP_REPLY: &str =
+    "hbd.bot: You're unsubscribed and won't get any more reminders. Reply START to resubscribe.";
+pub const START_REPLY: &str =
+    "hbd.bot: You're resubscribed and will get birthday reminders again. Reply STOP to unsubscribe.";
+
+/// Carrier compliance keywords we have to honor on every inbound message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComplianceKeyword {
+    Stop,
+    Start,
+    Help,
+}
+
+/// Matches the whole message (ignoring case, whitespace and trailing
+/// punctuation) against the standard opt-out, opt-in and help keywords.
+pub fn parse_compliance_keyword(body: &str) -> Option<ComplianceKeyword> {
+    let keyword = body
+        .trim()
+        .trim_end_matches(['.', '!'])
+        .to_ascii_uppercase();
+
+    match keyword.as_str() {
+        "STOP" | "STOPALL" | "UNSUBSCRIBE" | "CANCEL" | "END" | "QUIT" => {
+            Some(ComplianceKeyword::Stop)
+        }
+        "START" | "UNSTOP" | "YES" => Some(ComplianceKeyword::Start),
+        "HELP" | "INFO" => Some(ComplianceKeyword::Help),
+        _ => None,
+    }
+}
+
+/// Turns an inbound sender like "+15551234567" into the 10-digit form stored
+/// on users. Returns `None` for anything that isn't a US number.
+pub fn normalize_phone_number(from: &str) -> Option<String> {
+    let digits: String = from.chars().filter(|c| c.is_ascii_digit()).collect();
+
+    match digits.len() {
+        10 => Some(digits),
+        11 if digits.starts_with('1') => Some(digits[1..].to_string()),
+        _ => None,
+    }
+}
+
+/// Builds the `X-Signature` header value for `body` sent at `timestamp`, the
+/// counterpart of `verify_signature` for whatever posts to the webhook.
+pub fn sign_payload(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    format!(
+        "t={},v1={}",
+        timestamp,
+        hex::encode(mac.finalize().into_bytes())
+    )
+}
+
+/// Checks an `X-Signature: t=<unix>,v1=<hex>` header, where the signature is
+/// HMAC-SHA256 over "<t>.<body>" keyed with the shared secret.
+pub fn verify_signature(secret: &str, header: &str, body: &[u8], now: i64) -> bool {
+    let mut timestamp = None;
+    let mut signature = None;
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signature = hex::decode(value).ok(),
+            _ => {}
+        }
+    }
+
+    let (Some(timestamp), Some(signature)) = (timestamp, signature) else {
+        return false;
+    };
+    if (now - timestamp).abs() > SIGNATURE_TOLERANCE_SECS {
+        return false;
+    }
+
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    // verify_slice compares in constant time
+    mac.verify_slice(&signature).is_ok()
+}
@@ -0,0 +1,365 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use sqlx::{
+    sqlite::{SqlitePool, SqliteRow},
+    Row,
+};
+use std::collections::HashSet;
+
+use crate::format_db_timestamp;
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_SENT: &str = "sent";
+/// Waiting in the retry queue until `next_attempt_at`
+pub const STATUS_RETRYING: &str = "retrying";
+/// Gave up: the provider rejected the message or we ran out of attempts
+pub const STATUS_DEAD: &str = "dead";
+
+/// Attempts (including the first send) before a digest is dead-lettered
+pub const MAX_SEND_ATTEMPTS: i64 = 5;
+
+/// How long a pending digest's claims hold. A run sends right after claiming,
+/// so anything older belongs to a run that died before recording the send.
+pub const CLAIM_LEASE_MINUTES: i64 = 15;
+
+/// One reminder occurrence covered by a digest.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DigestEntry {
+    pub reminder_id: i64,
+    pub birthday_date: NaiveDate,
+    pub days_until: i64,
+}
+
+pub struct DbSentMessage {
+    pub id: i64,
+    pub user_id: i64,
+    pub provider: String,
+    pub provider_message_id: Option<String>,
+    pub body: String,
+    pub status: String,
+    pub error: Option<String>,
+    pub attempts: i64,
+    pub next_attempt_at: Option<String>,
+    pub created_at: String,
+    pub sent_at: Option<String>,
+    pub updated_at: String,
+}
+
+const SENT_MESSAGE_COLUMNS: &str = "id, user_id, provider, provider_message_id, body, status, error, attempts, next_attempt_at, created_at, sent_at, updated_at";
+
+fn sent_message_from_row(row: &SqliteRow) -> DbSentMessage {
+    DbSentMessage {
+        id: row.get("id"),
+        user_id: row.get("user_id"),
+        provider: row.get("provider"),
+        provider_message_id: row.get("provider_message_id"),
+        body: row.get("body"),
+        status: row.get("status"),
+        error: row.get("error"),
+        attempts: row.get("attempts"),
+        next_attempt_at: row.get("next_attempt_at"),
+        created_at: row.get("created_at"),
+        sent_at: row.get("sent_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+/// Delay before the next attempt once `attempts` sends have failed:
+/// 5 minutes, then 10, 20, 40...
+pub fn retry_backoff(attempts: i64) -> Duration {
+    let exponent = (attempts - 1).clamp(0, 10) as u32;
+    Duration::minutes(5 * 2i64.pow(exponent))
+}
+
+/// Records a pending digest and claims every entry it covers. Returns `None`
+/// if any entry is held by another digest, in which case nothing is written
+/// and the digest must not be sent. Claims still pending after
+/// `CLAIM_LEASE_MINUTES` are taken over, and the digest that held them is
+/// dead-lettered.
+pub async fn claim_digest(
+    pool: &SqlitePool,
+    user_id: i64,
+    provider: &str,
+    body: &str,
+    entries: &[DigestEntry],
+    current_utc: DateTime<Utc>,
+) -> Result<Option<i64>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let lease_cutoff = format_db_timestamp(current_utc - Duration::minutes(CLAIM_LEASE_MINUTES));
+    let mut stale_ids = HashSet::new();
+    for entry in entries {
+        let released: Vec<i64> = sqlx::query_scalar(
+            "DELETE FROM sent_message_reminders
+             WHERE reminder_id = ? AND birthday_date = ? AND days_until = ?
+               AND sent_message_id IN (
+                   SELECT id FROM sent_messages WHERE status = ? AND claimed_at <= ?
+               )
+             RETURNING sent_message_id",
+        )
+        .bind(entry.reminder_id)
+        .bind(entry.birthday_date.format("%Y-%m-%d").to_string())
+        .bind(entry.days_until)
+        .bind(STATUS_PENDING)
+        .bind(&lease_cutoff)
+        .fetch_all(&mut *tx)
+        .await?;
+        stale_ids.extend(released);
+    }
+    for stale_id in stale_ids {
+        sqlx::query(
+            "UPDATE sent_messages SET status = ?, error = ?, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?",
+        )
+        .bind(STATUS_DEAD)
+        .bind("Claim lease expired before the send was recorded")
+        .bind(stale_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let result = sqlx::query(
+        "INSERT INTO sent_messages (user_id, provider, body, status, claimed_at)
+         VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(user_id)
+    .bind(provider)
+    .bind(body)
+    .bind(STATUS_PENDING)
+    .bind(format_db_timestamp(current_utc))
+    .execute(&mut *tx)
+    .await?;
+    let sent_message_id = result.last_insert_rowid();
+
+    for entry in entries {
+        let claimed = sqlx::query(
+            "INSERT INTO sent_message_reminders (sent_message_id, reminder_id, birthday_date, days_until)
+             VALUES (?, ?, ?, ?)",
+        )
+        .bind(sent_message_id)
+        .bind(entry.reminder_id)
+        .bind(entry.birthday_date.format("%Y-%m-%d").to_string())
+        .bind(entry.days_until)
+        .execute(&mut *tx)
+        .await;
+
+        match claimed {
+            Ok(_) => {}
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                tx.rollback().await?;
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    tx.commit().await?;
+    Ok(Some(sent_message_id))
+}
+
+pub async fn mark_digest_sent(
+    pool: &SqlitePool,
+    sent_message_id: i64,
+    provider_message_id: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE sent_messages
+         SET status = ?, provider_message_id = ?, attempts = attempts + 1, error = NULL,
+             next_attempt_at = NULL, sent_at = CURRENT_TIMESTAMP, updated_at = CURRENT_TIMESTAMP
+         WHERE id = ?",
+    )
+    .bind(STATUS_SENT)
+    .bind(provider_message_id)
+    .bind(sent_message_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Records a failed send. The digest keeps its claims either way: it goes back
+/// into the retry queue with exponential backoff, or to the dead-letter state
+/// if the error was permanent or it has used up its attempts. Returns the new
+/// status.
+pub async fn record_failed_attempt(
+    pool: &SqlitePool,
+    sent_message_id: i64,
+    error: &str,
+    permanent: bool,
+    current_utc: DateTime<Utc>,
+) -> Result<&'static str, sqlx::Error> {
+    let attempts: i64 = sqlx::query("SELECT attempts FROM sent_messages WHERE id = ?")
+        .bind(sent_message_id)
+        .fetch_one(pool)
+        .await?
+        .get("attempts");
+    let attempts = attempts + 1;
+
+    let (status, next_attempt_at) = if permanent || attempts >= MAX_SEND_ATTEMPTS {
+        (STATUS_DEAD, None)
+    } else {
+        (
+            STATUS_RETRYING,
+            Some(format_db_timestamp(current_utc + retry_backoff(attempts))),
+        )
+    };
+
+    sqlx::query(
+        "UPDATE sent_messages
+         SET status = ?, error = ?, attempts = ?, next_attempt_at = ?, updated_at = CURRENT_TIMESTAMP
+         WHERE id = ?",
+    )
+    .bind(status)
+    .bind(error)
+    .bind(attempts)
+    .bind(next_attempt_at)
+    .bind(sent_message_id)
+    .execute(pool)
+    .await?;
+
+    Ok(status)
+}
+
+/// Digests in the retry queue whose next attempt is due.
+pub async fn get_due_retries(
+    pool: &SqlitePool,
+    current_utc: DateTime<Utc>,
+) -> Result<Vec<DbSentMessage>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM sent_messages WHERE status = ? AND next_attempt_at <= ? ORDER BY next_attempt_at, id",
+        SENT_MESSAGE_COLUMNS
+    ))
+    .bind(STATUS_RETRYING)
+    .bind(format_db_timestamp(current_utc))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.iter().map(sent_message_from_row).collect())
+}
+
+/// Takes a due digest out of the retry queue before resending it, so
+/// overlapping runs don't both send it. Returns false if another run got there
+/// first. The digest is pending again under a fresh lease: if this run dies
+/// before recording the send, its claims are taken over like any other stale
+/// pending digest's.
+pub async fn claim_retry(
+    pool: &SqlitePool,
+    sent_message_id: i64,
+    current_utc: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let now = format_db_timestamp(current_utc);
+    let result = sqlx::query(
+        "UPDATE sent_messages
+         SET status = ?, claimed_at = ?, updated_at = CURRENT_TIMESTAMP
+         WHERE id = ? AND status = ? AND next_attempt_at <= ?",
+    )
+    .bind(STATUS_PENDING)
+    .bind(&now)
+    .bind(sent_message_id)
+    .bind(STATUS_RETRYING)
+    .bind(&now)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Every dead-lettered digest, most recent first.
+pub async fn get_dead_letters(pool: &SqlitePool) -> Result<Vec<DbSentMessage>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM sent_messages WHERE status = ? ORDER BY updated_at DESC, id DESC",
+        SENT_MESSAGE_COLUMNS
+    ))
+    .bind(STATUS_DEAD)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.iter().map(sent_message_from_row).collect())
+}
+
+/// Every reminder occurrence claimed by one of the user's digests, leaving out
+/// pending claims whose lease has run out.
+pub async fn get_claimed_entries_by_user_id(
+    pool: &SqlitePool,
+    user_id: i64,
+    current_utc: DateTime<Utc>,
+) -> Result<HashSet<DigestEntry>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT smr.reminder_id, smr.birthday_date, smr.days_until
+         FROM sent_message_reminders smr
+         JOIN sent_messages sm ON sm.id = smr.sent_message_id
+         WHERE sm.user_id = ? AND NOT (sm.status = ? AND sm.claimed_at <= ?)",
+    )
+    .bind(user_id)
+    .bind(STATUS_PENDING)
+    .bind(format_db_timestamp(
+        current_utc - Duration::minutes(CLAIM_LEASE_MINUTES),
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    let entries = rows
+        .into_iter()
+        .filter_map(|row| {
+            let birthday_date: String = row.get("birthday_date");
+            Some(DigestEntry {
+                reminder_id: row.get("reminder_id"),
+                birthday_date: NaiveDate::parse_from_str(&birthday_date, "%Y-%m-%d").ok()?,
+                days_until: row.get("days_until"),
+            })
+        })
+        .collect();
+
+    Ok(entries)
+}
+
+/// Reminder occurrences covered by the last digest the user actually received,
+/// which is what a SNOOZE reply refers to.
+pub async fn get_latest_digest_entries(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> Result<Vec<DigestEntry>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT smr.reminder_id, smr.birthday_date, smr.days_until
+         FROM sent_message_reminders smr
+         WHERE smr.sent_message_id = (
+             SELECT id FROM sent_messages
+             WHERE user_id = ? AND status = ?
+             ORDER BY sent_at DESC, id DESC
+             LIMIT 1
+         )
+         ORDER BY smr.days_until, smr.reminder_id",
+    )
+    .bind(user_id)
+    .bind(STATUS_SENT)
+    .fetch_all(pool)
+    .await?;
+
+    let entries = rows
+        .into_iter()
+        .filter_map(|row| {
+            let birthday_date: String = row.get("birthday_date");
+            Some(DigestEntry {
+                reminder_id: row.get("reminder_id"),
+                birthday_date: NaiveDate::parse_from_str(&birthday_date, "%Y-%m-%d").ok()?,
+                days_until: row.get("days_until"),
+            })
+        })
+        .collect();
+
+    Ok(entries)
+}
+
+pub async fn get_sent_messages_by_user_id(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> Result<Vec<DbSentMessage>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM sent_messages WHERE user_id = ? ORDER BY id",
+        SENT_MESSAGE_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.iter().map(sent_message_from_row).collect())
+}
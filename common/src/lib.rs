@@ -1,28 +1,164 @@
-use sqlx::{migrate::MigrateDatabase, sqlite::SqlitePool, Row, Sqlite};
-
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::{
+    migrate::MigrateDatabase,
+    sqlite::{SqlitePool, SqliteRow},
+    Row, Sqlite,
+};
+
+pub mod api;
+pub mod api_tokens;
+pub mod birthday;
+pub mod calendars;
+pub mod clock;
+pub mod commands;
+pub mod events;
+pub mod export;
+pub mod ics;
+pub mod import;
+pub mod inbound;
+pub mod ledger;
 pub mod message_central;
+pub mod notice;
+pub mod occurrences;
+pub mod otp;
+pub mod otp_sessions;
+pub mod rate_limit;
+pub mod recurrence;
+pub mod sealing;
+pub mod sessions;
+pub mod sms;
 pub mod surge;
 pub use message_central::MessageCentralSendOTPData;
 
+use birthday::LeapDayPolicy;
+use calendars::{CalendarDate, CalendarSystem};
+use events::EventType;
+use notice::{format_notice_offsets, parse_notice_offsets, DEFAULT_NOTICE_OFFSETS};
+use recurrence::Recurrence;
+
+/// How timestamps are stored: UTC, the same form as `CURRENT_TIMESTAMP`
+pub(crate) const DB_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+pub(crate) fn format_db_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.format(DB_TIMESTAMP_FORMAT).to_string()
+}
+
+/// What's stored in place of a secret we generated, like a session cookie or
+/// an API token. Those are random, so a plain hash is enough; there's nothing
+/// to guess.
+pub fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// Deletes the rows of `table` whose `expires_at` has passed. Tables of
+/// short-lived rows call this when adding one, so they don't pile up.
+pub(crate) async fn delete_expired(
+    pool: &SqlitePool,
+    table: &str,
+    current_utc: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(&format!("DELETE FROM {} WHERE expires_at <= ?", table))
+        .bind(format_db_timestamp(current_utc))
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
 pub struct DbUser {
     pub id: i64,
     pub phone_number: String,
     pub created_at: String,
     pub last_digest_at: Option<String>,
-    pub days_notice: i64,
+    /// Days before a birthday to send a reminder, furthest first (0 = day-of)
+    pub notice_offsets: Vec<i64>,
     pub send_hour: i64,
     pub iana_tz: String,
+    pub leap_day_policy: LeapDayPolicy,
+    /// Set while the user has texted STOP; no digests go out until START
+    pub sms_opted_out_at: Option<String>,
 }
 
+/// Timezones users can pick (US only for now)
+pub const SUPPORTED_TIMEZONES: [&str; 6] = [
+    "America/New_York",
+    "America/Chicago",
+    "America/Denver",
+    "America/Los_Angeles",
+    "America/Anchorage",
+    "Pacific/Honolulu",
+];
+
+/// Placeholder year stored for birthdays with no known year. It's a leap year
+/// so Feb 29 can be stored.
+pub const UNKNOWN_BIRTH_YEAR: i32 = 2000;
+
 pub struct DbReminder {
     pub id: i64,
     pub user_id: i64,
     pub name: String,
     pub birthdate: i64, // Unix timestamp in milliseconds
+    /// False when only the month and day are known; `birthdate` then uses
+    /// `UNKNOWN_BIRTH_YEAR`
+    pub year_known: bool,
+    /// Overrides the user's `notice_offsets` for this reminder when set
+    pub notice_offsets: Option<Vec<i64>>,
+    pub event_type: EventType,
+    /// Repeats on this rule instead of once a year when set
+    pub recurrence: Option<Recurrence>,
+    /// The date in a lunar or Hebrew calendar, for reminders that follow
+    /// one; `None` for Gregorian
+    pub calendar_date: Option<CalendarDate>,
     pub created_at: String,
     pub updated_at: String,
 }
 
+const USER_COLUMNS: &str =
+    "id, phone_number, created_at, last_digest_at, notice_offsets, send_hour, iana_tz, leap_day_policy, sms_opted_out_at";
+
+fn user_from_row(row: &SqliteRow) -> DbUser {
+    DbUser {
+        id: row.get("id"),
+        phone_number: row.get("phone_number"),
+        created_at: row.get("created_at"),
+        last_digest_at: row.get("last_digest_at"),
+        notice_offsets: parse_notice_offsets(&row.get::<String, _>("notice_offsets"))
+            .unwrap_or_else(|_| DEFAULT_NOTICE_OFFSETS.to_vec()),
+        send_hour: row.get("send_hour"),
+        iana_tz: row.get("iana_tz"),
+        leap_day_policy: LeapDayPolicy::parse(row.get("leap_day_policy")).unwrap_or_default(),
+        sms_opted_out_at: row.get("sms_opted_out_at"),
+    }
+}
+
+const REMINDER_COLUMNS: &str =
+    "id, user_id, name, birthdate, year_known, notice_offsets, event_type, event_label, rrule, calendar, calendar_month, calendar_day, created_at, updated_at";
+
+fn reminder_from_row(row: &SqliteRow) -> DbReminder {
+    let notice_offsets: Option<String> = row.get("notice_offsets");
+    let event_label: Option<String> = row.get("event_label");
+    let rrule: Option<String> = row.get("rrule");
+    let calendar_month: Option<String> = row.get("calendar_month");
+    let calendar_day: Option<i64> = row.get("calendar_day");
+    DbReminder {
+        id: row.get("id"),
+        user_id: row.get("user_id"),
+        name: row.get("name"),
+        birthdate: row.get("birthdate"),
+        year_known: row.get("year_known"),
+        notice_offsets: notice_offsets.and_then(|offsets| parse_notice_offsets(&offsets).ok()),
+        event_type: EventType::parse(row.get("event_type"), event_label.as_deref())
+            .unwrap_or_default(),
+        recurrence: rrule.and_then(|rrule| Recurrence::parse(&rrule).ok()),
+        calendar_date: CalendarSystem::parse(row.get("calendar"))
+            .zip(calendar_month.zip(calendar_day))
+            .and_then(|(system, (month, day))| CalendarDate::parse(system, &month, day)),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
 pub async fn init_database(database_url: &str) -> Result<SqlitePool, sqlx::Error> {
     if !Sqlite::database_exists(database_url).await.unwrap_or(false) {
         println!("Creating database {}", database_url);
@@ -38,23 +174,11 @@ pub async fn init_database(database_url: &str) -> Result<SqlitePool, sqlx::Error
 }
 
 pub async fn get_all_users(pool: &SqlitePool) -> Result<Vec<DbUser>, sqlx::Error> {
-    let users =
-        sqlx::query("SELECT id, phone_number, created_at, last_digest_at, days_notice, send_hour, iana_tz FROM users")
-            .fetch_all(pool)
-            .await?;
+    let users = sqlx::query(&format!("SELECT {} FROM users", USER_COLUMNS))
+        .fetch_all(pool)
+        .await?;
 
-    let users: Vec<DbUser> = users
-        .into_iter()
-        .map(|row| DbUser {
-            id: row.get("id"),
-            phone_number: row.get("phone_number"),
-            created_at: row.get("created_at"),
-            last_digest_at: row.get("last_digest_at"),
-            days_notice: row.get("days_notice"),
-            send_hour: row.get("send_hour"),
-            iana_tz: row.get("iana_tz"),
-        })
-        .collect();
+    let users: Vec<DbUser> = users.into_iter().map(|row| user_from_row(&row)).collect();
 
     Ok(users)
 }
@@ -63,21 +187,16 @@ pub async fn get_user_by_phone(
     pool: &SqlitePool,
     phone_number: &str,
 ) -> Result<Option<DbUser>, sqlx::Error> {
-    let user = sqlx::query("SELECT id, phone_number, created_at, last_digest_at, days_notice, send_hour, iana_tz FROM users WHERE phone_number = ?")
-        .bind(phone_number)
-        .fetch_optional(pool)
-        .await?;
+    let user = sqlx::query(&format!(
+        "SELECT {} FROM users WHERE phone_number = ?",
+        USER_COLUMNS
+    ))
+    .bind(phone_number)
+    .fetch_optional(pool)
+    .await?;
 
     match user {
-        Some(row) => Ok(Some(DbUser {
-            id: row.get("id"),
-            phone_number: row.get("phone_number"),
-            created_at: row.get("created_at"),
-            last_digest_at: row.get("last_digest_at"),
-            days_notice: row.get("days_notice"),
-            send_hour: row.get("send_hour"),
-            iana_tz: row.get("iana_tz"),
-        })),
+        Some(row) => Ok(Some(user_from_row(&row))),
         None => Ok(None),
     }
 }
@@ -86,21 +205,13 @@ pub async fn get_user_by_id(
     pool: &SqlitePool,
     user_id: i64,
 ) -> Result<Option<DbUser>, sqlx::Error> {
-    let user = sqlx::query("SELECT id, phone_number, created_at, last_digest_at, days_notice, send_hour, iana_tz FROM users WHERE id = ?")
+    let user = sqlx::query(&format!("SELECT {} FROM users WHERE id = ?", USER_COLUMNS))
         .bind(user_id)
         .fetch_optional(pool)
         .await?;
 
     match user {
-        Some(row) => Ok(Some(DbUser {
-            id: row.get("id"),
-            phone_number: row.get("phone_number"),
-            created_at: row.get("created_at"),
-            last_digest_at: row.get("last_digest_at"),
-            days_notice: row.get("days_notice"),
-            send_hour: row.get("send_hour"),
-            iana_tz: row.get("iana_tz"),
-        })),
+        Some(row) => Ok(Some(user_from_row(&row))),
         None => Ok(None),
     }
 }
@@ -111,74 +222,49 @@ pub async fn create_user(pool: &SqlitePool, phone_number: &str) -> Result<DbUser
         .execute(pool)
         .await?;
 
-    let user = sqlx::query(
-        "SELECT id, phone_number, created_at, last_digest_at, days_notice, send_hour, iana_tz FROM users WHERE id = ?",
-    )
-    .bind(result.last_insert_rowid())
-    .fetch_one(pool)
-    .await?;
+    let user = sqlx::query(&format!("SELECT {} FROM users WHERE id = ?", USER_COLUMNS))
+        .bind(result.last_insert_rowid())
+        .fetch_one(pool)
+        .await?;
 
-    Ok(DbUser {
-        id: user.get("id"),
-        phone_number: user.get("phone_number"),
-        created_at: user.get("created_at"),
-        last_digest_at: user.get("last_digest_at"),
-        days_notice: user.get("days_notice"),
-        send_hour: user.get("send_hour"),
-        iana_tz: user.get("iana_tz"),
-    })
+    Ok(user_from_row(&user))
 }
 
 pub async fn get_all_reminders(pool: &SqlitePool) -> Result<Vec<DbReminder>, sqlx::Error> {
-    let reminders =
-        sqlx::query("SELECT id, user_id, name, birthdate, created_at, updated_at FROM reminders")
-            .fetch_all(pool)
-            .await?;
+    let reminders = sqlx::query(&format!("SELECT {} FROM reminders", REMINDER_COLUMNS))
+        .fetch_all(pool)
+        .await?;
 
     let reminders: Vec<DbReminder> = reminders
         .into_iter()
-        .map(|row| DbReminder {
-            id: row.get("id"),
-            user_id: row.get("user_id"),
-            name: row.get("name"),
-            birthdate: row.get("birthdate"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-        })
+        .map(|row| reminder_from_row(&row))
         .collect();
 
     Ok(reminders)
 }
 
+/// The user's reminders in creation order.
+///
+/// This used to sort upcoming-first in SQL by month and day against the
+/// server's UTC date. That's wrong near midnight for anyone outside UTC, and
+/// can't follow leap-day policies, recurrence rules or lunar and Hebrew
+/// dates, so there's no SQL version of that order any more: callers that want
+/// it use `BirthdayEngine::sort_upcoming`, which handles all of those.
 pub async fn get_reminders_by_user_id(
     pool: &SqlitePool,
     user_id: i64,
 ) -> Result<Vec<DbReminder>, sqlx::Error> {
-    let reminders = sqlx::query(
-        "SELECT id, user_id, name, birthdate, created_at, updated_at
-         FROM reminders
-         WHERE user_id = ?
-         ORDER BY
-           CASE
-             WHEN strftime('%m-%d', datetime(birthdate / 1000, 'unixepoch')) >= strftime('%m-%d', 'now')
-             THEN strftime('%m-%d', datetime(birthdate / 1000, 'unixepoch'))
-             ELSE '13' || strftime('%m-%d', datetime(birthdate / 1000, 'unixepoch'))
-           END"
-    )
+    let reminders = sqlx::query(&format!(
+        "SELECT {} FROM reminders WHERE user_id = ? ORDER BY id",
+        REMINDER_COLUMNS
+    ))
     .bind(user_id)
     .fetch_all(pool)
     .await?;
 
     let reminders: Vec<DbReminder> = reminders
         .into_iter()
-        .map(|row| DbReminder {
-            id: row.get("id"),
-            user_id: row.get("user_id"),
-            name: row.get("name"),
-            birthdate: row.get("birthdate"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-        })
+        .map(|row| reminder_from_row(&row))
         .collect();
 
     Ok(reminders)
@@ -188,22 +274,16 @@ pub async fn get_reminder_by_id(
     pool: &SqlitePool,
     reminder_id: i64,
 ) -> Result<Option<DbReminder>, sqlx::Error> {
-    let reminder = sqlx::query(
-        "SELECT id, user_id, name, birthdate, created_at, updated_at FROM reminders WHERE id = ?",
-    )
+    let reminder = sqlx::query(&format!(
+        "SELECT {} FROM reminders WHERE id = ?",
+        REMINDER_COLUMNS
+    ))
     .bind(reminder_id)
     .fetch_optional(pool)
     .await?;
 
     match reminder {
-        Some(row) => Ok(Some(DbReminder {
-            id: row.get("id"),
-            user_id: row.get("user_id"),
-            name: row.get("name"),
-            birthdate: row.get("birthdate"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-        })),
+        Some(row) => Ok(Some(reminder_from_row(&row))),
         None => Ok(None),
     }
 }
@@ -221,21 +301,15 @@ pub async fn create_reminder(
         .execute(pool)
         .await?;
 
-    let reminder = sqlx::query(
-        "SELECT id, user_id, name, birthdate, created_at, updated_at FROM reminders WHERE id = ?",
-    )
+    let reminder = sqlx::query(&format!(
+        "SELECT {} FROM reminders WHERE id = ?",
+        REMINDER_COLUMNS
+    ))
     .bind(result.last_insert_rowid())
     .fetch_one(pool)
     .await?;
 
-    Ok(DbReminder {
-        id: reminder.get("id"),
-        user_id: reminder.get("user_id"),
-        name: reminder.get("name"),
-        birthdate: reminder.get("birthdate"),
-        created_at: reminder.get("created_at"),
-        updated_at: reminder.get("updated_at"),
-    })
+    Ok(reminder_from_row(&reminder))
 }
 
 pub async fn update_reminder(
@@ -253,61 +327,212 @@ pub async fn update_reminder(
     .execute(pool)
     .await?;
 
-    let reminder = sqlx::query(
-        "SELECT id, user_id, name, birthdate, created_at, updated_at FROM reminders WHERE id = ?",
-    )
+    let reminder = sqlx::query(&format!(
+        "SELECT {} FROM reminders WHERE id = ?",
+        REMINDER_COLUMNS
+    ))
     .bind(reminder_id)
     .fetch_one(pool)
     .await?;
 
-    Ok(DbReminder {
-        id: reminder.get("id"),
-        user_id: reminder.get("user_id"),
-        name: reminder.get("name"),
-        birthdate: reminder.get("birthdate"),
-        created_at: reminder.get("created_at"),
-        updated_at: reminder.get("updated_at"),
-    })
+    Ok(reminder_from_row(&reminder))
+}
+
+/// Marks whether the reminder's birth year is known. Callers storing an
+/// unknown year should put the birthdate in `UNKNOWN_BIRTH_YEAR`.
+pub async fn update_reminder_year_known(
+    pool: &SqlitePool,
+    reminder_id: i64,
+    year_known: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE reminders SET year_known = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+        .bind(year_known)
+        .bind(reminder_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Sets (or with `None`, clears) the reminder's own notice offsets.
+pub async fn update_reminder_notice_offsets(
+    pool: &SqlitePool,
+    reminder_id: i64,
+    notice_offsets: Option<&[i64]>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE reminders SET notice_offsets = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+    )
+    .bind(notice_offsets.map(format_notice_offsets))
+    .bind(reminder_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn update_reminder_event_type(
+    pool: &SqlitePool,
+    reminder_id: i64,
+    event_type: &EventType,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE reminders SET event_type = ?, event_label = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+    )
+    .bind(event_type.as_str())
+    .bind(event_type.label())
+    .bind(reminder_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Sets (or with `None`, clears) the reminder's recurrence rule.
+pub async fn update_reminder_recurrence(
+    pool: &SqlitePool,
+    reminder_id: i64,
+    recurrence: Option<&Recurrence>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE reminders SET rrule = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+        .bind(recurrence.map(|recurrence| recurrence.to_string()))
+        .bind(reminder_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Sets (or with `None`, clears back to Gregorian) the calendar the
+/// reminder's date follows.
+pub async fn update_reminder_calendar_date(
+    pool: &SqlitePool,
+    reminder_id: i64,
+    calendar_date: Option<&CalendarDate>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE reminders SET calendar = ?, calendar_month = ?, calendar_day = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+    )
+    .bind(calendar_date.map_or(CalendarSystem::Gregorian, |date| date.system).as_str())
+    .bind(calendar_date.map(|date| date.month_code.as_str()))
+    .bind(calendar_date.map(|date| date.day as i64))
+    .bind(reminder_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Everything the add and edit forms (and the API) set on a reminder
+pub struct ReminderFields<'a> {
+    pub name: &'a str,
+    /// Milliseconds since the epoch, as `create_reminder` takes it
+    pub birthdate: &'a str,
+    pub year_known: bool,
+    pub notice_offsets: Option<&'a [i64]>,
+    pub event_type: &'a EventType,
+    pub recurrence: Option<&'a Recurrence>,
+    pub calendar_date: Option<&'a CalendarDate>,
+}
+
+/// Creates the reminder, or with `reminder_id` overwrites that one of the
+/// user's, writing every field in a single statement. `None` if there's no
+/// such reminder of theirs.
+pub async fn save_reminder(
+    pool: &SqlitePool,
+    user_id: i64,
+    reminder_id: Option<i64>,
+    fields: &ReminderFields<'_>,
+) -> Result<Option<DbReminder>, sqlx::Error> {
+    let sql = match reminder_id {
+        None => {
+            "INSERT INTO reminders (name, birthdate, year_known, notice_offsets, event_type,
+                 event_label, rrule, calendar, calendar_month, calendar_day, user_id)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        }
+        Some(_) => {
+            "UPDATE reminders
+             SET name = ?, birthdate = ?, year_known = ?, notice_offsets = ?, event_type = ?,
+                 event_label = ?, rrule = ?, calendar = ?, calendar_month = ?, calendar_day = ?,
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE user_id = ? AND id = ?"
+        }
+    };
+    let mut query = sqlx::query(sql)
+        .bind(fields.name)
+        .bind(fields.birthdate)
+        .bind(fields.year_known)
+        .bind(fields.notice_offsets.map(format_notice_offsets))
+        .bind(fields.event_type.as_str())
+        .bind(fields.event_type.label())
+        .bind(fields.recurrence.map(|recurrence| recurrence.to_string()))
+        .bind(
+            fields
+                .calendar_date
+                .map_or(CalendarSystem::Gregorian, |date| date.system)
+                .as_str(),
+        )
+        .bind(fields.calendar_date.map(|date| date.month_code.as_str()))
+        .bind(fields.calendar_date.map(|date| date.day as i64))
+        .bind(user_id);
+    if let Some(reminder_id) = reminder_id {
+        query = query.bind(reminder_id);
+    }
+    let result = query.execute(pool).await?;
+
+    let saved_id = match reminder_id {
+        None => result.last_insert_rowid(),
+        Some(_) if result.rows_affected() == 0 => return Ok(None),
+        Some(reminder_id) => reminder_id,
+    };
+    get_reminder_by_id(pool, saved_id).await
 }
 
 pub async fn update_user_settings(
     pool: &SqlitePool,
     user_id: i64,
-    days_notice: i64,
+    notice_offsets: &[i64],
     send_hour: i64,
     iana_tz: &str,
 ) -> Result<DbUser, sqlx::Error> {
-    sqlx::query("UPDATE users SET days_notice = ?, send_hour = ?, iana_tz = ? WHERE id = ?")
-        .bind(days_notice)
+    sqlx::query("UPDATE users SET notice_offsets = ?, send_hour = ?, iana_tz = ? WHERE id = ?")
+        .bind(format_notice_offsets(notice_offsets))
         .bind(send_hour)
         .bind(iana_tz)
         .bind(user_id)
         .execute(pool)
         .await?;
 
-    let user = sqlx::query(
-        "SELECT id, phone_number, created_at, last_digest_at, days_notice, send_hour, iana_tz FROM users WHERE id = ?",
-    )
-    .bind(user_id)
-    .fetch_one(pool)
-    .await?;
+    let user = sqlx::query(&format!("SELECT {} FROM users WHERE id = ?", USER_COLUMNS))
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
 
-    Ok(DbUser {
-        id: user.get("id"),
-        phone_number: user.get("phone_number"),
-        created_at: user.get("created_at"),
-        last_digest_at: user.get("last_digest_at"),
-        days_notice: user.get("days_notice"),
-        send_hour: user.get("send_hour"),
-        iana_tz: user.get("iana_tz"),
-    })
+    Ok(user_from_row(&user))
+}
+
+pub async fn update_user_leap_day_policy(
+    pool: &SqlitePool,
+    user_id: i64,
+    policy: LeapDayPolicy,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET leap_day_policy = ? WHERE id = ?")
+        .bind(policy.as_str())
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
 }
 
 pub async fn update_user_last_digest_at(
     pool: &SqlitePool,
     user_id: i64,
+    sent_at: DateTime<Utc>,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query("UPDATE users SET last_digest_at = CURRENT_TIMESTAMP WHERE id = ?")
+    // Taken from the sweeper's clock rather than CURRENT_TIMESTAMP
+    sqlx::query("UPDATE users SET last_digest_at = ? WHERE id = ?")
+        .bind(format_db_timestamp(sent_at))
         .bind(user_id)
         .execute(pool)
         .await?;
@@ -323,3 +548,31 @@ pub async fn delete_reminder(pool: &SqlitePool, reminder_id: i64) -> Result<(),
 
     Ok(())
 }
+
+/// Deletes the user's account. Their reminders, message history and other
+/// records go with it through `ON DELETE CASCADE`.
+pub async fn delete_user(pool: &SqlitePool, user_id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM users WHERE id = ?")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Records a STOP (`opted_out = true`) or START from the user.
+pub async fn set_user_sms_opt_out(
+    pool: &SqlitePool,
+    user_id: i64,
+    opted_out: bool,
+) -> Result<(), sqlx::Error> {
+    let query = if opted_out {
+        "UPDATE users SET sms_opted_out_at = CURRENT_TIMESTAMP WHERE id = ?"
+    } else {
+        "UPDATE users SET sms_opted_out_at = NULL WHERE id = ?"
+    };
+
+    sqlx::query(query).bind(user_id).execute(pool).await?;
+
+    Ok(())
+}
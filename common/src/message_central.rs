@@ -1,6 +1,10 @@
+use async_trait::async_trait;
 use reqwest;
 use serde::{Deserialize, Serialize};
 
+use crate::otp::{OtpChallenge, OtpError, OtpProvider, OTP_LENGTH};
+use crate::sms::{SmsError, SmsReceipt, SmsSender};
+
 const API_BASE_URL: &str = "https://cpaas.messagecentral.com";
 
 #[derive(Debug)]
@@ -272,13 +276,95 @@ impl MessageCentralClient {
             });
         }
 
-        let send_sms_response = serde_json::from_str::<MessageCentralSendSMSData>(&response_text)
-            .map_err(|e| MessageCentralError {
-            message: format!(
-                "Failed to parse send SMS response: {}. Raw response: {}",
-                e, response_text
-            ),
+        let send_sms_response =
+            serde_json::from_str::<MessageCentralSendSMSResponse>(&response_text).map_err(|e| {
+                MessageCentralError {
+                    message: format!(
+                        "Failed to parse send SMS response: {}. Raw response: {}",
+                        e, response_text
+                    ),
+                }
+            })?;
+        Ok(send_sms_response.data)
+    }
+}
+
+#[async_trait]
+impl SmsSender for MessageCentralClient {
+    fn provider(&self) -> &'static str {
+        "message_central"
+    }
+
+    async fn send_sms(&self, to: &str, body: &str) -> Result<SmsReceipt, SmsError> {
+        // MessageCentral takes the national number and a separate country code
+        let mobile_number = to.strip_prefix("+1").ok_or_else(|| SmsError {
+            message: format!("MessageCentral only supports +1 numbers, got {}", to),
+            permanent: true,
+        })?;
+
+        let token = self.generate_token().await.map_err(|e| SmsError {
+            message: e.to_string(),
+            permanent: false,
         })?;
-        Ok(send_sms_response)
+
+        let data = self
+            .send_sms(token, mobile_number.to_string(), body.to_string())
+            .await
+            .map_err(|e| SmsError {
+                message: e.to_string(),
+                permanent: false,
+            })?;
+
+        Ok(SmsReceipt {
+            message_id: Some(data.verification_id.to_string()),
+        })
+    }
+}
+
+#[async_trait]
+impl OtpProvider for MessageCentralClient {
+    fn provider(&self) -> &'static str {
+        "message_central"
+    }
+
+    async fn send_code(&self, phone_number: &str) -> Result<OtpChallenge, OtpError> {
+        let token = self.generate_token().await.map_err(|e| OtpError {
+            message: e.to_string(),
+        })?;
+
+        let data = self
+            .send_otp(
+                token.clone(),
+                "1".to_string(),
+                OTP_LENGTH as i32,
+                phone_number.to_string(),
+            )
+            .await
+            .map_err(|e| OtpError {
+                message: e.to_string(),
+            })?;
+
+        Ok(OtpChallenge {
+            verification_id: data.verification_id,
+            provider_token: token,
+            validity_seconds: data.timeout.parse::<f64>().unwrap_or(600.0) as i64,
+        })
+    }
+
+    async fn check_code(
+        &self,
+        verification_id: &str,
+        provider_token: &str,
+        code: &str,
+    ) -> Result<(), OtpError> {
+        self.verify_otp(
+            provider_token.to_string(),
+            verification_id.to_string(),
+            code.to_string(),
+        )
+        .await
+        .map_err(|e| OtpError {
+            message: e.to_string(),
+        })
     }
 }
@@ -0,0 +1,96 @@
+/// Furthest ahead a reminder can be scheduled
+pub const MAX_NOTICE_DAYS: i64 = 60;
+/// Most offsets a user can have, so digests stay occasional
+pub const MAX_NOTICE_OFFSETS: usize = 5;
+
+pub const DEFAULT_NOTICE_OFFSETS: [i64; 3] = [7, 1, 0];
+
+/// Parses a list like "14, 3, 0" (commas and/or spaces) into distinct offsets,
+/// furthest first.
+pub fn parse_notice_offsets(input: &str) -> Result<Vec<i64>, String> {
+    let mut offsets = Vec::new();
+    for part in input
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+    {
+        let offset: i64 = part
+            .parse()
+            .map_err(|_| format!("'{}' is not a number of days", part))?;
+        offsets.push(offset);
+    }
+
+    normalize_notice_offsets(offsets)
+}
+
+/// Checks a list of offsets is within limits, and orders it furthest first
+/// without repeats.
+pub fn normalize_notice_offsets(mut offsets: Vec<i64>) -> Result<Vec<i64>, String> {
+    if offsets
+        .iter()
+        .any(|offset| !(0..=MAX_NOTICE_DAYS).contains(offset))
+    {
+        return Err(format!(
+            "Reminder days must be between 0 and {}",
+            MAX_NOTICE_DAYS
+        ));
+    }
+
+    offsets.sort_unstable_by(|a, b| b.cmp(a));
+    offsets.dedup();
+
+    if offsets.is_empty() {
+        return Err("Add at least one reminder day".to_string());
+    }
+    if offsets.len() > MAX_NOTICE_OFFSETS {
+        return Err(format!(
+            "You can have at most {} reminder days",
+            MAX_NOTICE_OFFSETS
+        ));
+    }
+
+    Ok(offsets)
+}
+
+/// Like `parse_notice_offsets`, but a blank input means "no override".
+pub fn parse_notice_override(input: &str) -> Result<Option<Vec<i64>>, String> {
+    if input.trim().is_empty() {
+        return Ok(None);
+    }
+    parse_notice_offsets(input).map(Some)
+}
+
+/// Storage form used in `users.notice_offsets`, e.g. "14,3,0".
+pub fn format_notice_offsets(offsets: &[i64]) -> String {
+    offsets
+        .iter()
+        .map(|offset| offset.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Form-field form, e.g. "14, 3, 0".
+pub fn display_notice_offsets(offsets: &[i64]) -> String {
+    offsets
+        .iter()
+        .map(|offset| offset.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Human form for replies, e.g. "14 days, 3 days and day-of".
+pub fn describe_notice_offsets(offsets: &[i64]) -> String {
+    let parts: Vec<String> = offsets
+        .iter()
+        .map(|offset| match offset {
+            0 => "day-of".to_string(),
+            1 => "1 day".to_string(),
+            n => format!("{} days", n),
+        })
+        .collect();
+
+    match parts.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,102 @@
+use chrono::NaiveDate;
+use sqlx::{sqlite::SqlitePool, Row};
+use std::collections::HashMap;
+
+/// What the user has said about one reminder's upcoming birthday.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OccurrenceState {
+    /// Set by DONE: no more reminders for this occurrence
+    pub done: bool,
+    /// Set by SNOOZE: no reminders before this (user-local) date
+    pub snoozed_until: Option<NaiveDate>,
+}
+
+impl OccurrenceState {
+    /// Whether the occurrence should be left out of a digest sent on `today`.
+    pub fn suppresses(&self, today: NaiveDate) -> bool {
+        self.done || self.snoozed_until.is_some_and(|until| today < until)
+    }
+}
+
+fn format_date(date: NaiveDate) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+pub async fn mark_occurrence_done(
+    pool: &SqlitePool,
+    reminder_id: i64,
+    occurrence_date: NaiveDate,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO reminder_occurrence_state (reminder_id, occurrence_date, done_at)
+         VALUES (?, ?, CURRENT_TIMESTAMP)
+         ON CONFLICT (reminder_id, occurrence_date)
+         DO UPDATE SET done_at = CURRENT_TIMESTAMP, updated_at = CURRENT_TIMESTAMP",
+    )
+    .bind(reminder_id)
+    .bind(format_date(occurrence_date))
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn snooze_occurrence(
+    pool: &SqlitePool,
+    reminder_id: i64,
+    occurrence_date: NaiveDate,
+    snoozed_until: NaiveDate,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO reminder_occurrence_state (reminder_id, occurrence_date, snoozed_until)
+         VALUES (?, ?, ?)
+         ON CONFLICT (reminder_id, occurrence_date)
+         DO UPDATE SET snoozed_until = excluded.snoozed_until, updated_at = CURRENT_TIMESTAMP",
+    )
+    .bind(reminder_id)
+    .bind(format_date(occurrence_date))
+    .bind(format_date(snoozed_until))
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Every occurrence state for the user's reminders, keyed by
+/// (reminder_id, occurrence_date).
+pub async fn get_occurrence_states_by_user_id(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> Result<HashMap<(i64, NaiveDate), OccurrenceState>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT ros.reminder_id, ros.occurrence_date, ros.done_at, ros.snoozed_until
+         FROM reminder_occurrence_state ros
+         JOIN reminders r ON r.id = ros.reminder_id
+         WHERE r.user_id = ?",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let states = rows
+        .into_iter()
+        .filter_map(|row| {
+            let occurrence_date: String = row.get("occurrence_date");
+            let done_at: Option<String> = row.get("done_at");
+            let snoozed_until: Option<String> = row.get("snoozed_until");
+            Some((
+                (
+                    row.get("reminder_id"),
+                    NaiveDate::parse_from_str(&occurrence_date, "%Y-%m-%d").ok()?,
+                ),
+                OccurrenceState {
+                    done: done_at.is_some(),
+                    snoozed_until: snoozed_until
+                        .and_then(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok()),
+                },
+            ))
+        })
+        .collect();
+
+    Ok(states)
+}
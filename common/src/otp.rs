@@ -0,0 +1,325 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use log::info;
+use rand::{Rng, RngCore};
+use sha2::Sha256;
+use sqlx::sqlite::SqlitePool;
+use std::env;
+use std::sync::Arc;
+
+use crate::message_central::MessageCentralClient;
+use crate::sealing::{derive_key, secrets_from_env};
+use crate::sms::SmsSender;
+use crate::{delete_expired, format_db_timestamp};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Digits in a login code
+pub const OTP_LENGTH: u32 = 6;
+
+/// How long a locally issued code stays valid
+pub const LOCAL_OTP_VALIDITY_MINUTES: i64 = 10;
+
+/// Wrong guesses allowed against one locally issued code
+pub const MAX_LOCAL_OTP_ATTEMPTS: i64 = 5;
+
+/// HKDF info string for the key locally issued codes are hashed under
+const CODE_KEY_INFO: &[u8] = b"hbd-bot v1 local otp hmac key";
+
+/// Keys the hashes of locally issued codes. New codes are hashed under the
+/// current secret; codes issued under a previous one still check out, so
+/// rotating `JWT_SECRET` doesn't void codes already texted.
+#[derive(Clone)]
+pub struct OtpKeys {
+    current: [u8; 32],
+    previous: Vec<[u8; 32]>,
+}
+
+impl OtpKeys {
+    pub fn new(current_secret: &str, previous_secrets: &[String]) -> Self {
+        OtpKeys {
+            current: derive_key(current_secret, CODE_KEY_INFO),
+            previous: previous_secrets
+                .iter()
+                .map(|secret| derive_key(secret, CODE_KEY_INFO))
+                .collect(),
+        }
+    }
+
+    /// Keys from `sealing::secrets_from_env`.
+    pub fn from_env() -> Result<Self, String> {
+        let (current, previous) = secrets_from_env()?;
+        Ok(OtpKeys::new(&current, &previous))
+    }
+
+    fn all(&self) -> impl Iterator<Item = &[u8; 32]> {
+        std::iter::once(&self.current).chain(&self.previous)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OtpError {
+    pub message: String,
+}
+
+impl std::fmt::Display for OtpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "OTP Error: {}", self.message)
+    }
+}
+
+impl std::error::Error for OtpError {}
+
+/// A code on its way to the user, and what's needed to check it later.
+#[derive(Debug, Clone)]
+pub struct OtpChallenge {
+    pub verification_id: String,
+    /// Credential the provider needs back when checking the code; callers
+    /// should store it encrypted. Empty for providers that don't need one.
+    pub provider_token: String,
+    pub validity_seconds: i64,
+}
+
+#[async_trait]
+pub trait OtpProvider: Send + Sync {
+    /// Short backend name, e.g. "message_central" or "local"
+    fn provider(&self) -> &'static str;
+
+    /// Texts a code to `phone_number` (10 digits, US)
+    async fn send_code(&self, phone_number: &str) -> Result<OtpChallenge, OtpError>;
+
+    /// Ok if `code` is the one sent for `verification_id`
+    async fn check_code(
+        &self,
+        verification_id: &str,
+        provider_token: &str,
+        code: &str,
+    ) -> Result<(), OtpError>;
+}
+
+/// Which OTP provider the web server should use, read from `OTP_BACKEND`.
+#[derive(Clone, Debug)]
+pub enum OtpBackend {
+    MessageCentral {
+        customer_id: String,
+        email: String,
+        password_b64: String,
+    },
+    /// Codes generated here and sent through the configured SMS backend
+    Local,
+}
+
+impl OtpBackend {
+    /// Reads the backend from the environment. Defaults to MessageCentral so
+    /// existing deployments keep working without setting `OTP_BACKEND`.
+    pub fn from_env() -> Result<Self, String> {
+        let backend = env::var("OTP_BACKEND").unwrap_or_else(|_| "message_central".to_string());
+
+        match backend.as_str() {
+            "message_central" => Ok(OtpBackend::MessageCentral {
+                customer_id: env::var("MESSAGE_CENTRAL_CUSTOMER_ID")
+                    .map_err(|_| "MESSAGE_CENTRAL_CUSTOMER_ID environment variable not set")?,
+                email: env::var("MESSAGE_CENTRAL_EMAIL")
+                    .map_err(|_| "MESSAGE_CENTRAL_EMAIL environment variable not set")?,
+                password_b64: env::var("MESSAGE_CENTRAL_PASSWORD_B64")
+                    .map_err(|_| "MESSAGE_CENTRAL_PASSWORD_B64 environment variable not set")?,
+            }),
+            "local" => Ok(OtpBackend::Local),
+            other => Err(format!(
+                "Unknown OTP_BACKEND '{}' (expected message_central or local)",
+                other
+            )),
+        }
+    }
+
+    /// `keys` hash locally issued codes.
+    pub fn build(
+        &self,
+        db: SqlitePool,
+        sms: Arc<dyn SmsSender>,
+        keys: &OtpKeys,
+    ) -> Arc<dyn OtpProvider> {
+        match self {
+            OtpBackend::MessageCentral {
+                customer_id,
+                email,
+                password_b64,
+            } => Arc::new(MessageCentralClient::new(
+                customer_id.clone(),
+                email.clone(),
+                password_b64.clone(),
+            )),
+            OtpBackend::Local => Arc::new(LocalOtpProvider::new(db, sms, keys.clone())),
+        }
+    }
+}
+
+/// A random zero-padded code, e.g. "042917"
+pub fn generate_code() -> String {
+    let code = rand::thread_rng().gen_range(0..10u32.pow(OTP_LENGTH));
+    format!("{:0width$}", code, width = OTP_LENGTH as usize)
+}
+
+/// Binding the code to its id keeps one stored hash from matching another
+/// row's code.
+fn code_mac(key: &[u8; 32], verification_id: &str, code: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(verification_id.as_bytes());
+    mac.update(b":");
+    mac.update(code.as_bytes());
+    mac
+}
+
+/// Stores a hashed code for `phone_number` and returns its verification id.
+pub async fn store_local_code(
+    pool: &SqlitePool,
+    keys: &OtpKeys,
+    phone_number: &str,
+    code: &str,
+    current_utc: DateTime<Utc>,
+) -> Result<String, sqlx::Error> {
+    delete_expired(pool, "local_otp_codes", current_utc).await?;
+
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let verification_id = hex::encode(bytes);
+    let code_hash = hex::encode(
+        code_mac(&keys.current, &verification_id, code)
+            .finalize()
+            .into_bytes(),
+    );
+
+    sqlx::query(
+        "INSERT INTO local_otp_codes (id, phone_number, code_hash, expires_at) VALUES (?, ?, ?, ?)",
+    )
+    .bind(&verification_id)
+    .bind(phone_number)
+    .bind(code_hash)
+    .bind(format_db_timestamp(
+        current_utc + Duration::minutes(LOCAL_OTP_VALIDITY_MINUTES),
+    ))
+    .execute(pool)
+    .await?;
+
+    Ok(verification_id)
+}
+
+/// True if `code` matches an unused, unexpired code. Every check counts
+/// against the attempt limit, and a matching code can't be used again.
+pub async fn check_local_code(
+    pool: &SqlitePool,
+    keys: &OtpKeys,
+    verification_id: &str,
+    code: &str,
+    current_utc: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let code_hash: Vec<String> = sqlx::query_scalar(
+        "UPDATE local_otp_codes SET attempts = attempts + 1
+         WHERE id = ? AND used_at IS NULL AND expires_at > ? AND attempts < ?
+         RETURNING code_hash",
+    )
+    .bind(verification_id)
+    .bind(format_db_timestamp(current_utc))
+    .bind(MAX_LOCAL_OTP_ATTEMPTS)
+    .fetch_all(pool)
+    .await?;
+
+    let Some(expected) = code_hash
+        .into_iter()
+        .next()
+        .and_then(|hash| hex::decode(hash).ok())
+    else {
+        return Ok(false);
+    };
+    if !keys.all().any(|key| {
+        code_mac(key, verification_id, code.trim())
+            .verify_slice(&expected)
+            .is_ok()
+    }) {
+        return Ok(false);
+    }
+
+    // Only one of two concurrent checks with the right code gets to use it
+    let result = sqlx::query(
+        "UPDATE local_otp_codes SET used_at = CURRENT_TIMESTAMP WHERE id = ? AND used_at IS NULL",
+    )
+    .bind(verification_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Generates codes here and texts them through the configured SMS sender,
+/// for environments that can't reach MessageCentral.
+pub struct LocalOtpProvider {
+    db: SqlitePool,
+    sms: Arc<dyn SmsSender>,
+    keys: OtpKeys,
+}
+
+impl LocalOtpProvider {
+    pub fn new(db: SqlitePool, sms: Arc<dyn SmsSender>, keys: OtpKeys) -> Self {
+        Self { db, sms, keys }
+    }
+}
+
+#[async_trait]
+impl OtpProvider for LocalOtpProvider {
+    fn provider(&self) -> &'static str {
+        "local"
+    }
+
+    async fn send_code(&self, phone_number: &str) -> Result<OtpChallenge, OtpError> {
+        let code = generate_code();
+        let verification_id =
+            store_local_code(&self.db, &self.keys, phone_number, &code, Utc::now())
+                .await
+                .map_err(|e| OtpError {
+                    message: format!("Failed to store code: {}", e),
+                })?;
+
+        let body = format!(
+            "Your HBD Bot code is {}. It expires in {} minutes.",
+            code, LOCAL_OTP_VALIDITY_MINUTES
+        );
+        self.sms
+            .send_sms(&format!("+1{}", phone_number), &body)
+            .await
+            .map_err(|e| OtpError {
+                message: e.to_string(),
+            })?;
+        info!(
+            "Sent local OTP {} via {}",
+            verification_id,
+            self.sms.provider()
+        );
+
+        Ok(OtpChallenge {
+            verification_id,
+            provider_token: String::new(),
+            validity_seconds: LOCAL_OTP_VALIDITY_MINUTES * 60,
+        })
+    }
+
+    async fn check_code(
+        &self,
+        verification_id: &str,
+        _provider_token: &str,
+        code: &str,
+    ) -> Result<(), OtpError> {
+        let matched = check_local_code(&self.db, &self.keys, verification_id, code, Utc::now())
+            .await
+            .map_err(|e| OtpError {
+                message: format!("Failed to check code: {}", e),
+            })?;
+
+        match matched {
+            true => Ok(()),
+            false => Err(OtpError {
+                message: "OTP verification failed".to_string(),
+            }),
+        }
+    }
+}
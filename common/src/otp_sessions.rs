@@ -0,0 +1,149 @@
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use sqlx::{
+    sqlite::{SqlitePool, SqliteRow},
+    Row,
+};
+
+use crate::{delete_expired, format_db_timestamp};
+
+/// Wrong codes allowed per session before the user has to request a new one
+pub const MAX_OTP_ATTEMPTS: i64 = 5;
+
+/// What a code was sent for. A session only completes the flow it was
+/// started by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtpPurpose {
+    Login,
+    DeleteAccount,
+}
+
+impl OtpPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OtpPurpose::Login => "login",
+            OtpPurpose::DeleteAccount => "delete_account",
+        }
+    }
+}
+
+pub struct DbOtpSession {
+    pub id: String,
+    /// The number the code was sent to; the only phone a session vouches for
+    pub phone_number: String,
+    pub verification_id: String,
+    /// Encrypted by the caller before storing
+    pub provider_token: String,
+    /// Codes checked so far, including the current one
+    pub attempts: i64,
+    pub expires_at: String,
+}
+
+const OTP_SESSION_COLUMNS: &str =
+    "id, phone_number, verification_id, provider_token, attempts, expires_at";
+
+fn otp_session_from_row(row: &SqliteRow) -> DbOtpSession {
+    DbOtpSession {
+        id: row.get("id"),
+        phone_number: row.get("phone_number"),
+        verification_id: row.get("verification_id"),
+        provider_token: row.get("provider_token"),
+        attempts: row.get("attempts"),
+        expires_at: row.get("expires_at"),
+    }
+}
+
+/// Outcome of presenting a session for a code check
+pub enum OtpAttempt {
+    /// Check the code against this session's phone and verification
+    Allowed(DbOtpSession),
+    /// No such session for this purpose, or it has expired
+    Expired,
+    TooManyAttempts,
+}
+
+/// Records a code sent to `phone_number` and returns the session id to hand
+/// to the browser.
+pub async fn create_otp_session(
+    pool: &SqlitePool,
+    purpose: OtpPurpose,
+    phone_number: &str,
+    verification_id: &str,
+    provider_token: &str,
+    expires_at: DateTime<Utc>,
+    current_utc: DateTime<Utc>,
+) -> Result<String, sqlx::Error> {
+    delete_expired(pool, "otp_sessions", current_utc).await?;
+
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let session_id = hex::encode(bytes);
+
+    sqlx::query(
+        "INSERT INTO otp_sessions (id, purpose, phone_number, verification_id, provider_token, expires_at)
+         VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&session_id)
+    .bind(purpose.as_str())
+    .bind(phone_number)
+    .bind(verification_id)
+    .bind(provider_token)
+    .bind(format_db_timestamp(expires_at))
+    .execute(pool)
+    .await?;
+
+    Ok(session_id)
+}
+
+/// Counts an attempt against the session before the code is checked, so
+/// guesses are limited even if checking fails part way.
+pub async fn begin_otp_attempt(
+    pool: &SqlitePool,
+    session_id: &str,
+    purpose: OtpPurpose,
+    current_utc: DateTime<Utc>,
+) -> Result<OtpAttempt, sqlx::Error> {
+    let now = format_db_timestamp(current_utc);
+    let row = sqlx::query(&format!(
+        "UPDATE otp_sessions SET attempts = attempts + 1
+         WHERE id = ? AND purpose = ? AND expires_at > ? AND attempts < ?
+         RETURNING {}",
+        OTP_SESSION_COLUMNS
+    ))
+    .bind(session_id)
+    .bind(purpose.as_str())
+    .bind(&now)
+    .bind(MAX_OTP_ATTEMPTS)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .next();
+
+    if let Some(row) = row {
+        return Ok(OtpAttempt::Allowed(otp_session_from_row(&row)));
+    }
+
+    let used_up: Option<i64> = sqlx::query_scalar(
+        "SELECT attempts FROM otp_sessions WHERE id = ? AND purpose = ? AND expires_at > ?",
+    )
+    .bind(session_id)
+    .bind(purpose.as_str())
+    .bind(&now)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(match used_up {
+        Some(_) => OtpAttempt::TooManyAttempts,
+        None => OtpAttempt::Expired,
+    })
+}
+
+/// Ends a session, once its code has been accepted or it's no longer wanted.
+pub async fn delete_otp_session(pool: &SqlitePool, session_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM otp_sessions WHERE id = ?")
+        .bind(session_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
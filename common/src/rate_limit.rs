@@ -0,0 +1,324 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{sqlite::SqlitePool, Sqlite, Transaction};
+use std::env;
+
+/// A bucket holding up to `capacity` tokens, refilled continuously at
+/// `per_hour` tokens an hour. Each request takes one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenBucket {
+    pub capacity: f64,
+    pub per_hour: f64,
+}
+
+impl TokenBucket {
+    fn per_ms(&self) -> f64 {
+        self.per_hour / 3_600_000.0
+    }
+
+    /// How long an empty bucket takes to fill back up
+    fn refill_ms(&self) -> i64 {
+        (self.capacity / self.per_ms()).ceil() as i64
+    }
+}
+
+/// Limits on texting login codes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoginLimits {
+    pub per_phone: TokenBucket,
+    pub per_ip: TokenBucket,
+    pub global: TokenBucket,
+    /// Codes texted per UTC day, across everyone
+    pub daily_budget: i64,
+    /// Country calling codes we'll text. Users are stored as 10-digit US
+    /// numbers and texted at +1, so this is always ["1"].
+    pub country_codes: Vec<String>,
+}
+
+impl Default for LoginLimits {
+    fn default() -> Self {
+        LoginLimits {
+            per_phone: TokenBucket {
+                capacity: 3.0,
+                per_hour: 5.0,
+            },
+            per_ip: TokenBucket {
+                capacity: 10.0,
+                per_hour: 20.0,
+            },
+            global: TokenBucket {
+                capacity: 60.0,
+                per_hour: 300.0,
+            },
+            daily_budget: 1000,
+            country_codes: vec!["1".to_string()],
+        }
+    }
+}
+
+impl LoginLimits {
+    /// The defaults, with the daily budget taken from `LOGIN_DAILY_OTP_BUDGET`
+    /// when set. Login is US-only, so the country allow-list isn't
+    /// configurable.
+    pub fn from_env() -> Result<Self, String> {
+        let mut limits = LoginLimits::default();
+
+        if let Ok(budget) = env::var("LOGIN_DAILY_OTP_BUDGET") {
+            limits.daily_budget = budget
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid LOGIN_DAILY_OTP_BUDGET '{}'", budget))?;
+        }
+
+        Ok(limits)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginRefusalKind {
+    InvalidPhone,
+    CountryNotAllowed,
+    PhoneThrottled,
+    IpThrottled,
+    GlobalThrottled,
+    DailyBudgetExhausted,
+}
+
+/// Why a login code wasn't sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginRefusal {
+    pub kind: LoginRefusalKind,
+    /// When it's worth trying again, for throttling
+    pub retry_after_seconds: Option<i64>,
+}
+
+impl LoginRefusal {
+    fn new(kind: LoginRefusalKind) -> Self {
+        LoginRefusal {
+            kind,
+            retry_after_seconds: None,
+        }
+    }
+
+    /// Machine-readable, e.g. "phone_throttled"
+    pub fn code(&self) -> &'static str {
+        match self.kind {
+            LoginRefusalKind::InvalidPhone => "invalid_phone",
+            LoginRefusalKind::CountryNotAllowed => "country_not_allowed",
+            LoginRefusalKind::PhoneThrottled => "phone_throttled",
+            LoginRefusalKind::IpThrottled => "ip_throttled",
+            LoginRefusalKind::GlobalThrottled => "global_throttled",
+            LoginRefusalKind::DailyBudgetExhausted => "daily_budget_exhausted",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        let wait = match self.retry_after_seconds {
+            Some(seconds) if seconds > 60 => format!(" in {} minutes", (seconds + 59) / 60),
+            Some(_) => " in a minute".to_string(),
+            None => " later".to_string(),
+        };
+
+        match self.kind {
+            LoginRefusalKind::InvalidPhone => "Please enter a 10-digit phone number".to_string(),
+            LoginRefusalKind::CountryNotAllowed => {
+                "We can't text numbers in that country".to_string()
+            }
+            LoginRefusalKind::PhoneThrottled => {
+                format!("Too many codes sent to this number. Try again{}.", wait)
+            }
+            LoginRefusalKind::IpThrottled => {
+                format!("Too many login attempts. Try again{}.", wait)
+            }
+            LoginRefusalKind::GlobalThrottled | LoginRefusalKind::DailyBudgetExhausted => {
+                format!(
+                    "We're getting a lot of logins right now. Try again{}.",
+                    wait
+                )
+            }
+        }
+    }
+}
+
+/// Turns the submitted phone into the 10-digit form users are stored under.
+/// Accepts "5551234567", "15551234567" and E.164 like "+15551234567";
+/// numbers outside an allowed country are refused. The rest of the app only
+/// knows US numbers, so anything but +1 is refused as invalid for now.
+pub fn normalize_login_phone(
+    input: &str,
+    country_codes: &[String],
+) -> Result<String, LoginRefusal> {
+    let input = input.trim();
+    let digits: String = input.chars().filter(|c| c.is_ascii_digit()).collect();
+    if digits.is_empty() {
+        return Err(LoginRefusal::new(LoginRefusalKind::InvalidPhone));
+    }
+
+    // Without a "+" only the US forms are understood
+    let e164 = if input.starts_with('+') {
+        digits
+    } else if digits.len() == 10 {
+        format!("1{}", digits)
+    } else if digits.len() == 11 && digits.starts_with('1') {
+        digits
+    } else {
+        return Err(LoginRefusal::new(LoginRefusalKind::InvalidPhone));
+    };
+
+    if !country_codes
+        .iter()
+        .any(|code| e164.starts_with(code.as_str()))
+    {
+        return Err(LoginRefusal::new(LoginRefusalKind::CountryNotAllowed));
+    }
+
+    match e164.strip_prefix('1') {
+        Some(national) if national.len() == 10 => Ok(national.to_string()),
+        _ => Err(LoginRefusal::new(LoginRefusalKind::InvalidPhone)),
+    }
+}
+
+/// Takes a token from the bucket at `key`, creating it full. Returns how long
+/// until a token is available if the bucket is empty.
+async fn take_token(
+    tx: &mut Transaction<'_, Sqlite>,
+    key: &str,
+    bucket: TokenBucket,
+    now_ms: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    let taken = sqlx::query(
+        "INSERT INTO rate_limit_buckets (key, tokens, updated_ms) VALUES (?1, ?2 - 1, ?3)
+         ON CONFLICT(key) DO UPDATE SET
+             tokens = MIN(?2, tokens + MAX(0, ?3 - updated_ms) * ?4) - 1,
+             updated_ms = ?3
+         WHERE MIN(?2, tokens + MAX(0, ?3 - updated_ms) * ?4) >= 1",
+    )
+    .bind(key)
+    .bind(bucket.capacity)
+    .bind(now_ms)
+    .bind(bucket.per_ms())
+    .execute(&mut **tx)
+    .await?
+    .rows_affected()
+        > 0;
+
+    if taken {
+        return Ok(None);
+    }
+
+    let (tokens, updated_ms): (f64, i64) =
+        sqlx::query_as("SELECT tokens, updated_ms FROM rate_limit_buckets WHERE key = ?")
+            .bind(key)
+            .fetch_one(&mut **tx)
+            .await?;
+    let available = (tokens + (now_ms - updated_ms).max(0) as f64 * bucket.per_ms()).min(1.0);
+    let wait_ms = ((1.0 - available) / bucket.per_ms()).round() as i64;
+    Ok(Some((wait_ms + 999) / 1000))
+}
+
+/// Counts a code against today's budget, unless it's used up.
+async fn spend_daily_budget(
+    tx: &mut Transaction<'_, Sqlite>,
+    budget: i64,
+    current_utc: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    if budget <= 0 {
+        return Ok(false);
+    }
+
+    let spent = sqlx::query(
+        "INSERT INTO otp_daily_counts (day, sent) VALUES (?, 1)
+         ON CONFLICT(day) DO UPDATE SET sent = sent + 1 WHERE sent < ?",
+    )
+    .bind(current_utc.format("%Y-%m-%d").to_string())
+    .bind(budget)
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+
+    Ok(spent > 0)
+}
+
+/// Deletes buckets idle long enough to have refilled, which are no different
+/// from missing ones, and the counts of days before today.
+async fn prune_login_limits(
+    pool: &SqlitePool,
+    limits: &LoginLimits,
+    current_utc: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let refill_ms = [limits.per_phone, limits.per_ip, limits.global]
+        .iter()
+        .map(TokenBucket::refill_ms)
+        .max()
+        .unwrap_or(0);
+    sqlx::query("DELETE FROM rate_limit_buckets WHERE updated_ms <= ?")
+        .bind(current_utc.timestamp_millis() - refill_ms)
+        .execute(pool)
+        .await?;
+
+    sqlx::query("DELETE FROM otp_daily_counts WHERE day < ?")
+        .bind(current_utc.format("%Y-%m-%d").to_string())
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Decides whether we may text a login or account-deletion code to
+/// `phone_number` (already normalized) for a request from `ip`, if known.
+/// Either every limit is charged or, when one refuses, none are.
+pub async fn check_login_limits(
+    pool: &SqlitePool,
+    limits: &LoginLimits,
+    phone_number: &str,
+    ip: Option<&str>,
+    current_utc: DateTime<Utc>,
+) -> Result<Option<LoginRefusal>, sqlx::Error> {
+    prune_login_limits(pool, limits, current_utc).await?;
+
+    let now_ms = current_utc.timestamp_millis();
+    let mut tx = pool.begin().await?;
+
+    let mut buckets = vec![(
+        format!("login:phone:{}", phone_number),
+        limits.per_phone,
+        LoginRefusalKind::PhoneThrottled,
+    )];
+    if let Some(ip) = ip {
+        buckets.push((
+            format!("login:ip:{}", ip),
+            limits.per_ip,
+            LoginRefusalKind::IpThrottled,
+        ));
+    }
+    buckets.push((
+        "login:global".to_string(),
+        limits.global,
+        LoginRefusalKind::GlobalThrottled,
+    ));
+
+    for (key, bucket, kind) in buckets {
+        if let Some(retry_after_seconds) = take_token(&mut tx, &key, bucket, now_ms).await? {
+            tx.rollback().await?;
+            return Ok(Some(LoginRefusal {
+                kind,
+                retry_after_seconds: Some(retry_after_seconds),
+            }));
+        }
+    }
+
+    if !spend_daily_budget(&mut tx, limits.daily_budget, current_utc).await? {
+        tx.rollback().await?;
+        let tomorrow = (current_utc + Duration::days(1))
+            .date_naive()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc();
+        return Ok(Some(LoginRefusal {
+            kind: LoginRefusalKind::DailyBudgetExhausted,
+            retry_after_seconds: Some((tomorrow - current_utc).num_seconds()),
+        }));
+    }
+
+    tx.commit().await?;
+    Ok(None)
+}
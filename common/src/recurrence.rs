@@ -0,0 +1,559 @@
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+
+/// Longest INTERVAL we accept
+pub const MAX_INTERVAL: u32 = 1000;
+
+/// Upper bound on recurrence periods walked looking for the next occurrence,
+/// so a rule that never matches (e.g. BYMONTHDAY=31;BYMONTH=2) can't spin.
+const MAX_PERIODS: i64 = 50_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Yearly,
+    Monthly,
+    Weekly,
+    Daily,
+}
+
+impl Frequency {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Frequency::Yearly => "YEARLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Daily => "DAILY",
+        }
+    }
+}
+
+/// A BYDAY entry: a weekday, optionally the nth (or nth-from-last when
+/// negative) of the month or year, like `-1FR`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByDay {
+    pub nth: Option<i32>,
+    pub weekday: Weekday,
+}
+
+/// An RFC 5545 recurrence rule, stored in `reminders.rrule`. The reminder's
+/// date is the rule's DTSTART. Dates are floating: they're expanded against
+/// the user's local "today", so there's no time or zone in the rule.
+///
+/// Supports FREQ (YEARLY, MONTHLY, WEEKLY, DAILY), INTERVAL, BYMONTH,
+/// BYMONTHDAY, BYDAY, COUNT and UNTIL. Weeks start on Monday.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    pub freq: Frequency,
+    pub interval: u32,
+    pub by_month: Vec<u32>,
+    pub by_month_day: Vec<i32>,
+    pub by_day: Vec<ByDay>,
+    pub count: Option<u32>,
+    pub until: Option<NaiveDate>,
+}
+
+impl Recurrence {
+    pub fn new(freq: Frequency) -> Self {
+        Self {
+            freq,
+            interval: 1,
+            by_month: Vec::new(),
+            by_month_day: Vec::new(),
+            by_day: Vec::new(),
+            count: None,
+            until: None,
+        }
+    }
+
+    /// Parses a rule like `FREQ=MONTHLY;INTERVAL=6`, with or without the
+    /// `RRULE:` prefix.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim();
+        let input = input
+            .strip_prefix("RRULE:")
+            .or_else(|| input.strip_prefix("rrule:"))
+            .unwrap_or(input);
+
+        let mut freq = None;
+        let mut rule = Recurrence::new(Frequency::Yearly);
+        for part in input.split(';').filter(|part| !part.trim().is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("Expected NAME=VALUE, got \"{}\"", part))?;
+            let value = value.trim().to_ascii_uppercase();
+            match key.trim().to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match value.as_str() {
+                        "YEARLY" => Frequency::Yearly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "WEEKLY" => Frequency::Weekly,
+                        "DAILY" => Frequency::Daily,
+                        _ => return Err(format!("Unsupported FREQ \"{}\"", value)),
+                    })
+                }
+                "INTERVAL" => {
+                    rule.interval = value
+                        .parse()
+                        .ok()
+                        .filter(|interval| (1..=MAX_INTERVAL).contains(interval))
+                        .ok_or_else(|| format!("INTERVAL must be between 1 and {}", MAX_INTERVAL))?
+                }
+                "BYMONTH" => {
+                    rule.by_month = parse_list(&value, |month| {
+                        month.parse().ok().filter(|month| (1..=12).contains(month))
+                    })
+                    .ok_or("BYMONTH must list months from 1 to 12")?
+                }
+                "BYMONTHDAY" => {
+                    rule.by_month_day = parse_list(&value, |day| {
+                        day.parse()
+                            .ok()
+                            .filter(|day: &i32| *day != 0 && (-31..=31).contains(day))
+                    })
+                    .ok_or("BYMONTHDAY must list days from 1 to 31 or -31 to -1")?
+                }
+                "BYDAY" => {
+                    rule.by_day = parse_list(&value, parse_by_day)
+                        .ok_or("BYDAY must list days like MO, 2TU or -1FR")?
+                }
+                "COUNT" => {
+                    rule.count = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|count| *count > 0)
+                            .ok_or("COUNT must be a positive number")?,
+                    )
+                }
+                "UNTIL" => {
+                    // A date, or a date-time of which only the date matters
+                    let date = value.split('T').next().unwrap_or_default();
+                    rule.until = Some(
+                        NaiveDate::parse_from_str(date, "%Y%m%d")
+                            .map_err(|_| "UNTIL must be a date like 20301231".to_string())?,
+                    )
+                }
+                // Monday is the only week start we support, and the default
+                "WKST" if value == "MO" => {}
+                other => return Err(format!("Unsupported rule part \"{}\"", other)),
+            }
+        }
+
+        rule.freq = freq.ok_or("The rule needs a FREQ")?;
+        if rule.count.is_some() && rule.until.is_some() {
+            return Err("Use COUNT or UNTIL, not both".to_string());
+        }
+        let has_nth = rule.by_day.iter().any(|by_day| by_day.nth.is_some());
+        if has_nth && !matches!(rule.freq, Frequency::Yearly | Frequency::Monthly) {
+            return Err("Numbered BYDAY entries need FREQ=MONTHLY or FREQ=YEARLY".to_string());
+        }
+
+        Ok(rule)
+    }
+
+    /// The first occurrence on or after `from`, counting occurrences from
+    /// `dtstart`. `None` once COUNT or UNTIL has run out.
+    pub fn next_on_or_after(&self, dtstart: NaiveDate, from: NaiveDate) -> Option<NaiveDate> {
+        // COUNT needs every earlier occurrence counted, so only skip ahead
+        // without one
+        let first_period = match self.count {
+            Some(_) => 0,
+            None => (self.periods_between(dtstart, from) / self.interval as i64 - 1).max(0),
+        };
+
+        let mut emitted = 0;
+        for period in first_period..first_period + MAX_PERIODS {
+            let dates = self.period_dates(dtstart, period)?;
+            for date in dates {
+                if date < dtstart {
+                    continue;
+                }
+                if self.until.is_some_and(|until| date > until) {
+                    return None;
+                }
+                emitted += 1;
+                if self.count.is_some_and(|count| emitted > count) {
+                    return None;
+                }
+                if date >= from {
+                    return Some(date);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Whole periods (years, months, weeks or days) from `dtstart`'s period
+    /// to `from`'s.
+    fn periods_between(&self, dtstart: NaiveDate, from: NaiveDate) -> i64 {
+        match self.freq {
+            Frequency::Yearly => (from.year() - dtstart.year()) as i64,
+            Frequency::Monthly => month_index(from) - month_index(dtstart),
+            Frequency::Weekly => (week_start(from) - week_start(dtstart)).num_days() / 7,
+            Frequency::Daily => (from - dtstart).num_days(),
+        }
+    }
+
+    /// Candidate dates in the `period`th interval after `dtstart`, in order.
+    /// `None` once the period runs past the dates chrono can represent.
+    fn period_dates(&self, dtstart: NaiveDate, period: i64) -> Option<Vec<NaiveDate>> {
+        let step = period * self.interval as i64;
+        let mut dates = match self.freq {
+            Frequency::Yearly => {
+                let year = i32::try_from(dtstart.year() as i64 + step).ok()?;
+                NaiveDate::from_ymd_opt(year, 1, 1)?;
+                self.year_dates(year, dtstart)
+            }
+            Frequency::Monthly => {
+                let index = month_index(dtstart) + step;
+                let year = i32::try_from(index.div_euclid(12)).ok()?;
+                let month = index.rem_euclid(12) as u32 + 1;
+                NaiveDate::from_ymd_opt(year, month, 1)?;
+                if self.by_month.is_empty() || self.by_month.contains(&month) {
+                    self.month_dates(year, month, dtstart)
+                } else {
+                    Vec::new()
+                }
+            }
+            Frequency::Weekly => {
+                let start = week_start(dtstart).checked_add_signed(Duration::weeks(step))?;
+                let weekdays: Vec<Weekday> = if self.by_day.is_empty() {
+                    vec![dtstart.weekday()]
+                } else {
+                    self.by_day.iter().map(|by_day| by_day.weekday).collect()
+                };
+                (0..7)
+                    .filter_map(|offset| start.checked_add_signed(Duration::days(offset)))
+                    .filter(|date| weekdays.contains(&date.weekday()))
+                    .filter(|date| {
+                        self.by_month.is_empty() || self.by_month.contains(&date.month())
+                    })
+                    .collect()
+            }
+            Frequency::Daily => {
+                let date = dtstart.checked_add_signed(Duration::days(step))?;
+                let matches = (self.by_month.is_empty() || self.by_month.contains(&date.month()))
+                    && (self.by_month_day.is_empty()
+                        || self.by_month_day.iter().any(|day| {
+                            resolve_month_day(date.year(), date.month(), *day) == Some(date)
+                        }))
+                    && (self.by_day.is_empty()
+                        || self
+                            .by_day
+                            .iter()
+                            .any(|by_day| by_day.weekday == date.weekday()));
+                if matches {
+                    vec![date]
+                } else {
+                    Vec::new()
+                }
+            }
+        };
+
+        dates.sort();
+        dates.dedup();
+        Some(dates)
+    }
+
+    fn year_dates(&self, year: i32, dtstart: NaiveDate) -> Vec<NaiveDate> {
+        if !self.by_month.is_empty() {
+            return self
+                .by_month
+                .iter()
+                .flat_map(|month| self.month_dates(year, *month, dtstart))
+                .collect();
+        }
+        if !self.by_month_day.is_empty() {
+            return (1..=12)
+                .flat_map(|month| self.month_dates(year, month, dtstart))
+                .collect();
+        }
+        if !self.by_day.is_empty() {
+            // Weekdays counted across the whole year, e.g. 20MO
+            let start = NaiveDate::from_ymd_opt(year, 1, 1);
+            let end = NaiveDate::from_ymd_opt(year, 12, 31);
+            return match (start, end) {
+                (Some(start), Some(end)) => self
+                    .by_day
+                    .iter()
+                    .flat_map(|by_day| weekdays_between(start, end, *by_day))
+                    .collect(),
+                _ => Vec::new(),
+            };
+        }
+
+        self.month_dates(year, dtstart.month(), dtstart)
+    }
+
+    fn month_dates(&self, year: i32, month: u32, dtstart: NaiveDate) -> Vec<NaiveDate> {
+        if !self.by_month_day.is_empty() {
+            return self
+                .by_month_day
+                .iter()
+                .filter_map(|day| resolve_month_day(year, month, *day))
+                .filter(|date| {
+                    self.by_day.is_empty()
+                        || self
+                            .by_day
+                            .iter()
+                            .any(|by_day| by_day.weekday == date.weekday())
+                })
+                .collect();
+        }
+        if !self.by_day.is_empty() {
+            let start = NaiveDate::from_ymd_opt(year, month, 1);
+            let end = start.and_then(|start| last_day_of_month(start.year(), start.month()));
+            return match (start, end) {
+                (Some(start), Some(end)) => self
+                    .by_day
+                    .iter()
+                    .flat_map(|by_day| weekdays_between(start, end, *by_day))
+                    .collect(),
+                _ => Vec::new(),
+            };
+        }
+
+        // Months without DTSTART's day (e.g. the 31st) are skipped
+        NaiveDate::from_ymd_opt(year, month, dtstart.day())
+            .into_iter()
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FREQ={}", self.freq.as_str())?;
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if !self.by_month.is_empty() {
+            write!(f, ";BYMONTH={}", join(&self.by_month))?;
+        }
+        if !self.by_month_day.is_empty() {
+            write!(f, ";BYMONTHDAY={}", join(&self.by_month_day))?;
+        }
+        if !self.by_day.is_empty() {
+            let days: Vec<String> = self
+                .by_day
+                .iter()
+                .map(|by_day| match by_day.nth {
+                    Some(nth) => format!("{}{}", nth, weekday_code(by_day.weekday)),
+                    None => weekday_code(by_day.weekday).to_string(),
+                })
+                .collect();
+            write!(f, ";BYDAY={}", days.join(","))?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={}", count)?;
+        }
+        if let Some(until) = self.until {
+            write!(f, ";UNTIL={}", until.format("%Y%m%d"))?;
+        }
+        Ok(())
+    }
+}
+
+/// The recurrence choices offered on the add/edit forms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecurrencePreset {
+    /// Once a year on the date; no rule is stored
+    Yearly,
+    EverySixMonths,
+    Monthly,
+    /// The same numbered weekday of the month each year, e.g. 4th Thursday
+    /// of November
+    YearlyByWeekday,
+    /// The last of the date's weekday in its month each year, e.g. last
+    /// Friday of October
+    YearlyByLastWeekday,
+    /// A rule typed in by hand
+    Custom,
+}
+
+impl RecurrencePreset {
+    /// Value used by the form's select
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecurrencePreset::Yearly => "yearly",
+            RecurrencePreset::EverySixMonths => "every_6_months",
+            RecurrencePreset::Monthly => "monthly",
+            RecurrencePreset::YearlyByWeekday => "yearly_weekday",
+            RecurrencePreset::YearlyByLastWeekday => "yearly_last_weekday",
+            RecurrencePreset::Custom => "custom",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "yearly" => Some(RecurrencePreset::Yearly),
+            "every_6_months" => Some(RecurrencePreset::EverySixMonths),
+            "monthly" => Some(RecurrencePreset::Monthly),
+            "yearly_weekday" => Some(RecurrencePreset::YearlyByWeekday),
+            "yearly_last_weekday" => Some(RecurrencePreset::YearlyByLastWeekday),
+            "custom" => Some(RecurrencePreset::Custom),
+            _ => None,
+        }
+    }
+
+    /// The rule to store for this preset and the reminder's date. `custom_rule`
+    /// is only used by `Custom`.
+    pub fn to_recurrence(
+        &self,
+        date: NaiveDate,
+        custom_rule: &str,
+    ) -> Result<Option<Recurrence>, String> {
+        match self {
+            RecurrencePreset::Yearly => Ok(None),
+            RecurrencePreset::EverySixMonths => Ok(Some(Recurrence {
+                interval: 6,
+                ..Recurrence::new(Frequency::Monthly)
+            })),
+            RecurrencePreset::Monthly => Ok(Some(Recurrence::new(Frequency::Monthly))),
+            RecurrencePreset::YearlyByWeekday => {
+                Ok(Some(yearly_by_weekday(date, weekday_of_month(date))))
+            }
+            RecurrencePreset::YearlyByLastWeekday => Ok(Some(yearly_by_weekday(date, -1))),
+            RecurrencePreset::Custom => Recurrence::parse(custom_rule).map(Some),
+        }
+    }
+
+    /// The preset that would store `recurrence` for `date`, so the edit form
+    /// can preselect it.
+    pub fn for_recurrence(recurrence: Option<&Recurrence>, date: NaiveDate) -> Self {
+        let Some(recurrence) = recurrence else {
+            return RecurrencePreset::Yearly;
+        };
+        [
+            RecurrencePreset::EverySixMonths,
+            RecurrencePreset::Monthly,
+            RecurrencePreset::YearlyByWeekday,
+            RecurrencePreset::YearlyByLastWeekday,
+        ]
+        .into_iter()
+        .find(|preset| preset.to_recurrence(date, "").ok().flatten().as_ref() == Some(recurrence))
+        .unwrap_or(RecurrencePreset::Custom)
+    }
+}
+
+/// Which occurrence of its weekday `date` is in its month: 1-4, or -1 for a
+/// 5th, which only exists in some years.
+pub fn weekday_of_month(date: NaiveDate) -> i32 {
+    match date.day0() / 7 + 1 {
+        5 => -1,
+        nth => nth as i32,
+    }
+}
+
+fn yearly_by_weekday(date: NaiveDate, nth: i32) -> Recurrence {
+    Recurrence {
+        by_month: vec![date.month()],
+        by_day: vec![ByDay {
+            nth: Some(nth),
+            weekday: date.weekday(),
+        }],
+        ..Recurrence::new(Frequency::Yearly)
+    }
+}
+
+fn parse_list<T>(value: &str, parse: impl Fn(&str) -> Option<T>) -> Option<Vec<T>> {
+    value.split(',').map(|item| parse(item.trim())).collect()
+}
+
+fn parse_by_day(value: &str) -> Option<ByDay> {
+    if value.len() < 2 || !value.is_char_boundary(value.len() - 2) {
+        return None;
+    }
+    let (nth, code) = value.split_at(value.len() - 2);
+    let weekday = match code {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    };
+    let nth = match nth {
+        "" => None,
+        nth => Some(
+            nth.parse::<i32>()
+                .ok()
+                .filter(|nth| *nth != 0 && (-53..=53).contains(nth))?,
+        ),
+    };
+
+    Some(ByDay { nth, weekday })
+}
+
+fn weekday_code(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn month_index(date: NaiveDate) -> i64 {
+    date.year() as i64 * 12 + date.month0() as i64
+}
+
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+fn last_day_of_month(year: i32, month: u32) -> Option<NaiveDate> {
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    NaiveDate::from_ymd_opt(next_year, next_month, 1)?.pred_opt()
+}
+
+/// BYMONTHDAY value to a date; negative days count back from the month's end
+fn resolve_month_day(year: i32, month: u32, day: i32) -> Option<NaiveDate> {
+    if day > 0 {
+        return NaiveDate::from_ymd_opt(year, month, day as u32);
+    }
+    let last = last_day_of_month(year, month)?;
+    let date = last.checked_add_signed(Duration::days(day as i64 + 1))?;
+    (date.month() == month).then_some(date)
+}
+
+/// The `by_day` weekdays from `start` through `end`: all of them, or just the
+/// nth (from the end when negative).
+fn weekdays_between(start: NaiveDate, end: NaiveDate, by_day: ByDay) -> Vec<NaiveDate> {
+    let offset = (7 + by_day.weekday.num_days_from_monday() as i64
+        - start.weekday().num_days_from_monday() as i64)
+        % 7;
+    let first = start + Duration::days(offset);
+    let all: Vec<NaiveDate> = std::iter::successors(Some(first), |date| {
+        Some(*date + Duration::weeks(1)).filter(|next| *next <= end)
+    })
+    .filter(|date| *date <= end)
+    .collect();
+
+    match by_day.nth {
+        None => all,
+        Some(nth) if nth > 0 => all.get(nth as usize - 1).copied().into_iter().collect(),
+        Some(nth) => all
+            .len()
+            .checked_sub(nth.unsigned_abs() as usize)
+            .and_then(|index| all.get(index).copied())
+            .into_iter()
+            .collect(),
+    }
+}
@@ -0,0 +1,193 @@
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, Key, KeyInit, Nonce};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hkdf::Hkdf;
+use rand::RngCore;
+use sha2::Sha256;
+use std::env;
+
+/// Prefix of everything sealed in the current format
+const FORMAT_VERSION: &str = "v1";
+
+/// Fixed HKDF salt, so the derived keys are specific to this app
+const HKDF_SALT: &[u8] = b"hbd-bot sealing";
+
+/// HKDF info strings. Each gives an independent key from the same secret,
+/// so neither reveals the other or anything the raw secret is used for.
+const CIPHER_KEY_INFO: &[u8] = b"hbd-bot v1 aes-256-gcm key";
+const KEY_ID_INFO: &[u8] = b"hbd-bot v1 key id";
+
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SealError {
+    /// Not something we sealed, or a format we no longer read
+    Malformed,
+    /// Sealed under a secret that's neither current nor listed as previous
+    UnknownKey,
+    /// The key matched but the contents, or the purpose, didn't check out
+    Tampered,
+}
+
+impl std::fmt::Display for SealError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SealError::Malformed => write!(f, "Malformed sealed value"),
+            SealError::UnknownKey => write!(f, "Sealed with an unknown key"),
+            SealError::Tampered => write!(f, "Sealed value failed authentication"),
+        }
+    }
+}
+
+impl std::error::Error for SealError {}
+
+/// A 256-bit key derived from `secret` for the use `info` names. Anything
+/// keyed from `JWT_SECRET` goes through here with its own info string.
+pub(crate) fn derive_key(secret: &str, info: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(HKDF_SALT), secret.as_bytes())
+        .expand(info, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 length");
+    key
+}
+
+/// `JWT_SECRET`, plus `JWT_PREVIOUS_SECRETS` (comma-separated) for what was
+/// keyed before a rotation.
+pub fn secrets_from_env() -> Result<(String, Vec<String>), String> {
+    let current = env::var("JWT_SECRET").map_err(|_| "JWT_SECRET environment variable not set")?;
+    let previous = env::var("JWT_PREVIOUS_SECRETS")
+        .map(|secrets| {
+            secrets
+                .split(',')
+                .map(|secret| secret.trim().to_string())
+                .filter(|secret| !secret.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok((current, previous))
+}
+
+#[derive(Clone)]
+struct SealingKey {
+    /// Short public name for the secret, so the right key is tried first
+    id: String,
+    cipher: Aes256Gcm,
+}
+
+impl SealingKey {
+    fn derive(secret: &str) -> Self {
+        let cipher_key = derive_key(secret, CIPHER_KEY_INFO);
+        let mut key_id = [0u8; 4];
+        Hkdf::<Sha256>::new(Some(HKDF_SALT), secret.as_bytes())
+            .expand(KEY_ID_INFO, &mut key_id)
+            .expect("4 bytes is a valid HKDF-SHA256 length");
+
+        SealingKey {
+            id: hex::encode(key_id),
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&cipher_key)),
+        }
+    }
+}
+
+/// Encrypts and authenticates small values we hand out or store, such as
+/// provider tokens. Values are sealed under the current secret; previous
+/// secrets can still open what they sealed, so rotating `JWT_SECRET` doesn't
+/// break anything in flight.
+#[derive(Clone)]
+pub struct Sealer {
+    current: SealingKey,
+    previous: Vec<SealingKey>,
+}
+
+impl Sealer {
+    pub fn new(current_secret: &str, previous_secrets: &[String]) -> Self {
+        Sealer {
+            current: SealingKey::derive(current_secret),
+            previous: previous_secrets
+                .iter()
+                .map(|secret| SealingKey::derive(secret))
+                .collect(),
+        }
+    }
+
+    /// Keys from `secrets_from_env`.
+    pub fn from_env() -> Result<Self, String> {
+        let (current, previous) = secrets_from_env()?;
+        Ok(Sealer::new(&current, &previous))
+    }
+
+    /// Id of the key new values are sealed under
+    pub fn current_key_id(&self) -> &str {
+        &self.current.id
+    }
+
+    /// Seals `plaintext` for `purpose`, e.g. "login". It only opens for the
+    /// same purpose. Every call uses a fresh random nonce.
+    pub fn seal(&self, purpose: &str, plaintext: &str) -> Result<String, SealError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let ciphertext = self
+            .current
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext.as_bytes(),
+                    aad: purpose.as_bytes(),
+                },
+            )
+            .map_err(|_| SealError::Malformed)?;
+
+        let mut combined = nonce.to_vec();
+        combined.extend(ciphertext);
+        Ok(format!(
+            "{}.{}.{}",
+            FORMAT_VERSION,
+            self.current.id,
+            URL_SAFE_NO_PAD.encode(combined)
+        ))
+    }
+
+    /// Opens a value `seal` produced for `purpose`, under the current or a
+    /// previous secret.
+    pub fn open(&self, purpose: &str, sealed: &str) -> Result<String, SealError> {
+        let mut parts = sealed.splitn(3, '.');
+        let (Some(FORMAT_VERSION), Some(key_id), Some(payload)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(SealError::Malformed);
+        };
+        let combined = URL_SAFE_NO_PAD
+            .decode(payload)
+            .map_err(|_| SealError::Malformed)?;
+        if combined.len() < NONCE_LEN {
+            return Err(SealError::Malformed);
+        }
+        let (nonce, ciphertext) = combined.split_at(NONCE_LEN);
+
+        // Ids are short, so more than one secret could share one; try each
+        let mut keys = std::iter::once(&self.current)
+            .chain(&self.previous)
+            .filter(|key| key.id == key_id)
+            .peekable();
+        if keys.peek().is_none() {
+            return Err(SealError::UnknownKey);
+        }
+
+        for key in keys {
+            let opened = key.cipher.decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: purpose.as_bytes(),
+                },
+            );
+            if let Ok(plaintext) = opened {
+                return String::from_utf8(plaintext).map_err(|_| SealError::Malformed);
+            }
+        }
+        Err(SealError::Tampered)
+    }
+}
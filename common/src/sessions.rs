@@ -0,0 +1,180 @@
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use sqlx::{
+    sqlite::{SqlitePool, SqliteRow},
+    Row,
+};
+
+use crate::{delete_expired, format_db_timestamp, hash_secret};
+
+/// A session ends after this long without being used
+pub const SESSION_IDLE_DAYS: i64 = 30;
+
+/// How stale `last_seen_at` gets before using the session refreshes it
+pub const SESSION_TOUCH_MINUTES: i64 = 5;
+
+/// Longest user agent kept, in characters
+const MAX_USER_AGENT_LEN: usize = 255;
+
+pub struct DbSession {
+    pub id: i64,
+    pub user_id: i64,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: String,
+    pub last_seen_at: String,
+    pub expires_at: String,
+}
+
+const SESSION_COLUMNS: &str = "id, user_id, user_agent, ip, created_at, last_seen_at, expires_at";
+
+fn session_from_row(row: &SqliteRow) -> DbSession {
+    DbSession {
+        id: row.get("id"),
+        user_id: row.get("user_id"),
+        user_agent: row.get("user_agent"),
+        ip: row.get("ip"),
+        created_at: row.get("created_at"),
+        last_seen_at: row.get("last_seen_at"),
+        expires_at: row.get("expires_at"),
+    }
+}
+
+/// Signs `user_id` in, returning the session and the secret to put in the
+/// cookie.
+pub async fn create_session(
+    pool: &SqlitePool,
+    user_id: i64,
+    user_agent: Option<&str>,
+    ip: Option<&str>,
+    current_utc: DateTime<Utc>,
+) -> Result<(DbSession, String), sqlx::Error> {
+    delete_expired(pool, "sessions", current_utc).await?;
+
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let secret = hex::encode(bytes);
+
+    let user_agent =
+        user_agent.map(|agent| agent.chars().take(MAX_USER_AGENT_LEN).collect::<String>());
+    let now = format_db_timestamp(current_utc);
+    let row = sqlx::query(&format!(
+        "INSERT INTO sessions (user_id, token_hash, user_agent, ip, created_at, last_seen_at, expires_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING {}",
+        SESSION_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(hash_secret(&secret))
+    .bind(user_agent)
+    .bind(ip)
+    .bind(&now)
+    .bind(&now)
+    .bind(format_db_timestamp(
+        current_utc + Duration::days(SESSION_IDLE_DAYS),
+    ))
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .next()
+    .ok_or(sqlx::Error::RowNotFound)?;
+
+    Ok((session_from_row(&row), secret))
+}
+
+/// The unexpired session a cookie holds. Using it pushes its expiry out to
+/// `SESSION_IDLE_DAYS` from now, at most once every `SESSION_TOUCH_MINUTES`
+/// so each request doesn't write to the database.
+pub async fn authenticate_session(
+    pool: &SqlitePool,
+    secret: &str,
+    current_utc: DateTime<Utc>,
+) -> Result<Option<DbSession>, sqlx::Error> {
+    let session = sqlx::query(&format!(
+        "SELECT {} FROM sessions WHERE token_hash = ? AND expires_at > ?",
+        SESSION_COLUMNS
+    ))
+    .bind(hash_secret(secret))
+    .bind(format_db_timestamp(current_utc))
+    .fetch_optional(pool)
+    .await?
+    .map(|row| session_from_row(&row));
+
+    let Some(session) = session else {
+        return Ok(None);
+    };
+    let touch_before = format_db_timestamp(current_utc - Duration::minutes(SESSION_TOUCH_MINUTES));
+    if session.last_seen_at > touch_before {
+        return Ok(Some(session));
+    }
+
+    let rows = sqlx::query(&format!(
+        "UPDATE sessions SET last_seen_at = ?, expires_at = ?
+         WHERE id = ? AND expires_at > ?
+         RETURNING {}",
+        SESSION_COLUMNS
+    ))
+    .bind(format_db_timestamp(current_utc))
+    .bind(format_db_timestamp(
+        current_utc + Duration::days(SESSION_IDLE_DAYS),
+    ))
+    .bind(session.id)
+    .bind(format_db_timestamp(current_utc))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.first().map(session_from_row))
+}
+
+/// The user's unexpired sessions, most recently used first.
+pub async fn get_sessions_by_user_id(
+    pool: &SqlitePool,
+    user_id: i64,
+    current_utc: DateTime<Utc>,
+) -> Result<Vec<DbSession>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM sessions WHERE user_id = ? AND expires_at > ?
+         ORDER BY last_seen_at DESC, id DESC",
+        SESSION_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(format_db_timestamp(current_utc))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.iter().map(session_from_row).collect())
+}
+
+/// Signs one of the user's sessions out. False if it isn't theirs.
+pub async fn revoke_session(
+    pool: &SqlitePool,
+    user_id: i64,
+    session_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM sessions WHERE id = ? AND user_id = ?")
+        .bind(session_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Signs the user out everywhere, returning how many sessions ended.
+pub async fn revoke_all_sessions(pool: &SqlitePool, user_id: i64) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM sessions WHERE user_id = ?")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Ends the session a cookie holds, for logging out.
+pub async fn delete_session_by_secret(pool: &SqlitePool, secret: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM sessions WHERE token_hash = ?")
+        .bind(hash_secret(secret))
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
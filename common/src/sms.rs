@@ -0,0 +1,241 @@
+use async_trait::async_trait;
+use log::info;
+use std::collections::VecDeque;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::message_central::MessageCentralClient;
+use crate::surge::SurgeClient;
+
+/// Longest body we send, so every message fits in a single SMS segment
+pub const SMS_MAX_LEN: usize = 160;
+
+/// Joins as many `lines` as fit in one SMS, in order, summarizing whatever
+/// is left as "+ N more...". `suffix` always goes at the end. Returns the
+/// body and how many lines it includes, or `None` if not even the first line
+/// fits, so callers can pick their own fallback.
+pub fn fit_lines(lines: &[String], suffix: &str) -> Option<(String, usize)> {
+    let full = format!("{}{}", lines.join("\n"), suffix);
+    if full.len() <= SMS_MAX_LEN {
+        return Some((full, lines.len()));
+    }
+
+    for included in (1..lines.len()).rev() {
+        let candidate = format!(
+            "{}\n+ {} more...{}",
+            lines[..included].join("\n"),
+            lines.len() - included,
+            suffix
+        );
+        if candidate.len() <= SMS_MAX_LEN {
+            return Some((candidate, included));
+        }
+    }
+
+    None
+}
+
+#[derive(Debug, Clone)]
+pub struct SmsError {
+    pub message: String,
+    /// True when the provider rejected the message outright (e.g. an invalid
+    /// number) and sending it again won't help
+    pub permanent: bool,
+}
+
+impl std::fmt::Display for SmsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SMS Error: {}", self.message)
+    }
+}
+
+impl std::error::Error for SmsError {}
+
+/// What a backend reports back after accepting a message.
+#[derive(Debug, Clone, Default)]
+pub struct SmsReceipt {
+    /// Provider-assigned id, if the backend returns one
+    pub message_id: Option<String>,
+}
+
+#[async_trait]
+pub trait SmsSender: Send + Sync {
+    /// Short backend name, e.g. "surge" or "console"
+    fn provider(&self) -> &'static str;
+
+    /// Send `body` to `to`, which must be an E.164 phone number
+    async fn send_sms(&self, to: &str, body: &str) -> Result<SmsReceipt, SmsError>;
+}
+
+/// Which SMS backend a binary should use, read from `SMS_BACKEND`.
+#[derive(Clone, Debug)]
+pub enum SmsBackend {
+    Surge {
+        api_key: String,
+    },
+    MessageCentral {
+        customer_id: String,
+        email: String,
+        password_b64: String,
+    },
+    Console,
+    File {
+        path: PathBuf,
+    },
+}
+
+impl SmsBackend {
+    /// Reads the backend from the environment. Defaults to Surge so existing
+    /// deployments keep working without setting `SMS_BACKEND`.
+    pub fn from_env() -> Result<Self, String> {
+        let backend = env::var("SMS_BACKEND").unwrap_or_else(|_| "surge".to_string());
+
+        match backend.as_str() {
+            "surge" => Ok(SmsBackend::Surge {
+                api_key: env::var("SURGE_API_KEY")
+                    .map_err(|_| "SURGE_API_KEY environment variable not set")?,
+            }),
+            "message_central" => Ok(SmsBackend::MessageCentral {
+                customer_id: env::var("MESSAGE_CENTRAL_CUSTOMER_ID")
+                    .map_err(|_| "MESSAGE_CENTRAL_CUSTOMER_ID environment variable not set")?,
+                email: env::var("MESSAGE_CENTRAL_EMAIL")
+                    .map_err(|_| "MESSAGE_CENTRAL_EMAIL environment variable not set")?,
+                password_b64: env::var("MESSAGE_CENTRAL_PASSWORD_B64")
+                    .map_err(|_| "MESSAGE_CENTRAL_PASSWORD_B64 environment variable not set")?,
+            }),
+            "console" => Ok(SmsBackend::Console),
+            "file" => Ok(SmsBackend::File {
+                path: env::var("SMS_OUTBOX_PATH")
+                    .map_err(|_| "SMS_OUTBOX_PATH environment variable not set")?
+                    .into(),
+            }),
+            other => Err(format!(
+                "Unknown SMS_BACKEND '{}' (expected surge, message_central, console or file)",
+                other
+            )),
+        }
+    }
+
+    pub fn build(&self) -> Arc<dyn SmsSender> {
+        match self {
+            SmsBackend::Surge { api_key } => Arc::new(SurgeClient::new(api_key.clone())),
+            SmsBackend::MessageCentral {
+                customer_id,
+                email,
+                password_b64,
+            } => Arc::new(MessageCentralClient::new(
+                customer_id.clone(),
+                email.clone(),
+                password_b64.clone(),
+            )),
+            SmsBackend::Console => Arc::new(ConsoleSender),
+            SmsBackend::File { path } => Arc::new(FileSender::new(path.clone())),
+        }
+    }
+}
+
+/// Logs messages instead of sending them. Useful for local development.
+pub struct ConsoleSender;
+
+#[async_trait]
+impl SmsSender for ConsoleSender {
+    fn provider(&self) -> &'static str {
+        "console"
+    }
+
+    async fn send_sms(&self, to: &str, body: &str) -> Result<SmsReceipt, SmsError> {
+        info!("[console sms] to={}\n{}", to, body);
+        Ok(SmsReceipt::default())
+    }
+}
+
+/// Appends messages to a local file instead of sending them.
+pub struct FileSender {
+    path: PathBuf,
+}
+
+impl FileSender {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+#[async_trait]
+impl SmsSender for FileSender {
+    fn provider(&self) -> &'static str {
+        "file"
+    }
+
+    async fn send_sms(&self, to: &str, body: &str) -> Result<SmsReceipt, SmsError> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| SmsError {
+                message: format!("Failed to open outbox {}: {}", self.path.display(), e),
+                permanent: false,
+            })?;
+
+        writeln!(file, "--- to: {}\n{}\n", to, body).map_err(|e| SmsError {
+            message: format!("Failed to write outbox {}: {}", self.path.display(), e),
+            permanent: false,
+        })?;
+
+        Ok(SmsReceipt::default())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedSms {
+    pub to: String,
+    pub body: String,
+}
+
+/// Keeps every message in memory so tests can assert on what was sent.
+#[derive(Default)]
+pub struct MemorySender {
+    sent: Mutex<Vec<RecordedSms>>,
+    failures: Mutex<VecDeque<SmsError>>,
+}
+
+impl MemorySender {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sent(&self) -> Vec<RecordedSms> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    /// Makes the next send fail with `error` instead of recording the message.
+    /// Queued failures are used up in order.
+    pub fn fail_next(&self, error: SmsError) {
+        self.failures.lock().unwrap().push_back(error);
+    }
+}
+
+#[async_trait]
+impl SmsSender for MemorySender {
+    fn provider(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn send_sms(&self, to: &str, body: &str) -> Result<SmsReceipt, SmsError> {
+        if let Some(error) = self.failures.lock().unwrap().pop_front() {
+            return Err(error);
+        }
+
+        let mut sent = self.sent.lock().unwrap();
+        sent.push(RecordedSms {
+            to: to.to_string(),
+            body: body.to_string(),
+        });
+
+        Ok(SmsReceipt {
+            message_id: Some(format!("memory-{}", sent.len())),
+        })
+    }
+}
@@ -1,10 +1,15 @@
+use async_trait::async_trait;
 use log::info;
 use reqwest;
 use serde::{Deserialize, Serialize};
 
+use crate::sms::{SmsError, SmsReceipt, SmsSender};
+
 #[derive(Debug)]
 pub struct SurgeError {
     pub message: String,
+    /// True when retrying the same request can't succeed (bad number, rejected body)
+    pub permanent: bool,
 }
 
 impl std::fmt::Display for SurgeError {
@@ -29,6 +34,11 @@ pub struct MessageRequest1 {
     pub body: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct MessageResponse {
+    id: Option<String>,
+}
+
 impl SurgeClient {
     pub fn new(api_key: String) -> Self {
         Self {
@@ -37,11 +47,12 @@ impl SurgeClient {
         }
     }
 
-    pub async fn send_sms(&self, to: String, body: String) -> Result<(), SurgeError> {
+    pub async fn send_message(&self, to: String, body: String) -> Result<SmsReceipt, SurgeError> {
         // Validate required field
         if to.is_empty() {
             return Err(SurgeError {
                 message: "Phone number 'to' is required".to_string(),
+                permanent: true,
             });
         }
 
@@ -49,6 +60,7 @@ impl SurgeClient {
         if !to.starts_with('+') {
             return Err(SurgeError {
                 message: "Phone number 'to' must be in E.164 format (starting with +)".to_string(),
+                permanent: true,
             });
         }
 
@@ -69,6 +81,7 @@ impl SurgeClient {
             .await
             .map_err(|e| SurgeError {
                 message: format!("Failed to send SMS request: {}", e),
+                permanent: false,
             })?;
 
         if !response.status().is_success() {
@@ -77,15 +90,45 @@ impl SurgeClient {
                 .text()
                 .await
                 .unwrap_or_else(|_| "Unknown error".to_string());
+            // 4xx means Surge rejected the message itself; timeouts and rate
+            // limits are worth another try
+            let permanent = status.is_client_error()
+                && status != reqwest::StatusCode::REQUEST_TIMEOUT
+                && status != reqwest::StatusCode::TOO_MANY_REQUESTS;
             return Err(SurgeError {
                 message: format!(
                     "SMS API returned error status: {}. Response: {}",
                     status, error_text
                 ),
+                permanent,
             });
         }
 
+        // The message id is only informational, so a body we can't parse isn't an error
+        let message_id = response
+            .text()
+            .await
+            .ok()
+            .and_then(|text| serde_json::from_str::<MessageResponse>(&text).ok())
+            .and_then(|parsed| parsed.id);
+
         info!("SMS sent successfully");
-        Ok(())
+        Ok(SmsReceipt { message_id })
+    }
+}
+
+#[async_trait]
+impl SmsSender for SurgeClient {
+    fn provider(&self) -> &'static str {
+        "surge"
+    }
+
+    async fn send_sms(&self, to: &str, body: &str) -> Result<SmsReceipt, SmsError> {
+        self.send_message(to.to_string(), body.to_string())
+            .await
+            .map_err(|e| SmsError {
+                permanent: e.permanent,
+                message: e.to_string(),
+            })
     }
 }
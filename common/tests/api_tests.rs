@@ -0,0 +1,177 @@
+#[path = "common/mod.rs"]
+mod fixtures;
+
+use chrono::{NaiveDate, TimeZone, Utc};
+use chrono_tz::America::New_York;
+use common::api::{upcoming_events, ReminderInput, SettingsInput};
+use common::birthday::{birthdate_to_timestamp, BirthdayEngine, LeapDayPolicy};
+use common::events::EventType;
+use common::{
+    create_reminder, create_user, get_reminders_by_user_id, update_reminder_year_known, DbReminder,
+};
+use fixtures::{create_test_timestamp, setup_test_database};
+
+fn reminder_input(name: &str, date: &str) -> ReminderInput {
+    ReminderInput {
+        name: name.to_string(),
+        date: date.to_string(),
+        ..Default::default()
+    }
+}
+
+async fn reminders(db: &sqlx::SqlitePool, user_id: i64) -> Vec<DbReminder> {
+    get_reminders_by_user_id(db, user_id).await.unwrap()
+}
+
+#[test]
+fn test_reminder_input_dates() {
+    let full = reminder_input(" Jane ", "1990-03-14").validate().unwrap();
+    assert_eq!(full.name, "Jane");
+    assert_eq!(
+        full.birthdate,
+        NaiveDate::from_ymd_opt(1990, 3, 14).unwrap()
+    );
+    assert!(full.year_known);
+    assert_eq!(full.event_type, EventType::Birthday);
+    assert_eq!(
+        birthdate_to_timestamp(full.birthdate).to_string(),
+        create_test_timestamp(1990, 3, 14)
+    );
+
+    let no_year = reminder_input("Rex", "--07-15").validate().unwrap();
+    assert_eq!(
+        no_year.birthdate,
+        NaiveDate::from_ymd_opt(2000, 7, 15).unwrap()
+    );
+    assert!(!no_year.year_known);
+
+    // Feb 29 without a year is fine; the placeholder year is a leap year
+    assert!(reminder_input("Leap", "--02-29").validate().is_ok());
+
+    for date in ["03/14/1990", "1990-02-30", "--13-01", ""] {
+        let err = reminder_input("Jane", date).validate().unwrap_err();
+        assert_eq!(err.field, "date", "{:?} should be rejected", date);
+    }
+}
+
+#[test]
+fn test_reminder_input_names_the_bad_field() {
+    let err = reminder_input("  ", "1990-03-14").validate().unwrap_err();
+    assert_eq!(err.field, "name");
+
+    let mut input = reminder_input("Jane", "1990-03-14");
+    input.rrule = Some("FREQ=HOURLY".to_string());
+    assert_eq!(input.validate().unwrap_err().field, "rrule");
+
+    let mut input = reminder_input("Jane", "1990-03-14");
+    input.notice_offsets = Some(vec![7, 400]);
+    assert_eq!(input.validate().unwrap_err().field, "notice_offsets");
+
+    let mut input = reminder_input("Jane", "1990-03-14");
+    input.event_type = Some("custom".to_string());
+    assert_eq!(input.validate().unwrap_err().field, "event_type");
+
+    // Lunar dates are converted from the Gregorian one, which needs a year
+    let mut input = reminder_input("Grandma", "--03-14");
+    input.calendar = Some("chinese".to_string());
+    assert_eq!(input.validate().unwrap_err().field, "calendar");
+}
+
+#[test]
+fn test_reminder_input_optional_fields() {
+    let mut input = reminder_input("Sam & Alex", "2015-06-20");
+    input.event_type = Some("wedding_anniversary".to_string());
+    input.notice_offsets = Some(vec![1, 7, 7]);
+    input.rrule = Some("FREQ=YEARLY".to_string());
+
+    let reminder = input.validate().unwrap();
+    assert_eq!(reminder.event_type, EventType::WeddingAnniversary);
+    assert_eq!(reminder.notice_offsets, Some(vec![7, 1]));
+    assert!(reminder.recurrence.is_some());
+    assert!(reminder.calendar_date.is_none());
+}
+
+#[tokio::test]
+async fn test_settings_input_keeps_unset_fields() {
+    let (db, _temp_file) = setup_test_database().await;
+    let user = create_user(&db, "1234567890").await.unwrap();
+
+    let unchanged = SettingsInput::default().apply(&user).unwrap();
+    assert_eq!(unchanged.notice_offsets, user.notice_offsets);
+    assert_eq!(unchanged.send_hour, user.send_hour);
+    assert_eq!(unchanged.iana_tz, user.iana_tz);
+    assert_eq!(unchanged.leap_day_policy, user.leap_day_policy);
+
+    let input = SettingsInput {
+        send_hour: Some(20),
+        leap_day_policy: Some("mar1".to_string()),
+        ..Default::default()
+    };
+    let settings = input.apply(&user).unwrap();
+    assert_eq!(settings.send_hour, 20);
+    assert_eq!(settings.leap_day_policy, LeapDayPolicy::Mar1);
+    assert_eq!(settings.iana_tz, user.iana_tz);
+
+    let bad_hour = SettingsInput {
+        send_hour: Some(24),
+        ..Default::default()
+    };
+    assert_eq!(bad_hour.apply(&user).unwrap_err().field, "send_hour");
+
+    let bad_tz = SettingsInput {
+        iana_tz: Some("Europe/London".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(bad_tz.apply(&user).unwrap_err().field, "iana_tz");
+
+    let no_offsets = SettingsInput {
+        notice_offsets: Some(vec![]),
+        ..Default::default()
+    };
+    assert_eq!(no_offsets.apply(&user).unwrap_err().field, "notice_offsets");
+}
+
+#[tokio::test]
+async fn test_upcoming_events_window_and_order() {
+    let (db, _temp_file) = setup_test_database().await;
+    let user = create_user(&db, "1234567890").await.unwrap();
+
+    for (name, (year, month, day)) in [
+        ("Later", (1980, 6, 1)),
+        ("Tomorrow", (1990, 3, 15)),
+        ("Today", (1990, 3, 14)),
+    ] {
+        create_reminder(&db, user.id, name, &create_test_timestamp(year, month, day))
+            .await
+            .unwrap();
+    }
+    let no_year = create_reminder(&db, user.id, "Rex", &create_test_timestamp(2000, 3, 20))
+        .await
+        .unwrap();
+    update_reminder_year_known(&db, no_year.id, false)
+        .await
+        .unwrap();
+
+    let now = Utc.with_ymd_and_hms(2024, 3, 14, 15, 0, 0).unwrap();
+    let engine = BirthdayEngine::at(New_York, LeapDayPolicy::Feb28, now);
+
+    let events = upcoming_events(&engine, reminders(&db, user.id).await, 30);
+    let names: Vec<&str> = events.iter().map(|event| event.name.as_str()).collect();
+    assert_eq!(names, ["Today", "Tomorrow", "Rex"]);
+
+    assert_eq!(events[0].days_until, 0);
+    assert_eq!(events[0].date, "2024-03-14");
+    assert_eq!(events[0].years, Some(34));
+    assert_eq!(events[0].title, "Today's 34th");
+    assert_eq!(events[1].weekday, "Fri");
+    assert_eq!(events[2].years, None);
+    assert_eq!(events[2].title, "Rex's birthday");
+
+    let today_only = upcoming_events(&engine, reminders(&db, user.id).await, 0);
+    assert_eq!(today_only.len(), 1);
+
+    assert_eq!(
+        upcoming_events(&engine, reminders(&db, user.id).await, 366).len(),
+        4
+    );
+}
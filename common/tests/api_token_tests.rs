@@ -0,0 +1,119 @@
+#[path = "common/mod.rs"]
+mod fixtures;
+
+use common::api_tokens::{
+    authenticate_api_token, count_active_api_tokens, create_api_token, get_api_tokens_by_user_id,
+    revoke_api_token, validate_token_request, TokenScope, TOKEN_PREFIX,
+};
+use common::{create_user, delete_user, hash_secret};
+use fixtures::setup_test_database;
+
+#[tokio::test]
+async fn test_token_is_stored_hashed_and_authenticates() {
+    let (db, _temp_file) = setup_test_database().await;
+    let user = create_user(&db, "1234567890").await.unwrap();
+
+    let (token, secret) = create_api_token(&db, user.id, "HR sync", &[TokenScope::Read])
+        .await
+        .unwrap();
+    assert!(secret.starts_with(TOKEN_PREFIX));
+    assert_eq!(token.name, "HR sync");
+    assert_eq!(token.scopes, [TokenScope::Read]);
+    assert!(token.last_used_at.is_none());
+
+    let stored: String = sqlx::query_scalar("SELECT token_hash FROM api_tokens WHERE id = ?")
+        .bind(token.id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(stored, hash_secret(&secret));
+    assert!(!stored.contains(&secret[TOKEN_PREFIX.len()..]));
+
+    let authenticated = authenticate_api_token(&db, &secret).await.unwrap().unwrap();
+    assert_eq!(authenticated.id, token.id);
+    assert_eq!(authenticated.user_id, user.id);
+    assert!(authenticated.has_scope(TokenScope::Read));
+    assert!(!authenticated.has_scope(TokenScope::Write));
+    assert!(authenticated.last_used_at.is_some());
+
+    assert!(authenticate_api_token(&db, "hbd_not-a-token")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(authenticate_api_token(&db, &secret[TOKEN_PREFIX.len()..])
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_revoked_token_is_refused() {
+    let (db, _temp_file) = setup_test_database().await;
+    let user = create_user(&db, "1234567890").await.unwrap();
+    let other = create_user(&db, "1234567891").await.unwrap();
+
+    let scopes = [TokenScope::Read, TokenScope::Write];
+    let (token, secret) = create_api_token(&db, user.id, "Script", &scopes)
+        .await
+        .unwrap();
+    create_api_token(&db, user.id, "Other script", &scopes)
+        .await
+        .unwrap();
+    assert_eq!(count_active_api_tokens(&db, user.id).await.unwrap(), 2);
+
+    // Only the owner can revoke it, and only once
+    assert!(!revoke_api_token(&db, other.id, token.id).await.unwrap());
+    assert!(revoke_api_token(&db, user.id, token.id).await.unwrap());
+    assert!(!revoke_api_token(&db, user.id, token.id).await.unwrap());
+
+    assert!(authenticate_api_token(&db, &secret)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(count_active_api_tokens(&db, user.id).await.unwrap(), 1);
+
+    // Still listed, newest first, so the user can see what was revoked
+    let tokens = get_api_tokens_by_user_id(&db, user.id).await.unwrap();
+    assert_eq!(tokens.len(), 2);
+    assert_eq!(tokens[0].name, "Other script");
+    assert!(tokens[1].revoked_at.is_some());
+
+    assert!(delete_user(&db, user.id).await.unwrap());
+    assert!(get_api_tokens_by_user_id(&db, user.id)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_validate_token_request() {
+    assert_eq!(
+        validate_token_request("  HR sync ", &[TokenScope::Read]).unwrap(),
+        "HR sync"
+    );
+    assert!(validate_token_request(" ", &[TokenScope::Read]).is_err());
+    assert!(validate_token_request(&"x".repeat(61), &[TokenScope::Read]).is_err());
+    assert!(validate_token_request("HR sync", &[]).is_err());
+}
+
+#[tokio::test]
+async fn test_last_used_at_is_persisted() {
+    let (db, temp_file) = setup_test_database().await;
+    let user = create_user(&db, "1234567890").await.unwrap();
+    let (token, secret) = create_api_token(&db, user.id, "Backups", &[TokenScope::Read])
+        .await
+        .unwrap();
+    authenticate_api_token(&db, &secret).await.unwrap().unwrap();
+
+    // A separate connection only sees what was committed
+    let other = sqlx::SqlitePool::connect(&format!("sqlite:{}", temp_file.path().display()))
+        .await
+        .unwrap();
+    let last_used_at: Option<String> =
+        sqlx::query_scalar("SELECT last_used_at FROM api_tokens WHERE id = ?")
+            .bind(token.id)
+            .fetch_one(&other)
+            .await
+            .unwrap();
+    assert!(last_used_at.is_some());
+}
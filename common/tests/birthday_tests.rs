@@ -0,0 +1,234 @@
+use chrono::{Datelike, Duration, NaiveDate, TimeZone, Utc, Weekday};
+use chrono_tz::America::Los_Angeles;
+use common::birthday::{
+    birthdate_from_timestamp, birthdate_to_timestamp, birthday_in_year, next_birthday,
+    BirthdayEngine, LeapDayPolicy,
+};
+use common::clock::FixedClock;
+use common::events::EventType;
+use common::{DbReminder, UNKNOWN_BIRTH_YEAR};
+
+const POLICIES: [LeapDayPolicy; 2] = [LeapDayPolicy::Feb28, LeapDayPolicy::Mar1];
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+fn is_leap_year(year: i32) -> bool {
+    NaiveDate::from_ymd_opt(year, 2, 29).is_some()
+}
+
+/// Every day from `start` through `end`, inclusive
+fn days(start: NaiveDate, end: NaiveDate) -> impl Iterator<Item = NaiveDate> {
+    std::iter::successors(Some(start), move |day| {
+        Some(*day + Duration::days(1)).filter(|next| *next <= end)
+    })
+}
+
+#[test]
+fn test_leap_day_observed_date_every_year() {
+    let birthdate = date(1992, 2, 29);
+
+    // 1896-2104 covers the skipped century leap years 1900 and 2100 as well
+    // as the 400-year exception 2000
+    for year in 1896..=2104 {
+        for policy in POLICIES {
+            let observed = birthday_in_year(birthdate, year, policy);
+            let expected = match (is_leap_year(year), policy) {
+                (true, _) => date(year, 2, 29),
+                (false, LeapDayPolicy::Feb28) => date(year, 2, 28),
+                (false, LeapDayPolicy::Mar1) => date(year, 3, 1),
+            };
+            assert_eq!(observed, expected, "year {} with {:?}", year, policy);
+        }
+    }
+}
+
+#[test]
+fn test_leap_day_next_birthday_every_day_across_leap_cycles() {
+    let birthdate = date(1992, 2, 29);
+
+    for today in days(date(2019, 1, 1), date(2033, 12, 31)) {
+        for policy in POLICIES {
+            let (next, age) = next_birthday(birthdate, today, policy);
+            let days_until = (next - today).num_days();
+
+            assert!(next >= today, "{} -> {} with {:?}", today, next, policy);
+            assert!(days_until <= 366, "{} -> {} with {:?}", today, next, policy);
+            assert_eq!(next, birthday_in_year(birthdate, next.year(), policy));
+            assert_eq!(age, next.year() - 1992);
+
+            // Nothing was skipped: the previous year's date is already past
+            let previous = birthday_in_year(birthdate, next.year() - 1, policy);
+            assert!(
+                previous < today,
+                "{} skipped {} with {:?}",
+                today,
+                previous,
+                policy
+            );
+        }
+    }
+}
+
+#[test]
+fn test_leap_day_birthday_is_never_dropped() {
+    let birthdate = date(2000, 2, 29);
+
+    // Each policy yields exactly one celebration per year, in every year
+    for year in 2019..=2033 {
+        for policy in POLICIES {
+            let celebrations = days(date(year, 1, 1), date(year, 12, 31))
+                .filter(|today| next_birthday(birthdate, *today, policy).0 == *today)
+                .count();
+            assert_eq!(celebrations, 1, "year {} with {:?}", year, policy);
+        }
+    }
+}
+
+#[test]
+fn test_leap_day_policies_around_the_boundary() {
+    let birthdate = date(1992, 2, 29);
+
+    // Common year: Feb 28 vs Mar 1
+    assert_eq!(
+        next_birthday(birthdate, date(2023, 2, 27), LeapDayPolicy::Feb28),
+        (date(2023, 2, 28), 31)
+    );
+    assert_eq!(
+        next_birthday(birthdate, date(2023, 2, 27), LeapDayPolicy::Mar1),
+        (date(2023, 3, 1), 31)
+    );
+    // The day after Feb 28 with the Feb 28 policy rolls over to next leap day
+    assert_eq!(
+        next_birthday(birthdate, date(2023, 3, 1), LeapDayPolicy::Feb28),
+        (date(2024, 2, 29), 32)
+    );
+    assert_eq!(
+        next_birthday(birthdate, date(2023, 3, 1), LeapDayPolicy::Mar1),
+        (date(2023, 3, 1), 31)
+    );
+    // Leap year: both policies use the real date
+    for policy in POLICIES {
+        assert_eq!(
+            next_birthday(birthdate, date(2024, 2, 28), policy),
+            (date(2024, 2, 29), 32)
+        );
+    }
+}
+
+#[test]
+fn test_regular_birthdays_ignore_policy() {
+    for birthdate in [
+        date(1990, 1, 1),
+        date(1985, 2, 28),
+        date(1988, 3, 1),
+        date(1970, 12, 31),
+    ] {
+        for today in days(date(2023, 1, 1), date(2024, 12, 31)) {
+            let (feb28, feb28_age) = next_birthday(birthdate, today, LeapDayPolicy::Feb28);
+            let (mar1, mar1_age) = next_birthday(birthdate, today, LeapDayPolicy::Mar1);
+
+            assert_eq!(feb28, mar1);
+            assert_eq!(feb28_age, mar1_age);
+            assert_eq!(
+                (feb28.month(), feb28.day()),
+                (birthdate.month(), birthdate.day())
+            );
+            assert!(feb28 >= today && (feb28 - today).num_days() < 366);
+        }
+    }
+}
+
+#[test]
+fn test_policy_round_trips() {
+    for policy in POLICIES {
+        assert_eq!(LeapDayPolicy::parse(policy.as_str()), Some(policy));
+    }
+    assert_eq!(LeapDayPolicy::parse("feb29"), None);
+    assert_eq!(LeapDayPolicy::default(), LeapDayPolicy::Feb28);
+}
+
+fn reminder(id: i64, name: &str, birthdate: NaiveDate, year_known: bool) -> DbReminder {
+    DbReminder {
+        id,
+        user_id: 1,
+        name: name.to_string(),
+        birthdate: birthdate
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc()
+            .timestamp_millis(),
+        year_known,
+        notice_offsets: None,
+        event_type: EventType::Birthday,
+        recurrence: None,
+        calendar_date: None,
+        created_at: "2024-01-01 00:00:00".to_string(),
+        updated_at: "2024-01-01 00:00:00".to_string(),
+    }
+}
+
+#[test]
+fn test_engine_uses_local_date_late_in_the_evening() {
+    // 10pm on Mar 13 in Los Angeles is already Mar 14 in UTC
+    let now = Utc.with_ymd_and_hms(2024, 3, 14, 5, 0, 0).unwrap();
+    let jane = reminder(1, "Jane", date(1990, 3, 14), true);
+
+    let pacific = BirthdayEngine::at(Los_Angeles, LeapDayPolicy::Feb28, now);
+    assert_eq!(pacific.today(), date(2024, 3, 13));
+    assert_eq!(pacific.info(&jane).unwrap().days_until, 1);
+
+    let utc = BirthdayEngine::at(chrono_tz::UTC, LeapDayPolicy::Feb28, now);
+    assert_eq!(utc.info(&jane).unwrap().days_until, 0);
+
+    // A clock-driven engine agrees with the fixed instant
+    let clocked = BirthdayEngine::new(Los_Angeles, LeapDayPolicy::Feb28, &FixedClock(now));
+    assert_eq!(clocked.today(), pacific.today());
+}
+
+#[test]
+fn test_engine_info_fields() {
+    let now = Utc.with_ymd_and_hms(2024, 3, 1, 17, 0, 0).unwrap();
+    let engine = BirthdayEngine::at(Los_Angeles, LeapDayPolicy::Feb28, now);
+
+    let info = engine
+        .info(&reminder(1, "Jane", date(1990, 3, 14), true))
+        .unwrap();
+    assert_eq!(info.next_date, date(2024, 3, 14));
+    assert_eq!(info.days_until, 13);
+    assert_eq!(info.age_turning, Some(34));
+    assert_eq!(info.weekday, Weekday::Thu);
+
+    let info = engine
+        .info(&reminder(2, "Sam", date(UNKNOWN_BIRTH_YEAR, 3, 14), false))
+        .unwrap();
+    assert_eq!(info.age_turning, None);
+}
+
+#[test]
+fn test_engine_sorts_soonest_first_then_by_name() {
+    let now = Utc.with_ymd_and_hms(2024, 3, 14, 5, 0, 0).unwrap();
+    let engine = BirthdayEngine::at(Los_Angeles, LeapDayPolicy::Feb28, now);
+
+    let mut reminders = vec![
+        reminder(1, "Zed", date(1990, 3, 14), true),
+        reminder(2, "Past", date(1990, 3, 12), true),
+        reminder(3, "Today", date(1990, 3, 13), true),
+        reminder(4, "amy", date(1990, 3, 14), true),
+    ];
+    engine.sort_upcoming(&mut reminders);
+
+    let names: Vec<&str> = reminders.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, vec!["Today", "amy", "Zed", "Past"]);
+}
+
+#[test]
+fn test_birthdate_timestamp_round_trips() {
+    let birthdate = date(1990, 3, 14);
+    assert_eq!(birthdate_to_timestamp(birthdate), 637_372_800_000);
+    assert_eq!(
+        birthdate_from_timestamp(birthdate_to_timestamp(birthdate)),
+        Some(birthdate)
+    );
+}
@@ -0,0 +1,144 @@
+use chrono::{NaiveDate, TimeZone, Utc};
+use common::birthday::{BirthdayEngine, LeapDayPolicy};
+use common::calendars::{parse_calendar_form, CalendarDate, CalendarSystem};
+use common::events::EventType;
+use common::DbReminder;
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+#[test]
+fn test_chinese_new_year_moves_each_year() {
+    // Lunar New Year 2023 was Jan 22
+    let birthday =
+        CalendarDate::from_gregorian(CalendarSystem::Chinese, date(2023, 1, 22)).unwrap();
+    assert_eq!(birthday.month_code, "M01");
+    assert_eq!(birthday.day, 1);
+
+    assert_eq!(
+        birthday.next_occurrence(date(2023, 1, 22), date(2024, 1, 15)),
+        Some((date(2024, 2, 10), 1))
+    );
+    assert_eq!(
+        birthday.next_occurrence(date(2023, 1, 22), date(2024, 2, 11)),
+        Some((date(2025, 1, 29), 2))
+    );
+}
+
+#[test]
+fn test_hebrew_birthday() {
+    // 15 Nisan 5784
+    let birthday = CalendarDate::from_gregorian(CalendarSystem::Hebrew, date(2024, 4, 23)).unwrap();
+    assert_eq!(birthday.month_code, "M07");
+    assert_eq!(birthday.day, 15);
+    assert_eq!(birthday.describe(), "15 Nisan");
+
+    assert_eq!(
+        birthday.next_occurrence(date(2024, 4, 23), date(2024, 5, 1)),
+        Some((date(2025, 4, 13), 1))
+    );
+}
+
+#[test]
+fn test_chinese_leap_month_falls_back_to_regular_month() {
+    // 2023 had a leap second month
+    let birthdate = date(2023, 4, 1);
+    let birthday = CalendarDate::from_gregorian(CalendarSystem::Chinese, birthdate).unwrap();
+    assert_eq!(birthday.month_code, "M02L");
+    assert_eq!(birthday.describe(), "Leap month 2, day 11");
+
+    let (next, years) = birthday
+        .next_occurrence(birthdate, date(2024, 1, 15))
+        .unwrap();
+    assert_eq!(years, 1);
+    let observed = CalendarDate::from_gregorian(CalendarSystem::Chinese, next).unwrap();
+    assert_eq!((observed.month_code.as_str(), observed.day), ("M02", 11));
+}
+
+#[test]
+fn test_adar_one_falls_back_to_adar() {
+    // 5784 is a leap year, so this is in Adar I
+    let birthdate = date(2024, 2, 20);
+    let birthday = CalendarDate::from_gregorian(CalendarSystem::Hebrew, birthdate).unwrap();
+    assert_eq!(birthday.month_code, "M05L");
+    assert_eq!(birthday.describe(), "11 Adar I");
+
+    let (next, years) = birthday
+        .next_occurrence(birthdate, date(2024, 3, 1))
+        .unwrap();
+    assert_eq!(years, 1);
+    let observed = CalendarDate::from_gregorian(CalendarSystem::Hebrew, next).unwrap();
+    assert_eq!((observed.month_code.as_str(), observed.day), ("M06", 11));
+}
+
+#[test]
+fn test_stored_dates_round_trip() {
+    for system in [CalendarSystem::Chinese, CalendarSystem::Hebrew] {
+        assert_eq!(CalendarSystem::parse(system.as_str()), Some(system));
+        let stored = CalendarDate::from_gregorian(system, date(1990, 3, 14)).unwrap();
+        assert_eq!(
+            CalendarDate::parse(system, &stored.month_code, stored.day as i64),
+            Some(stored)
+        );
+    }
+    assert_eq!(CalendarDate::parse(CalendarSystem::Hebrew, "M13", 1), None);
+    assert_eq!(
+        CalendarDate::parse(CalendarSystem::Chinese, "M01", 31),
+        None
+    );
+    assert_eq!(
+        CalendarDate::parse(CalendarSystem::Gregorian, "M01", 1),
+        None
+    );
+}
+
+#[test]
+fn test_parse_calendar_form() {
+    let birthdate = date(1990, 3, 14);
+    assert_eq!(
+        parse_calendar_form("gregorian", birthdate, false, true),
+        Ok(None)
+    );
+    assert!(matches!(
+        parse_calendar_form("hebrew", birthdate, true, false),
+        Ok(Some(CalendarDate {
+            system: CalendarSystem::Hebrew,
+            ..
+        }))
+    ));
+    // Converting needs the real year, and rules are Gregorian-only
+    assert!(parse_calendar_form("chinese", birthdate, false, false).is_err());
+    assert!(parse_calendar_form("chinese", birthdate, true, true).is_err());
+    assert!(parse_calendar_form("julian", birthdate, true, false).is_err());
+}
+
+#[test]
+fn test_engine_follows_the_calendar() {
+    let birthdate = date(1990, 1, 27);
+    let reminder = DbReminder {
+        id: 1,
+        user_id: 1,
+        name: "Grandma".to_string(),
+        birthdate: birthdate
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc()
+            .timestamp_millis(),
+        year_known: true,
+        notice_offsets: None,
+        event_type: EventType::Birthday,
+        recurrence: None,
+        // Lunar New Year 1990
+        calendar_date: CalendarDate::from_gregorian(CalendarSystem::Chinese, birthdate),
+        created_at: "2024-01-01 00:00:00".to_string(),
+        updated_at: "2024-01-01 00:00:00".to_string(),
+    };
+
+    let now = Utc.with_ymd_and_hms(2024, 1, 15, 17, 0, 0).unwrap();
+    let engine = BirthdayEngine::at(chrono_tz::America::New_York, LeapDayPolicy::Feb28, now);
+    let info = engine.info(&reminder).unwrap();
+    assert_eq!(info.next_date, date(2024, 2, 10));
+    assert_eq!(info.days_until, 26);
+    assert_eq!(info.age_turning, Some(34));
+}
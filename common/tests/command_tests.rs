@@ -0,0 +1,315 @@
+#[path = "common/mod.rs"]
+mod fixtures;
+
+use chrono::{Duration, NaiveDate, TimeZone, Utc};
+use common::commands::{execute_command, format_reply, parse_command, SmsCommand, USAGE_REPLY};
+use common::sms::SMS_MAX_LEN;
+use common::{create_reminder, create_user, get_reminders_by_user_id, get_user_by_id};
+use fixtures::{create_test_timestamp, setup_test_database};
+
+fn test_now() -> chrono::DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 1, 15, 15, 0, 0).unwrap()
+}
+
+#[test]
+fn test_parse_commands() {
+    assert_eq!(
+        parse_command("ADD Jane Doe 3/14/1990"),
+        Ok(SmsCommand::Add {
+            name: "Jane Doe".to_string(),
+            birthdate: NaiveDate::from_ymd_opt(1990, 3, 14).unwrap(),
+            year_known: true,
+        })
+    );
+    assert_eq!(
+        parse_command("add Jane 1990-03-14"),
+        Ok(SmsCommand::Add {
+            name: "Jane".to_string(),
+            birthdate: NaiveDate::from_ymd_opt(1990, 3, 14).unwrap(),
+            year_known: true,
+        })
+    );
+    assert_eq!(
+        parse_command("ADD Jane 2/29"),
+        Ok(SmsCommand::Add {
+            name: "Jane".to_string(),
+            birthdate: NaiveDate::from_ymd_opt(2000, 2, 29).unwrap(),
+            year_known: false,
+        })
+    );
+    assert_eq!(parse_command(" list "), Ok(SmsCommand::List));
+    assert_eq!(parse_command("Next"), Ok(SmsCommand::Next));
+    assert_eq!(
+        parse_command("DELETE jane"),
+        Ok(SmsCommand::Delete {
+            name: "jane".to_string()
+        })
+    );
+    assert_eq!(
+        parse_command("NOTICE 3, 14 0"),
+        Ok(SmsCommand::Notice {
+            offsets: vec![14, 3, 0]
+        })
+    );
+    assert_eq!(parse_command("2"), Ok(SmsCommand::Choose(2)));
+    assert_eq!(parse_command("snooze"), Ok(SmsCommand::Snooze { days: 1 }));
+    assert_eq!(
+        parse_command("SNOOZE 2"),
+        Ok(SmsCommand::Snooze { days: 2 })
+    );
+    assert_eq!(
+        parse_command("DONE Jane"),
+        Ok(SmsCommand::Done {
+            name: "Jane".to_string()
+        })
+    );
+}
+
+#[test]
+fn test_parse_command_errors() {
+    assert!(parse_command("ADD Jane").is_err());
+    assert!(parse_command("ADD Jane 13/45/1990").is_err());
+    assert!(parse_command("ADD Jane 3/14/90").is_err());
+    assert!(parse_command("NOTICE 90").is_err());
+    assert!(parse_command("NOTICE").is_err());
+    assert!(parse_command("DELETE").is_err());
+    assert!(parse_command("SNOOZE 99").is_err());
+    assert!(parse_command("DONE").is_err());
+    assert_eq!(parse_command("0"), Err(USAGE_REPLY.to_string()));
+    assert_eq!(
+        parse_command("happy birthday!"),
+        Err(USAGE_REPLY.to_string())
+    );
+}
+
+#[test]
+fn test_format_reply_fits_one_sms() {
+    let lines: Vec<String> = (0..30)
+        .map(|i| format!("Reminder number {} 3/14", i))
+        .collect();
+    let reply = format_reply(&lines);
+
+    assert!(reply.len() <= SMS_MAX_LEN);
+    assert!(reply.starts_with("Reminder number 0 3/14"));
+    assert!(reply.ends_with("more..."));
+}
+
+#[tokio::test]
+async fn test_add_list_and_next() {
+    let (db, _temp_file) = setup_test_database().await;
+    let user = create_user(&db, "1234567890").await.unwrap();
+
+    let reply = execute_command(
+        &db,
+        &user,
+        parse_command("ADD Jane 1/20/1990").unwrap(),
+        test_now(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(reply, "Added Jane (1/20/1990).");
+    execute_command(
+        &db,
+        &user,
+        parse_command("ADD John 6/1/1985").unwrap(),
+        test_now(),
+    )
+    .await
+    .unwrap();
+
+    let reply = execute_command(&db, &user, SmsCommand::List, test_now())
+        .await
+        .unwrap();
+    assert!(reply.contains("Jane 1/20"));
+    assert!(reply.contains("John 6/1"));
+
+    let reply = execute_command(&db, &user, SmsCommand::Next, test_now())
+        .await
+        .unwrap();
+    assert_eq!(reply, "Jane in 5 days (1/20)");
+}
+
+#[tokio::test]
+async fn test_notice_updates_settings() {
+    let (db, _temp_file) = setup_test_database().await;
+    let user = create_user(&db, "1234567891").await.unwrap();
+
+    let reply = execute_command(
+        &db,
+        &user,
+        parse_command("NOTICE 14,3,0").unwrap(),
+        test_now(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(reply, "Reminders set for 14 days, 3 days and day-of.");
+
+    let user = get_user_by_id(&db, user.id).await.unwrap().unwrap();
+    assert_eq!(user.notice_offsets, vec![14, 3, 0]);
+}
+
+#[tokio::test]
+async fn test_delete_unique_match() {
+    let (db, _temp_file) = setup_test_database().await;
+    let user = create_user(&db, "1234567892").await.unwrap();
+    create_reminder(
+        &db,
+        user.id,
+        "Jane Doe",
+        &create_test_timestamp(1990, 3, 14),
+    )
+    .await
+    .unwrap();
+    create_reminder(&db, user.id, "John", &create_test_timestamp(1985, 6, 1))
+        .await
+        .unwrap();
+
+    let reply = execute_command(
+        &db,
+        &user,
+        parse_command("DELETE jane").unwrap(),
+        test_now(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(reply, "Deleted Jane Doe.");
+
+    let reminders = get_reminders_by_user_id(&db, user.id).await.unwrap();
+    assert_eq!(reminders.len(), 1);
+    assert_eq!(reminders[0].name, "John");
+}
+
+#[tokio::test]
+async fn test_ambiguous_delete_asks_for_a_number() {
+    let (db, _temp_file) = setup_test_database().await;
+    let user = create_user(&db, "1234567893").await.unwrap();
+    create_reminder(
+        &db,
+        user.id,
+        "Jane Doe",
+        &create_test_timestamp(1990, 3, 14),
+    )
+    .await
+    .unwrap();
+    create_reminder(
+        &db,
+        user.id,
+        "Jane Smith",
+        &create_test_timestamp(1988, 5, 2),
+    )
+    .await
+    .unwrap();
+
+    let reply = execute_command(
+        &db,
+        &user,
+        parse_command("DELETE Jane").unwrap(),
+        test_now(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        reply,
+        "Which Jane? Reply with a number:\n1) Jane Doe 3/14\n2) Jane Smith 5/2"
+    );
+    assert_eq!(
+        get_reminders_by_user_id(&db, user.id).await.unwrap().len(),
+        2
+    );
+
+    // Out-of-range answers keep the question open
+    let reply = execute_command(&db, &user, SmsCommand::Choose(3), test_now())
+        .await
+        .unwrap();
+    assert_eq!(reply, "Reply with a number from 1 to 2.");
+
+    let reply = execute_command(&db, &user, SmsCommand::Choose(2), test_now())
+        .await
+        .unwrap();
+    assert_eq!(reply, "Deleted Jane Smith.");
+
+    let reminders = get_reminders_by_user_id(&db, user.id).await.unwrap();
+    assert_eq!(reminders.len(), 1);
+    assert_eq!(reminders[0].name, "Jane Doe");
+
+    // The choice is used up
+    let reply = execute_command(&db, &user, SmsCommand::Choose(1), test_now())
+        .await
+        .unwrap();
+    assert_eq!(reply, USAGE_REPLY);
+}
+
+#[tokio::test]
+async fn test_pending_choice_expires() {
+    let (db, _temp_file) = setup_test_database().await;
+    let user = create_user(&db, "1234567894").await.unwrap();
+    create_reminder(
+        &db,
+        user.id,
+        "Jane Doe",
+        &create_test_timestamp(1990, 3, 14),
+    )
+    .await
+    .unwrap();
+    create_reminder(
+        &db,
+        user.id,
+        "Jane Smith",
+        &create_test_timestamp(1988, 5, 2),
+    )
+    .await
+    .unwrap();
+
+    execute_command(
+        &db,
+        &user,
+        parse_command("DELETE Jane").unwrap(),
+        test_now(),
+    )
+    .await
+    .unwrap();
+    let reply = execute_command(
+        &db,
+        &user,
+        SmsCommand::Choose(1),
+        test_now() + Duration::minutes(11),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(reply, USAGE_REPLY);
+    assert_eq!(
+        get_reminders_by_user_id(&db, user.id).await.unwrap().len(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn test_snooze_without_a_digest() {
+    let (db, _temp_file) = setup_test_database().await;
+    let user = create_user(&db, "1234567895").await.unwrap();
+
+    let reply = execute_command(&db, &user, SmsCommand::Snooze { days: 2 }, test_now())
+        .await
+        .unwrap();
+    assert_eq!(reply, "Nothing to snooze yet.");
+}
+
+#[tokio::test]
+async fn test_add_without_year() {
+    let (db, _temp_file) = setup_test_database().await;
+    let user = create_user(&db, "1234567896").await.unwrap();
+
+    let reply = execute_command(
+        &db,
+        &user,
+        parse_command("ADD Jane 1/20").unwrap(),
+        test_now(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(reply, "Added Jane (1/20).");
+
+    let reminders = get_reminders_by_user_id(&db, user.id).await.unwrap();
+    assert!(!reminders[0].year_known);
+}
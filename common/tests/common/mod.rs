@@ -0,0 +1,26 @@
+//! Fixtures shared by the integration tests. Test files pull this in with
+//! `#[path = "common/mod.rs"] mod fixtures;`, since a module named `common`
+//! would shadow the crate.
+
+use chrono::NaiveDate;
+use tempfile::NamedTempFile;
+
+pub async fn setup_test_database() -> (sqlx::SqlitePool, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let database_url = format!("sqlite:{}", temp_file.path().display());
+
+    let db = common::init_database(&database_url).await.unwrap();
+    (db, temp_file)
+}
+
+/// Midnight UTC on the date, as the millisecond string reminders store
+#[allow(dead_code)]
+pub fn create_test_timestamp(year: i32, month: u32, day: u32) -> String {
+    NaiveDate::from_ymd_opt(year, month, day)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc()
+        .timestamp_millis()
+        .to_string()
+}
@@ -0,0 +1,101 @@
+use common::events::{event_line, event_title, parse_event_form, EventType};
+
+#[test]
+fn test_event_titles() {
+    let wedding = EventType::WeddingAnniversary;
+    assert_eq!(
+        event_title("Sam & Alex", &wedding, Some(10)),
+        "Sam & Alex's 10th anniversary"
+    );
+    assert_eq!(
+        event_title("Sam & Alex", &wedding, None),
+        "Sam & Alex's anniversary"
+    );
+    assert_eq!(
+        event_title("Pat", &EventType::WorkAnniversary, Some(1)),
+        "Pat's 1st work anniversary"
+    );
+    assert_eq!(
+        event_title("Dad", &EventType::Memorial, Some(5)),
+        "Remembering Dad - 5 years"
+    );
+    assert_eq!(
+        event_title("Dad", &EventType::Memorial, Some(1)),
+        "Remembering Dad - 1 year"
+    );
+    assert_eq!(
+        event_title(
+            "Rex",
+            &EventType::Custom("adoption day".to_string()),
+            Some(3)
+        ),
+        "Rex's 3rd adoption day"
+    );
+    assert_eq!(
+        event_title("Jane", &EventType::Birthday, Some(34)),
+        "Jane's 34th"
+    );
+    assert_eq!(
+        event_title("Jane", &EventType::Birthday, None),
+        "Jane's birthday"
+    );
+}
+
+#[test]
+fn test_zero_years_has_no_count() {
+    // The first occurrence of an anniversary is the original date
+    assert_eq!(
+        event_title("Sam & Alex", &EventType::WeddingAnniversary, Some(0)),
+        "Sam & Alex's anniversary"
+    );
+    assert_eq!(
+        event_title("Dad", &EventType::Memorial, Some(0)),
+        "Remembering Dad"
+    );
+}
+
+#[test]
+fn test_event_lines() {
+    assert_eq!(
+        event_line("Jane", &EventType::Birthday, Some(34), "today"),
+        "Jane's 34th is today"
+    );
+    assert_eq!(
+        event_line("Dad", &EventType::Memorial, Some(5), "tomorrow"),
+        "Remembering Dad - 5 years tomorrow"
+    );
+}
+
+#[test]
+fn test_event_type_round_trips() {
+    let types = [
+        EventType::Birthday,
+        EventType::WeddingAnniversary,
+        EventType::WorkAnniversary,
+        EventType::Memorial,
+        EventType::Custom("adoption day".to_string()),
+    ];
+    for event_type in types {
+        assert_eq!(
+            EventType::parse(event_type.as_str(), event_type.label()),
+            Some(event_type)
+        );
+    }
+    assert_eq!(EventType::parse("custom", Some("  ")), None);
+    assert_eq!(EventType::parse("graduation", None), None);
+}
+
+#[test]
+fn test_parse_event_form() {
+    assert_eq!(
+        parse_event_form("memorial", "ignored"),
+        Ok(EventType::Memorial)
+    );
+    assert_eq!(
+        parse_event_form("custom", " adoption day "),
+        Ok(EventType::Custom("adoption day".to_string()))
+    );
+    assert!(parse_event_form("custom", "").is_err());
+    assert!(parse_event_form("custom", &"x".repeat(41)).is_err());
+    assert!(parse_event_form("graduation", "").is_err());
+}
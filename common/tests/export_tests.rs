@@ -0,0 +1,129 @@
+#[path = "common/mod.rs"]
+mod fixtures;
+
+use chrono::{NaiveDate, TimeZone, Utc};
+use common::events::EventType;
+use common::export::{build_export, export_json, reminders_csv, settings_csv};
+use common::import::{parse_import, CsvColumns, ImportFormat};
+use common::ledger::{claim_digest, DigestEntry};
+use common::recurrence::Recurrence;
+use common::{
+    create_reminder, create_user, delete_user, get_reminders_by_user_id, get_user_by_id,
+    update_reminder_event_type, update_reminder_notice_offsets, update_reminder_recurrence,
+    update_reminder_year_known,
+};
+use fixtures::{create_test_timestamp, setup_test_database};
+
+#[tokio::test]
+async fn test_export_covers_settings_and_reminders() {
+    let (db, _temp_file) = setup_test_database().await;
+    let user = create_user(&db, "1234567890").await.unwrap();
+
+    let jane = create_reminder(
+        &db,
+        user.id,
+        "Jane, Doe",
+        &create_test_timestamp(1990, 3, 14),
+    )
+    .await
+    .unwrap();
+    update_reminder_notice_offsets(&db, jane.id, Some(&[30, 7]))
+        .await
+        .unwrap();
+    let rex = create_reminder(&db, user.id, "Rex", &create_test_timestamp(2000, 7, 15))
+        .await
+        .unwrap();
+    update_reminder_year_known(&db, rex.id, false)
+        .await
+        .unwrap();
+    update_reminder_event_type(&db, rex.id, &EventType::Custom("adoption day".to_string()))
+        .await
+        .unwrap();
+    update_reminder_recurrence(
+        &db,
+        rex.id,
+        Some(&Recurrence::parse("FREQ=MONTHLY;INTERVAL=6").unwrap()),
+    )
+    .await
+    .unwrap();
+
+    let user = get_user_by_id(&db, user.id).await.unwrap().unwrap();
+    let reminders = get_reminders_by_user_id(&db, user.id).await.unwrap();
+    let now = Utc.with_ymd_and_hms(2024, 1, 15, 15, 0, 0).unwrap();
+    let export = build_export(&user, &reminders, now);
+
+    let json: serde_json::Value = serde_json::from_str(&export_json(&export)).unwrap();
+    assert_eq!(json["settings"]["phone_number"], "1234567890");
+    assert_eq!(json["settings"]["send_hour"], user.send_hour);
+    assert_eq!(json["reminders"][0]["date"], "1990-03-14");
+    assert_eq!(json["reminders"][0]["notice_offsets"][1], 7);
+    assert_eq!(json["reminders"][1]["date"], "--07-15");
+    assert_eq!(json["reminders"][1]["event_label"], "adoption day");
+    assert_eq!(json["reminders"][1]["rrule"], "FREQ=MONTHLY;INTERVAL=6");
+
+    let settings = settings_csv(&export);
+    assert!(settings.starts_with("Setting,Value\n"));
+    assert!(settings.contains("Phone Number,1234567890\n"));
+    assert!(settings.contains("Timezone,America/New_York\n"));
+
+    // The reminders CSV imports back with the default columns
+    let csv = reminders_csv(&export);
+    assert!(csv.contains("\"Jane, Doe\",1990-03-14,birthday,,30 7,"));
+    let parsed = parse_import(ImportFormat::Csv, &csv, &CsvColumns::default()).unwrap();
+    assert!(parsed.skipped.is_empty());
+    assert_eq!(parsed.rows.len(), 2);
+    assert_eq!(parsed.rows[0].name, "Jane, Doe");
+    assert!(!parsed.rows[1].year_known);
+}
+
+#[tokio::test]
+async fn test_delete_user_cascades() {
+    let (db, _temp_file) = setup_test_database().await;
+    let user = create_user(&db, "1234567890").await.unwrap();
+    let other = create_user(&db, "1234567891").await.unwrap();
+
+    let reminder = create_reminder(&db, user.id, "Jane", &create_test_timestamp(1990, 3, 14))
+        .await
+        .unwrap();
+    create_reminder(&db, other.id, "Bob", &create_test_timestamp(1985, 7, 4))
+        .await
+        .unwrap();
+    let entry = DigestEntry {
+        reminder_id: reminder.id,
+        birthday_date: NaiveDate::from_ymd_opt(2024, 3, 14).unwrap(),
+        days_until: 0,
+    };
+    claim_digest(
+        &db,
+        user.id,
+        "test",
+        "Jane's 34th is today",
+        &[entry],
+        Utc.with_ymd_and_hms(2024, 3, 14, 13, 0, 0).unwrap(),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+
+    assert!(delete_user(&db, user.id).await.unwrap());
+    assert!(!delete_user(&db, user.id).await.unwrap());
+
+    assert!(get_user_by_id(&db, user.id).await.unwrap().is_none());
+    assert!(get_reminders_by_user_id(&db, user.id)
+        .await
+        .unwrap()
+        .is_empty());
+    for table in ["sent_messages", "sent_message_reminders"] {
+        let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(count, 0, "{} should be empty", table);
+    }
+
+    // Other accounts are untouched
+    assert_eq!(
+        get_reminders_by_user_id(&db, other.id).await.unwrap().len(),
+        1
+    );
+}
@@ -0,0 +1,172 @@
+use chrono::{NaiveDate, TimeZone, Utc};
+use common::birthday::LeapDayPolicy;
+use common::calendars::{CalendarDate, CalendarSystem};
+use common::events::EventType;
+use common::ics::{
+    alarm_trigger, escape_text, get_or_create_ics_token, get_user_id_by_ics_token, render_feed,
+    rotate_ics_token,
+};
+use common::recurrence::Recurrence;
+use common::{create_user, init_database, DbReminder, DbUser};
+use tempfile::NamedTempFile;
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+fn test_user() -> DbUser {
+    DbUser {
+        id: 1,
+        phone_number: "1234567890".to_string(),
+        created_at: "2024-01-01 00:00:00".to_string(),
+        last_digest_at: None,
+        notice_offsets: vec![7, 0],
+        send_hour: 9,
+        iana_tz: "America/New_York".to_string(),
+        leap_day_policy: LeapDayPolicy::Feb28,
+        sms_opted_out_at: None,
+    }
+}
+
+fn reminder(id: i64, name: &str, birthdate: NaiveDate) -> DbReminder {
+    DbReminder {
+        id,
+        user_id: 1,
+        name: name.to_string(),
+        birthdate: birthdate
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc()
+            .timestamp_millis(),
+        year_known: true,
+        notice_offsets: None,
+        event_type: EventType::Birthday,
+        recurrence: None,
+        calendar_date: None,
+        created_at: "2024-01-01 00:00:00".to_string(),
+        updated_at: "2024-01-01 00:00:00".to_string(),
+    }
+}
+
+fn feed(user: &DbUser, reminders: &[DbReminder]) -> String {
+    render_feed(
+        user,
+        reminders,
+        Utc.with_ymd_and_hms(2024, 1, 15, 15, 0, 0).unwrap(),
+    )
+}
+
+#[test]
+fn test_yearly_event_with_age_overrides() {
+    let feed = feed(&test_user(), &[reminder(42, "Jane", date(1990, 3, 14))]);
+
+    assert!(feed.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+    assert!(feed.ends_with("END:VCALENDAR\r\n"));
+    assert!(!feed.replace("\r\n", "").contains('\n'));
+
+    assert!(feed.contains("UID:reminder-42@hbd.bot\r\n"));
+    assert!(feed.contains("DTSTART;VALUE=DATE:19900314\r\nRRULE:FREQ=YEARLY\r\n"));
+    assert!(feed.contains("SUMMARY:Jane's birthday\r\n"));
+    assert!(feed.contains(
+        "DTSTART;VALUE=DATE:20240314\r\nRECURRENCE-ID;VALUE=DATE:20240314\r\nSUMMARY:Jane's 34th birthday\r\n"
+    ));
+    assert!(feed.contains("SUMMARY:Jane's 36th birthday\r\n"));
+    assert!(!feed.contains("37th"));
+
+    // Two alarms on each of the master event and its three overrides
+    assert_eq!(feed.matches("BEGIN:VALARM").count(), 8);
+    assert!(feed.contains("TRIGGER:-P6DT15H\r\n"));
+    assert!(feed.contains("TRIGGER:PT9H\r\n"));
+}
+
+#[test]
+fn test_unknown_year_has_no_age() {
+    let mut reminder = reminder(1, "Jane", date(2000, 3, 14));
+    reminder.year_known = false;
+    reminder.notice_offsets = Some(vec![3]);
+    let feed = feed(&test_user(), &[reminder]);
+
+    assert!(!feed.contains("RECURRENCE-ID"));
+    assert_eq!(feed.matches("BEGIN:VEVENT").count(), 1);
+    assert_eq!(feed.matches("BEGIN:VALARM").count(), 1);
+}
+
+#[test]
+fn test_alarm_triggers() {
+    assert_eq!(alarm_trigger(7, 9), "-P6DT15H");
+    assert_eq!(alarm_trigger(0, 9), "PT9H");
+    assert_eq!(alarm_trigger(1, 0), "-P1D");
+    assert_eq!(alarm_trigger(0, 0), "PT0S");
+}
+
+#[test]
+fn test_leap_day_rules_follow_policy() {
+    let leapling = [reminder(1, "Leo", date(1996, 2, 29))];
+    assert!(feed(&test_user(), &leapling).contains("RRULE:FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1\r\n"));
+
+    let mut user = test_user();
+    user.leap_day_policy = LeapDayPolicy::Mar1;
+    let feed = feed(&user, &leapling);
+    assert!(feed.contains("RRULE:FREQ=YEARLY;BYYEARDAY=60\r\n"));
+    // 2025 is a common year
+    assert!(feed.contains("RECURRENCE-ID;VALUE=DATE:20250301\r\n"));
+}
+
+#[test]
+fn test_rules_and_lunar_dates() {
+    let mut rex = reminder(1, "Rex", date(2020, 7, 15));
+    rex.event_type = EventType::Custom("half-birthday".to_string());
+    rex.recurrence = Some(Recurrence::parse("FREQ=MONTHLY;INTERVAL=6").unwrap());
+
+    let mut grandma = reminder(2, "Grandma", date(1950, 2, 17));
+    grandma.calendar_date =
+        CalendarDate::from_gregorian(CalendarSystem::Chinese, date(2023, 1, 22));
+
+    let feed = feed(&test_user(), &[rex, grandma]);
+    assert!(feed.contains("RRULE:FREQ=MONTHLY;INTERVAL=6\r\nSUMMARY:Rex's half-birthday\r\n"));
+
+    // Lunar New Year 2024 and 2025, one event each
+    assert!(feed.contains("UID:reminder-2-2024@hbd.bot\r\n"));
+    assert!(feed.contains("DTSTART;VALUE=DATE:20240210\r\n"));
+    assert!(feed.contains("UID:reminder-2-2025@hbd.bot\r\n"));
+    assert!(feed.contains("DTSTART;VALUE=DATE:20250129\r\n"));
+    assert!(!feed.contains("UID:reminder-2@hbd.bot"));
+}
+
+#[test]
+fn test_escaping_and_folding() {
+    assert_eq!(escape_text("a, b; c\\d\ne"), "a\\, b\\; c\\\\d\\ne");
+
+    let name = "Ünïcödé ".repeat(12);
+    let feed = feed(&test_user(), &[reminder(1, &name, date(1990, 3, 14))]);
+    for line in feed.split("\r\n") {
+        assert!(line.len() <= 75, "line too long: {:?}", line);
+    }
+    let unfolded = feed.replace("\r\n ", "");
+    assert!(unfolded.contains(&format!("SUMMARY:{}'s birthday\r\n", name)));
+}
+
+#[tokio::test]
+async fn test_token_lookup_and_rotation() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = init_database(&format!("sqlite:{}", temp_file.path().display()))
+        .await
+        .unwrap();
+    let user = create_user(&db, "1234567890").await.unwrap();
+
+    let token = get_or_create_ics_token(&db, user.id).await.unwrap();
+    assert_eq!(token.len(), 48);
+    assert_eq!(get_or_create_ics_token(&db, user.id).await.unwrap(), token);
+    assert_eq!(
+        get_user_id_by_ics_token(&db, &token).await.unwrap(),
+        Some(user.id)
+    );
+
+    let rotated = rotate_ics_token(&db, user.id).await.unwrap();
+    assert_ne!(rotated, token);
+    assert_eq!(get_user_id_by_ics_token(&db, &token).await.unwrap(), None);
+    assert_eq!(
+        get_user_id_by_ics_token(&db, &rotated).await.unwrap(),
+        Some(user.id)
+    );
+}
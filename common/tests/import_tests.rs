@@ -0,0 +1,257 @@
+use chrono::NaiveDate;
+use common::events::EventType;
+use common::import::{
+    find_duplicates, import_reminders, parse_csv, parse_ics_events, parse_import,
+    parse_import_date, parse_vcards, CsvColumns, ImportFormat, ImportRow,
+};
+use common::{create_reminder, create_user, get_reminders_by_user_id, init_database};
+use tempfile::NamedTempFile;
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+fn birthday(name: &str, birthdate: NaiveDate, year_known: bool) -> ImportRow {
+    ImportRow {
+        name: name.to_string(),
+        birthdate,
+        year_known,
+        event_type: EventType::Birthday,
+    }
+}
+
+#[test]
+fn test_import_dates() {
+    assert_eq!(
+        parse_import_date("1990-03-14"),
+        Some((date(1990, 3, 14), true))
+    );
+    assert_eq!(
+        parse_import_date("19900314"),
+        Some((date(1990, 3, 14), true))
+    );
+    assert_eq!(
+        parse_import_date("3/14/1990"),
+        Some((date(1990, 3, 14), true))
+    );
+    assert_eq!(
+        parse_import_date("--03-14"),
+        Some((date(2000, 3, 14), false))
+    );
+    assert_eq!(
+        parse_import_date("--0229"),
+        Some((date(2000, 2, 29), false))
+    );
+    assert_eq!(parse_import_date("3/14"), Some((date(2000, 3, 14), false)));
+    // Apple Contacts' placeholder year
+    assert_eq!(
+        parse_import_date("1604-03-14"),
+        Some((date(2000, 3, 14), false))
+    );
+    assert_eq!(parse_import_date("--13-01"), None);
+    assert_eq!(parse_import_date("March 14"), None);
+}
+
+#[test]
+fn test_csv_columns_by_name_and_number() {
+    let content = "\
+First Name,Last Name,Birthday,Notes
+Jane,Doe,1990-03-14,\"likes cake, a lot\"
+Bob,,--07-04,
+Carol,King,,
+,,1985-01-01,
+Dan,Ray,someday,
+";
+    let columns = CsvColumns {
+        name: "first name + Last Name".to_string(),
+        date: "Birthday".to_string(),
+    };
+    let parsed = parse_csv(content, &columns).unwrap();
+    assert_eq!(
+        parsed.rows,
+        vec![
+            birthday("Jane Doe", date(1990, 3, 14), true),
+            birthday("Bob", date(2000, 7, 4), false),
+        ]
+    );
+    // Carol has no date and is passed over without a note
+    assert_eq!(parsed.skipped.len(), 2);
+    assert!(parsed.skipped[0].starts_with("Row 5:"));
+    assert!(parsed.skipped[1].contains("\"someday\" for Dan Ray"));
+
+    // Without headers, by column number
+    let parsed = parse_csv(
+        "Jane,3/14/1990\nBob,7/4\n",
+        &CsvColumns {
+            name: "1".to_string(),
+            date: "2".to_string(),
+        },
+    )
+    .unwrap();
+    assert_eq!(parsed.rows.len(), 2);
+
+    let missing = CsvColumns {
+        name: "Name".to_string(),
+        date: "DOB".to_string(),
+    };
+    assert!(parse_csv(content, &missing).is_err());
+}
+
+#[test]
+fn test_vcards() {
+    let content = "\
+BEGIN:VCARD\r
+VERSION:3.0\r
+FN:Jane Doe\r
+item1.BDAY;VALUE=date:1990-03-14\r
+END:VCARD\r
+BEGIN:VCARD\r
+VERSION:4.0\r
+N:Smith;Sam;;;\r
+BDAY:--0704\r
+ANNIVERSARY:20150620\r
+END:VCARD\r
+BEGIN:VCARD\r
+VERSION:3.0\r
+FN:No Birthday\r
+TEL:555-0100\r
+END:VCARD\r
+BEGIN:VCARD\r
+VERSION:3.0\r
+FN:Alex\r
+ andra Lee\r
+BDAY;X-APPLE-OMIT-YEAR=1604:1604-11-02\r
+END:VCARD\r
+";
+    let parsed = parse_vcards(content);
+    assert!(parsed.skipped.is_empty());
+    assert_eq!(
+        parsed.rows,
+        vec![
+            birthday("Jane Doe", date(1990, 3, 14), true),
+            birthday("Sam Smith", date(2000, 7, 4), false),
+            ImportRow {
+                name: "Sam Smith".to_string(),
+                birthdate: date(2015, 6, 20),
+                year_known: true,
+                event_type: EventType::WeddingAnniversary,
+            },
+            birthday("Alexandra Lee", date(2000, 11, 2), false),
+        ]
+    );
+}
+
+#[test]
+fn test_ics_yearly_events() {
+    let content = "\
+BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+UID:1\r
+DTSTART;VALUE=DATE:19900314\r
+RRULE:FREQ=YEARLY\r
+SUMMARY:Jane Doe's birthday\r
+BEGIN:VALARM\r
+TRIGGER:-P1D\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:1\r
+RECURRENCE-ID;VALUE=DATE:20240314\r
+DTSTART;VALUE=DATE:20240314\r
+SUMMARY:Jane Doe's 34th birthday\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:2\r
+DTSTART:20150620T000000Z\r
+RRULE:FREQ=YEARLY;BYMONTH=6\r
+SUMMARY:Sam & Alex's Anniversary\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:3\r
+DTSTART:20240105T150000Z\r
+SUMMARY:Dentist\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:4\r
+DTSTART;VALUE=DATE:19880909\r
+RRULE:FREQ=YEARLY\r
+SUMMARY:Birthday: Pat\\, Jr.\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+    let parsed = parse_ics_events(content);
+    assert!(parsed.skipped.is_empty());
+    assert_eq!(
+        parsed.rows,
+        vec![
+            birthday("Jane Doe", date(1990, 3, 14), true),
+            ImportRow {
+                name: "Sam & Alex".to_string(),
+                birthdate: date(2015, 6, 20),
+                year_known: true,
+                event_type: EventType::WeddingAnniversary,
+            },
+            birthday("Pat, Jr.", date(1988, 9, 9), true),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_duplicates_and_transactional_import() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = init_database(&format!("sqlite:{}", temp_file.path().display()))
+        .await
+        .unwrap();
+    let user = create_user(&db, "1234567890").await.unwrap();
+    let timestamp = date(1990, 3, 14)
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc()
+        .timestamp_millis()
+        .to_string();
+    create_reminder(&db, user.id, "Jane Doe", &timestamp)
+        .await
+        .unwrap();
+
+    let parsed = parse_import(
+        ImportFormat::Csv,
+        "Name,Birthday\njane  doe,--03-14\nBob,1985-07-04\nbob,7/4\nJane Doe,1990-03-15\n",
+        &CsvColumns::default(),
+    )
+    .unwrap();
+    let existing = get_reminders_by_user_id(&db, user.id).await.unwrap();
+    assert_eq!(
+        find_duplicates(&existing, &parsed.rows),
+        vec![true, false, true, false]
+    );
+
+    let imported = import_reminders(&db, user.id, &parsed.rows[1..2])
+        .await
+        .unwrap();
+    assert_eq!(imported, 1);
+    let reminders = get_reminders_by_user_id(&db, user.id).await.unwrap();
+    assert_eq!(reminders.len(), 2);
+    assert_eq!(reminders[1].name, "Bob");
+    assert!(reminders[1].year_known);
+
+    // Confirming the whole preview afterwards only adds what's still new
+    let imported = import_reminders(&db, user.id, &parsed.rows).await.unwrap();
+    assert_eq!(imported, 1);
+    let reminders = get_reminders_by_user_id(&db, user.id).await.unwrap();
+    assert_eq!(reminders.len(), 3);
+    assert_eq!(reminders[2].name, "Jane Doe");
+
+    // A failure part-way through leaves nothing behind
+    let other = create_user(&db, "1234567891").await.unwrap();
+    let mut rows = parsed.rows.clone();
+    rows[2].name = String::new();
+    sqlx::query("CREATE TRIGGER no_blank_names BEFORE INSERT ON reminders WHEN NEW.name = '' BEGIN SELECT RAISE(ABORT, 'blank name'); END")
+        .execute(&db)
+        .await
+        .unwrap();
+    assert!(import_reminders(&db, other.id, &rows).await.is_err());
+    assert!(get_reminders_by_user_id(&db, other.id)
+        .await
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,89 @@
+use common::inbound::{
+    normalize_phone_number, parse_compliance_keyword, sign_payload, verify_signature,
+    ComplianceKeyword, SIGNATURE_TOLERANCE_SECS,
+};
+
+const SECRET: &str = "test-secret";
+const NOW: i64 = 1_700_000_000;
+
+#[test]
+fn test_parse_compliance_keywords() {
+    assert_eq!(
+        parse_compliance_keyword("STOP"),
+        Some(ComplianceKeyword::Stop)
+    );
+    assert_eq!(
+        parse_compliance_keyword("  unsubscribe\n"),
+        Some(ComplianceKeyword::Stop)
+    );
+    assert_eq!(
+        parse_compliance_keyword("Quit."),
+        Some(ComplianceKeyword::Stop)
+    );
+    assert_eq!(
+        parse_compliance_keyword("start"),
+        Some(ComplianceKeyword::Start)
+    );
+    assert_eq!(
+        parse_compliance_keyword("UNSTOP"),
+        Some(ComplianceKeyword::Start)
+    );
+    assert_eq!(
+        parse_compliance_keyword("Help!"),
+        Some(ComplianceKeyword::Help)
+    );
+    assert_eq!(parse_compliance_keyword("please stop"), None);
+    assert_eq!(parse_compliance_keyword(""), None);
+}
+
+#[test]
+fn test_normalize_phone_number() {
+    assert_eq!(
+        normalize_phone_number("+15551234567").as_deref(),
+        Some("5551234567")
+    );
+    assert_eq!(
+        normalize_phone_number("(555) 123-4567").as_deref(),
+        Some("5551234567")
+    );
+    assert_eq!(normalize_phone_number("+445551234567"), None);
+    assert_eq!(normalize_phone_number("12345"), None);
+}
+
+#[test]
+fn test_signature_round_trip() {
+    let body = br#"{"from":"+15551234567","body":"STOP"}"#;
+    let header = sign_payload(SECRET, NOW, body);
+
+    assert!(verify_signature(SECRET, &header, body, NOW));
+    assert!(verify_signature(SECRET, &header, body, NOW + 60));
+}
+
+#[test]
+fn test_signature_rejects_tampering() {
+    let body = br#"{"from":"+15551234567","body":"STOP"}"#;
+    let header = sign_payload(SECRET, NOW, body);
+
+    assert!(!verify_signature("other-secret", &header, body, NOW));
+    assert!(!verify_signature(
+        SECRET,
+        &header,
+        br#"{"from":"+15551234568","body":"STOP"}"#,
+        NOW
+    ));
+    assert!(!verify_signature(SECRET, "", body, NOW));
+    assert!(!verify_signature(SECRET, "t=abc,v1=zz", body, NOW));
+}
+
+#[test]
+fn test_signature_rejects_stale_timestamp() {
+    let body = b"{}";
+    let header = sign_payload(SECRET, NOW, body);
+
+    assert!(!verify_signature(
+        SECRET,
+        &header,
+        body,
+        NOW + SIGNATURE_TOLERANCE_SECS + 1
+    ));
+}
@@ -0,0 +1,48 @@
+use common::notice::{
+    describe_notice_offsets, format_notice_offsets, parse_notice_offsets, parse_notice_override,
+    MAX_NOTICE_DAYS,
+};
+use common::{create_user, init_database};
+use tempfile::NamedTempFile;
+
+#[test]
+fn test_parse_notice_offsets() {
+    assert_eq!(parse_notice_offsets("14, 3, 0"), Ok(vec![14, 3, 0]));
+    assert_eq!(parse_notice_offsets("0 3 14 3"), Ok(vec![14, 3, 0]));
+    assert_eq!(parse_notice_offsets("7"), Ok(vec![7]));
+
+    assert!(parse_notice_offsets("").is_err());
+    assert!(parse_notice_offsets("1, two").is_err());
+    assert!(parse_notice_offsets("-1").is_err());
+    assert!(parse_notice_offsets(&(MAX_NOTICE_DAYS + 1).to_string()).is_err());
+    assert!(parse_notice_offsets("1,2,3,4,5,6").is_err());
+}
+
+#[test]
+fn test_parse_notice_override() {
+    assert_eq!(parse_notice_override("  "), Ok(None));
+    assert_eq!(parse_notice_override("30, 7"), Ok(Some(vec![30, 7])));
+    assert!(parse_notice_override("soon").is_err());
+}
+
+#[test]
+fn test_format_and_describe_notice_offsets() {
+    assert_eq!(format_notice_offsets(&[14, 3, 0]), "14,3,0");
+    assert_eq!(
+        describe_notice_offsets(&[14, 3, 0]),
+        "14 days, 3 days and day-of"
+    );
+    assert_eq!(describe_notice_offsets(&[1, 0]), "1 day and day-of");
+    assert_eq!(describe_notice_offsets(&[0]), "day-of");
+}
+
+#[tokio::test]
+async fn test_new_users_get_default_offsets() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = init_database(&format!("sqlite:{}", temp_file.path().display()))
+        .await
+        .unwrap();
+
+    let user = create_user(&db, "1234567890").await.unwrap();
+    assert_eq!(user.notice_offsets, vec![7, 1, 0]);
+}
@@ -0,0 +1,183 @@
+#[path = "common/mod.rs"]
+mod fixtures;
+
+use chrono::{Duration, TimeZone, Utc};
+use common::otp_sessions::{
+    begin_otp_attempt, create_otp_session, delete_otp_session, DbOtpSession, OtpAttempt,
+    OtpPurpose, MAX_OTP_ATTEMPTS,
+};
+use fixtures::setup_test_database;
+
+fn allowed(attempt: OtpAttempt) -> DbOtpSession {
+    match attempt {
+        OtpAttempt::Allowed(session) => session,
+        OtpAttempt::Expired => panic!("session expired"),
+        OtpAttempt::TooManyAttempts => panic!("too many attempts"),
+    }
+}
+
+#[tokio::test]
+async fn test_sessions_only_serve_their_purpose() {
+    let (db, _temp_file) = setup_test_database().await;
+    let now = Utc.with_ymd_and_hms(2024, 3, 14, 15, 0, 0).unwrap();
+
+    let login_session = create_otp_session(
+        &db,
+        OtpPurpose::Login,
+        "5550000001",
+        "verification-1",
+        "encrypted-token",
+        now + Duration::minutes(10),
+        now,
+    )
+    .await
+    .unwrap();
+
+    // A login session can't confirm an account deletion, or vice versa
+    assert!(matches!(
+        begin_otp_attempt(&db, &login_session, OtpPurpose::DeleteAccount, now)
+            .await
+            .unwrap(),
+        OtpAttempt::Expired
+    ));
+    let session = allowed(
+        begin_otp_attempt(&db, &login_session, OtpPurpose::Login, now)
+            .await
+            .unwrap(),
+    );
+    assert_eq!(session.phone_number, "5550000001");
+    assert_eq!(session.verification_id, "verification-1");
+}
+
+#[tokio::test]
+async fn test_attempts_are_limited() {
+    let (db, _temp_file) = setup_test_database().await;
+    let now = Utc.with_ymd_and_hms(2024, 3, 14, 15, 0, 0).unwrap();
+
+    let session_id = create_otp_session(
+        &db,
+        OtpPurpose::Login,
+        "5550000001",
+        "verification-1",
+        "encrypted-token",
+        now + Duration::minutes(10),
+        now,
+    )
+    .await
+    .unwrap();
+
+    for attempt in 1..=MAX_OTP_ATTEMPTS {
+        let session = allowed(
+            begin_otp_attempt(&db, &session_id, OtpPurpose::Login, now)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(session.attempts, attempt);
+    }
+    assert!(matches!(
+        begin_otp_attempt(&db, &session_id, OtpPurpose::Login, now)
+            .await
+            .unwrap(),
+        OtpAttempt::TooManyAttempts
+    ));
+}
+
+#[tokio::test]
+async fn test_sessions_expire_and_are_single_use() {
+    let (db, _temp_file) = setup_test_database().await;
+    let now = Utc.with_ymd_and_hms(2024, 3, 14, 15, 0, 0).unwrap();
+
+    let session_id = create_otp_session(
+        &db,
+        OtpPurpose::Login,
+        "5550000001",
+        "verification-1",
+        "encrypted-token",
+        now + Duration::minutes(10),
+        now,
+    )
+    .await
+    .unwrap();
+
+    let later = now + Duration::minutes(11);
+    assert!(matches!(
+        begin_otp_attempt(&db, &session_id, OtpPurpose::Login, later)
+            .await
+            .unwrap(),
+        OtpAttempt::Expired
+    ));
+
+    // Starting another session clears out the expired one
+    let next_id = create_otp_session(
+        &db,
+        OtpPurpose::Login,
+        "5550000001",
+        "verification-2",
+        "encrypted-token",
+        later + Duration::minutes(10),
+        later,
+    )
+    .await
+    .unwrap();
+    assert_ne!(next_id, session_id);
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM otp_sessions")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+
+    allowed(
+        begin_otp_attempt(&db, &next_id, OtpPurpose::Login, later)
+            .await
+            .unwrap(),
+    );
+    delete_otp_session(&db, &next_id).await.unwrap();
+    assert!(matches!(
+        begin_otp_attempt(&db, &next_id, OtpPurpose::Login, later)
+            .await
+            .unwrap(),
+        OtpAttempt::Expired
+    ));
+    assert!(matches!(
+        begin_otp_attempt(&db, "not-a-session", OtpPurpose::Login, later)
+            .await
+            .unwrap(),
+        OtpAttempt::Expired
+    ));
+}
+
+#[tokio::test]
+async fn test_attempts_are_committed() {
+    let (db, temp_file) = setup_test_database().await;
+    let now = Utc.with_ymd_and_hms(2024, 3, 14, 15, 0, 0).unwrap();
+
+    let session_id = create_otp_session(
+        &db,
+        OtpPurpose::Login,
+        "5550000001",
+        "verification-1",
+        "encrypted-token",
+        now + Duration::minutes(10),
+        now,
+    )
+    .await
+    .unwrap();
+    for _ in 0..3 {
+        allowed(
+            begin_otp_attempt(&db, &session_id, OtpPurpose::Login, now)
+                .await
+                .unwrap(),
+        );
+    }
+
+    // A separate connection only sees what was committed
+    let other = sqlx::SqlitePool::connect(&format!("sqlite:{}", temp_file.path().display()))
+        .await
+        .unwrap();
+    let attempts: i64 = sqlx::query_scalar("SELECT attempts FROM otp_sessions WHERE id = ?")
+        .bind(&session_id)
+        .fetch_one(&other)
+        .await
+        .unwrap();
+    assert_eq!(attempts, 3);
+}
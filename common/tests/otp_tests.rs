@@ -0,0 +1,170 @@
+#[path = "common/mod.rs"]
+mod fixtures;
+
+use chrono::{Duration, TimeZone, Utc};
+use common::otp::{
+    check_local_code, generate_code, store_local_code, LocalOtpProvider, OtpKeys, OtpProvider,
+    LOCAL_OTP_VALIDITY_MINUTES, MAX_LOCAL_OTP_ATTEMPTS,
+};
+use common::sms::MemorySender;
+use fixtures::setup_test_database;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::sync::Arc;
+
+const SECRET: &str = "test-secret";
+
+fn keys() -> OtpKeys {
+    OtpKeys::new(SECRET, &[])
+}
+
+#[test]
+fn test_generate_code() {
+    for _ in 0..50 {
+        let code = generate_code();
+        assert_eq!(code.len(), 6);
+        assert!(code.chars().all(|c| c.is_ascii_digit()));
+    }
+}
+
+#[tokio::test]
+async fn test_local_provider_texts_a_single_use_code() {
+    let (db, _temp_file) = setup_test_database().await;
+    let sms = Arc::new(MemorySender::new());
+    let provider = LocalOtpProvider::new(db.clone(), sms.clone(), keys());
+
+    let challenge = provider.send_code("5551234567").await.unwrap();
+    assert_eq!(challenge.validity_seconds, LOCAL_OTP_VALIDITY_MINUTES * 60);
+    assert!(challenge.provider_token.is_empty());
+
+    let sent = sms.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to, "+15551234567");
+    let code: String = sent[0]
+        .body
+        .chars()
+        .filter(|c| c.is_ascii_digit())
+        .take(6)
+        .collect();
+
+    // Only a keyed hash is stored
+    let stored: String = sqlx::query_scalar("SELECT code_hash FROM local_otp_codes WHERE id = ?")
+        .bind(&challenge.verification_id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert!(!stored.contains(&code));
+
+    assert!(provider
+        .check_code(&challenge.verification_id, "", "000000x")
+        .await
+        .is_err());
+    provider
+        .check_code(&challenge.verification_id, "", &code)
+        .await
+        .unwrap();
+    assert!(provider
+        .check_code(&challenge.verification_id, "", &code)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_local_code_attempts_and_expiry() {
+    let (db, _temp_file) = setup_test_database().await;
+    let now = Utc.with_ymd_and_hms(2024, 3, 14, 15, 0, 0).unwrap();
+
+    let guessed = store_local_code(&db, &keys(), "5551234567", "123456", now)
+        .await
+        .unwrap();
+    for _ in 0..MAX_LOCAL_OTP_ATTEMPTS {
+        assert!(!check_local_code(&db, &keys(), &guessed, "654321", now)
+            .await
+            .unwrap());
+    }
+    // Out of attempts, so even the right code is refused
+    assert!(!check_local_code(&db, &keys(), &guessed, "123456", now)
+        .await
+        .unwrap());
+
+    let expiring = store_local_code(&db, &keys(), "5551234567", "123456", now)
+        .await
+        .unwrap();
+    let later = now + Duration::minutes(LOCAL_OTP_VALIDITY_MINUTES + 1);
+    assert!(!check_local_code(&db, &keys(), &expiring, "123456", later)
+        .await
+        .unwrap());
+
+    // A different key can't match the stored hash
+    let fresh = store_local_code(&db, &keys(), "5551234567", "123456", later)
+        .await
+        .unwrap();
+    assert!(!check_local_code(
+        &db,
+        &OtpKeys::new("other-secret", &[]),
+        &fresh,
+        "123456",
+        later
+    )
+    .await
+    .unwrap());
+    assert!(check_local_code(&db, &keys(), &fresh, "123456", later)
+        .await
+        .unwrap());
+
+    // Expired codes were cleared out when the fresh one was stored
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM local_otp_codes")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn test_concurrent_checks_use_a_code_once() {
+    let (db, _temp_file) = setup_test_database().await;
+    let now = Utc.with_ymd_and_hms(2024, 3, 14, 15, 0, 0).unwrap();
+
+    let verification_id = store_local_code(&db, &keys(), "5551234567", "123456", now)
+        .await
+        .unwrap();
+    let keys = keys();
+    let (first, second) = tokio::join!(
+        check_local_code(&db, &keys, &verification_id, "123456", now),
+        check_local_code(&db, &keys, &verification_id, "123456", now),
+    );
+    let matches = [first.unwrap(), second.unwrap()];
+    assert_eq!(matches.iter().filter(|matched| **matched).count(), 1);
+}
+
+#[tokio::test]
+async fn test_codes_survive_a_secret_rotation() {
+    let (db, _temp_file) = setup_test_database().await;
+    let now = Utc.with_ymd_and_hms(2024, 3, 14, 15, 0, 0).unwrap();
+
+    let verification_id = store_local_code(&db, &keys(), "5551234567", "123456", now)
+        .await
+        .unwrap();
+
+    // The secret is only an input to the key, not the key itself
+    let raw_hash = hex::encode(
+        Hmac::<Sha256>::new_from_slice(SECRET.as_bytes())
+            .unwrap()
+            .chain_update(format!("{}:123456", verification_id))
+            .finalize()
+            .into_bytes(),
+    );
+    let stored: String = sqlx::query_scalar("SELECT code_hash FROM local_otp_codes WHERE id = ?")
+        .bind(&verification_id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_ne!(stored, raw_hash);
+
+    let rotated = OtpKeys::new("new-secret", &[SECRET.to_string()]);
+    assert!(
+        check_local_code(&db, &rotated, &verification_id, "123456", now)
+            .await
+            .unwrap()
+    );
+}
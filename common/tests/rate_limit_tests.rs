@@ -0,0 +1,202 @@
+#[path = "common/mod.rs"]
+mod fixtures;
+
+use chrono::{Duration, TimeZone, Utc};
+use common::rate_limit::{
+    check_login_limits, normalize_login_phone, LoginLimits, LoginRefusalKind, TokenBucket,
+};
+use fixtures::setup_test_database;
+
+#[test]
+fn test_normalize_login_phone() {
+    let us = vec!["1".to_string()];
+    assert_eq!(
+        normalize_login_phone("5551234567", &us).unwrap(),
+        "5551234567"
+    );
+    assert_eq!(
+        normalize_login_phone("15551234567", &us).unwrap(),
+        "5551234567"
+    );
+    assert_eq!(
+        normalize_login_phone("+1 (555) 123-4567", &us).unwrap(),
+        "5551234567"
+    );
+
+    let refused =
+        |input: &str, codes: &[String]| normalize_login_phone(input, codes).unwrap_err().kind;
+    assert_eq!(refused("", &us), LoginRefusalKind::InvalidPhone);
+    assert_eq!(refused("555123", &us), LoginRefusalKind::InvalidPhone);
+    assert_eq!(
+        refused("+447911123456", &us),
+        LoginRefusalKind::CountryNotAllowed
+    );
+    assert_eq!(
+        refused("+5551234567", &us),
+        LoginRefusalKind::CountryNotAllowed
+    );
+
+    // Allowing a country we can't store numbers for still refuses them
+    let uk = vec!["1".to_string(), "44".to_string()];
+    assert_eq!(
+        refused("+447911123456", &uk),
+        LoginRefusalKind::InvalidPhone
+    );
+}
+
+#[tokio::test]
+async fn test_phone_bucket_throttles_and_refills() {
+    let (db, _temp_file) = setup_test_database().await;
+    let limits = LoginLimits::default();
+    let now = Utc.with_ymd_and_hms(2024, 3, 14, 15, 0, 0).unwrap();
+
+    for _ in 0..3 {
+        assert!(
+            check_login_limits(&db, &limits, "5551234567", Some("10.0.0.1"), now)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+    let refusal = check_login_limits(&db, &limits, "5551234567", Some("10.0.0.1"), now)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(refusal.kind, LoginRefusalKind::PhoneThrottled);
+    // 5 tokens an hour is one every 12 minutes
+    assert_eq!(refusal.retry_after_seconds, Some(12 * 60));
+
+    // Another phone from the same address is unaffected
+    assert!(
+        check_login_limits(&db, &limits, "5559876543", Some("10.0.0.1"), now)
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    let later = now + Duration::minutes(12);
+    assert!(
+        check_login_limits(&db, &limits, "5551234567", Some("10.0.0.1"), later)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        check_login_limits(&db, &limits, "5551234567", Some("10.0.0.1"), later)
+            .await
+            .unwrap()
+            .is_some()
+    );
+}
+
+#[tokio::test]
+async fn test_refusal_charges_no_other_limit() {
+    let (db, _temp_file) = setup_test_database().await;
+    let limits = LoginLimits {
+        per_ip: TokenBucket {
+            capacity: 2.0,
+            per_hour: 1.0,
+        },
+        ..LoginLimits::default()
+    };
+    let now = Utc.with_ymd_and_hms(2024, 3, 14, 15, 0, 0).unwrap();
+
+    for phone in ["5550000001", "5550000002"] {
+        assert!(
+            check_login_limits(&db, &limits, phone, Some("10.0.0.1"), now)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    // The address is out, so this phone's bucket isn't touched and it can
+    // still get its full allowance from elsewhere
+    for _ in 0..3 {
+        let refusal = check_login_limits(&db, &limits, "5550000003", Some("10.0.0.1"), now)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(refusal.kind, LoginRefusalKind::IpThrottled);
+    }
+    for ip in ["10.0.0.2", "10.0.0.3", "10.0.0.4"] {
+        assert!(
+            check_login_limits(&db, &limits, "5550000003", Some(ip), now)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    let sent: i64 = sqlx::query_scalar("SELECT sent FROM otp_daily_counts")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(sent, 5);
+}
+
+#[tokio::test]
+async fn test_daily_budget() {
+    let (db, _temp_file) = setup_test_database().await;
+    let limits = LoginLimits {
+        daily_budget: 2,
+        ..LoginLimits::default()
+    };
+    let now = Utc.with_ymd_and_hms(2024, 3, 14, 18, 0, 0).unwrap();
+
+    for phone in ["5550000001", "5550000002"] {
+        assert!(check_login_limits(&db, &limits, phone, None, now)
+            .await
+            .unwrap()
+            .is_none());
+    }
+    let refusal = check_login_limits(&db, &limits, "5550000003", None, now)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(refusal.kind, LoginRefusalKind::DailyBudgetExhausted);
+    assert_eq!(refusal.retry_after_seconds, Some(6 * 3600));
+
+    // The refused request didn't use up this phone's bucket
+    let tomorrow = now + Duration::hours(6);
+    for _ in 0..2 {
+        assert!(
+            check_login_limits(&db, &limits, "5550000003", None, tomorrow)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_refilled_buckets_and_past_days_are_pruned() {
+    let (db, _temp_file) = setup_test_database().await;
+    let limits = LoginLimits::default();
+    let now = Utc.with_ymd_and_hms(2024, 3, 14, 23, 30, 0).unwrap();
+
+    assert!(
+        check_login_limits(&db, &limits, "5550000001", Some("10.0.0.1"), now)
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // An hour on, every bucket has refilled and it's another day
+    let later = now + Duration::hours(1);
+    assert!(check_login_limits(&db, &limits, "5550000002", None, later)
+        .await
+        .unwrap()
+        .is_none());
+
+    let keys: Vec<String> = sqlx::query_scalar("SELECT key FROM rate_limit_buckets ORDER BY key")
+        .fetch_all(&db)
+        .await
+        .unwrap();
+    assert_eq!(keys, vec!["login:global", "login:phone:5550000002"]);
+    let days: Vec<String> = sqlx::query_scalar("SELECT day FROM otp_daily_counts")
+        .fetch_all(&db)
+        .await
+        .unwrap();
+    assert_eq!(days, vec!["2024-03-15"]);
+}
@@ -0,0 +1,224 @@
+use chrono::{NaiveDate, TimeZone, Utc};
+use common::birthday::{BirthdayEngine, LeapDayPolicy};
+use common::events::EventType;
+use common::recurrence::{weekday_of_month, Recurrence, RecurrencePreset};
+use common::DbReminder;
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+fn next(rule: &str, dtstart: NaiveDate, from: NaiveDate) -> Option<NaiveDate> {
+    Recurrence::parse(rule)
+        .unwrap()
+        .next_on_or_after(dtstart, from)
+}
+
+#[test]
+fn test_every_six_months() {
+    let dtstart = date(1990, 8, 15);
+    assert_eq!(
+        next("FREQ=MONTHLY;INTERVAL=6", dtstart, date(2024, 1, 15)),
+        Some(date(2024, 2, 15))
+    );
+    assert_eq!(
+        next("FREQ=MONTHLY;INTERVAL=6", dtstart, date(2024, 2, 16)),
+        Some(date(2024, 8, 15))
+    );
+}
+
+#[test]
+fn test_last_friday_of_october() {
+    let rule = "RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1FR";
+    let dtstart = date(2020, 10, 30);
+    assert_eq!(
+        next(rule, dtstart, date(2024, 1, 15)),
+        Some(date(2024, 10, 25))
+    );
+    // October 2025 has five Fridays
+    assert_eq!(
+        next(rule, dtstart, date(2024, 10, 26)),
+        Some(date(2025, 10, 31))
+    );
+}
+
+#[test]
+fn test_monthly_on_the_fifteenth() {
+    let dtstart = date(2024, 1, 1);
+    let rule = "FREQ=MONTHLY;BYMONTHDAY=15";
+    assert_eq!(
+        next(rule, dtstart, date(2024, 1, 15)),
+        Some(date(2024, 1, 15))
+    );
+    assert_eq!(
+        next(rule, dtstart, date(2024, 1, 16)),
+        Some(date(2024, 2, 15))
+    );
+    // Negative days count from the end of the month
+    assert_eq!(
+        next("FREQ=MONTHLY;BYMONTHDAY=-1", dtstart, date(2024, 2, 2)),
+        Some(date(2024, 2, 29))
+    );
+}
+
+#[test]
+fn test_months_without_the_day_are_skipped() {
+    assert_eq!(
+        next("FREQ=MONTHLY", date(2024, 1, 31), date(2024, 2, 1)),
+        Some(date(2024, 3, 31))
+    );
+    // A rule that can never match gives up instead of looping forever
+    assert_eq!(
+        next(
+            "FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=31",
+            date(2024, 1, 1),
+            date(2024, 1, 1)
+        ),
+        None
+    );
+}
+
+#[test]
+fn test_weekly_by_day() {
+    let rule = "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,WE";
+    // Monday
+    let dtstart = date(2024, 1, 1);
+    assert_eq!(
+        next(rule, dtstart, date(2024, 1, 4)),
+        Some(date(2024, 1, 15))
+    );
+    assert_eq!(
+        next(rule, dtstart, date(2024, 1, 16)),
+        Some(date(2024, 1, 17))
+    );
+}
+
+#[test]
+fn test_count_and_until_end_the_rule() {
+    let dtstart = date(2020, 5, 1);
+    assert_eq!(
+        next("FREQ=YEARLY;COUNT=3", dtstart, date(2022, 1, 1)),
+        Some(date(2022, 5, 1))
+    );
+    assert_eq!(next("FREQ=YEARLY;COUNT=3", dtstart, date(2022, 6, 1)), None);
+    assert_eq!(
+        next("FREQ=YEARLY;UNTIL=20230501", dtstart, date(2023, 1, 1)),
+        Some(date(2023, 5, 1))
+    );
+    assert_eq!(
+        next(
+            "FREQ=YEARLY;UNTIL=20230501T000000Z",
+            dtstart,
+            date(2023, 6, 1)
+        ),
+        None
+    );
+}
+
+#[test]
+fn test_parse_errors() {
+    assert!(Recurrence::parse("").is_err());
+    assert!(Recurrence::parse("INTERVAL=2").is_err());
+    assert!(Recurrence::parse("FREQ=HOURLY").is_err());
+    assert!(Recurrence::parse("FREQ=MONTHLY;BYMONTHDAY=0").is_err());
+    assert!(Recurrence::parse("FREQ=YEARLY;BYMONTH=13").is_err());
+    assert!(Recurrence::parse("FREQ=WEEKLY;BYDAY=2MO").is_err());
+    assert!(Recurrence::parse("FREQ=YEARLY;COUNT=2;UNTIL=20300101").is_err());
+    assert!(Recurrence::parse("FREQ=YEARLY;BYSETPOS=1").is_err());
+}
+
+#[test]
+fn test_rule_round_trips() {
+    let rule = "FREQ=YEARLY;INTERVAL=2;BYMONTH=10;BYDAY=-1FR,MO;COUNT=5";
+    assert_eq!(Recurrence::parse(rule).unwrap().to_string(), rule);
+    assert_eq!(
+        Recurrence::parse("rrule:freq=monthly;bymonthday=15")
+            .unwrap()
+            .to_string(),
+        "FREQ=MONTHLY;BYMONTHDAY=15"
+    );
+}
+
+#[test]
+fn test_presets() {
+    // Thanksgiving 2024, the 4th Thursday of November
+    let thanksgiving = date(2024, 11, 28);
+    let rule = |preset: RecurrencePreset| {
+        preset
+            .to_recurrence(thanksgiving, "")
+            .unwrap()
+            .map(|rule| rule.to_string())
+    };
+    assert_eq!(rule(RecurrencePreset::Yearly), None);
+    assert_eq!(
+        rule(RecurrencePreset::EverySixMonths).as_deref(),
+        Some("FREQ=MONTHLY;INTERVAL=6")
+    );
+    assert_eq!(
+        rule(RecurrencePreset::YearlyByWeekday).as_deref(),
+        Some("FREQ=YEARLY;BYMONTH=11;BYDAY=4TH")
+    );
+    assert_eq!(
+        rule(RecurrencePreset::YearlyByLastWeekday).as_deref(),
+        Some("FREQ=YEARLY;BYMONTH=11;BYDAY=-1TH")
+    );
+    assert!(RecurrencePreset::Custom
+        .to_recurrence(thanksgiving, "FREQ=SOMETIMES")
+        .is_err());
+
+    for preset in [
+        RecurrencePreset::Yearly,
+        RecurrencePreset::EverySixMonths,
+        RecurrencePreset::Monthly,
+        RecurrencePreset::YearlyByWeekday,
+        RecurrencePreset::YearlyByLastWeekday,
+    ] {
+        let recurrence = preset.to_recurrence(thanksgiving, "").unwrap();
+        assert_eq!(
+            RecurrencePreset::for_recurrence(recurrence.as_ref(), thanksgiving),
+            preset
+        );
+        assert_eq!(RecurrencePreset::parse(preset.as_str()), Some(preset));
+    }
+    let custom = Recurrence::parse("FREQ=WEEKLY").unwrap();
+    assert_eq!(
+        RecurrencePreset::for_recurrence(Some(&custom), thanksgiving),
+        RecurrencePreset::Custom
+    );
+}
+
+#[test]
+fn test_weekday_of_month() {
+    assert_eq!(weekday_of_month(date(2024, 11, 1)), 1);
+    assert_eq!(weekday_of_month(date(2024, 11, 28)), 4);
+    assert_eq!(weekday_of_month(date(2024, 11, 29)), -1);
+}
+
+#[test]
+fn test_engine_expands_rules_in_user_timezone() {
+    let reminder = DbReminder {
+        id: 1,
+        user_id: 1,
+        name: "Rex".to_string(),
+        birthdate: date(2020, 7, 15)
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc()
+            .timestamp_millis(),
+        year_known: true,
+        notice_offsets: None,
+        event_type: EventType::Custom("half-birthday".to_string()),
+        recurrence: Some(Recurrence::parse("FREQ=MONTHLY;INTERVAL=6").unwrap()),
+        calendar_date: None,
+        created_at: "2024-01-01 00:00:00".to_string(),
+        updated_at: "2024-01-01 00:00:00".to_string(),
+    };
+
+    // 9pm Jan 14 in Los Angeles is already Jan 15 in UTC
+    let now = Utc.with_ymd_and_hms(2024, 1, 15, 5, 0, 0).unwrap();
+    let engine = BirthdayEngine::at(chrono_tz::America::Los_Angeles, LeapDayPolicy::Feb28, now);
+    let info = engine.info(&reminder).unwrap();
+    assert_eq!(info.next_date, date(2024, 1, 15));
+    assert_eq!(info.days_until, 1);
+    assert_eq!(info.age_turning, None);
+}
@@ -0,0 +1,74 @@
+#[path = "common/mod.rs"]
+mod fixtures;
+
+use chrono::NaiveDate;
+use common::calendars::{CalendarDate, CalendarSystem};
+use common::events::EventType;
+use common::recurrence::Recurrence;
+use common::{create_user, save_reminder, ReminderFields};
+use fixtures::{create_test_timestamp, setup_test_database};
+
+#[tokio::test]
+async fn test_save_reminder_writes_every_field() {
+    let (db, _temp_file) = setup_test_database().await;
+    let user = create_user(&db, "5551234567").await.unwrap();
+    let other = create_user(&db, "5559876543").await.unwrap();
+
+    let birthdate = create_test_timestamp(1990, 3, 14);
+    let created = save_reminder(
+        &db,
+        user.id,
+        None,
+        &ReminderFields {
+            name: "Jane",
+            birthdate: &birthdate,
+            year_known: true,
+            notice_offsets: None,
+            event_type: &EventType::Birthday,
+            recurrence: None,
+            calendar_date: None,
+        },
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(created.user_id, user.id);
+    assert_eq!(created.name, "Jane");
+    assert_eq!(created.birthdate.to_string(), birthdate);
+    assert!(created.calendar_date.is_none());
+
+    let recurrence = Recurrence::parse("FREQ=YEARLY;INTERVAL=2").unwrap();
+    let calendar_date = CalendarDate::from_gregorian(
+        CalendarSystem::Hebrew,
+        NaiveDate::from_ymd_opt(1990, 3, 14).unwrap(),
+    )
+    .unwrap();
+    let event_type = EventType::Custom("name day".to_string());
+    let fields = ReminderFields {
+        name: "Jane Doe",
+        birthdate: &birthdate,
+        year_known: false,
+        notice_offsets: Some(&[3, 0]),
+        event_type: &event_type,
+        recurrence: Some(&recurrence),
+        calendar_date: Some(&calendar_date),
+    };
+
+    // Someone else's reminder is left alone
+    assert!(save_reminder(&db, other.id, Some(created.id), &fields)
+        .await
+        .unwrap()
+        .is_none());
+
+    let updated = save_reminder(&db, user.id, Some(created.id), &fields)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(updated.id, created.id);
+    assert_eq!(updated.name, "Jane Doe");
+    assert!(!updated.year_known);
+    assert_eq!(updated.notice_offsets, Some(vec![3, 0]));
+    assert_eq!(updated.event_type, event_type);
+    assert_eq!(updated.recurrence, Some(recurrence));
+    assert_eq!(updated.calendar_date, Some(calendar_date));
+}
@@ -0,0 +1,66 @@
+use common::sealing::{SealError, Sealer};
+
+#[test]
+fn test_seal_round_trips_with_fresh_nonces() {
+    let sealer = Sealer::new("current-secret", &[]);
+
+    let first = sealer.seal("login", "provider-token").unwrap();
+    let second = sealer.seal("login", "provider-token").unwrap();
+    assert_ne!(first, second);
+    assert!(first.starts_with(&format!("v1.{}.", sealer.current_key_id())));
+    assert!(!first.contains("provider-token"));
+
+    assert_eq!(sealer.open("login", &first).unwrap(), "provider-token");
+    assert_eq!(sealer.open("login", &second).unwrap(), "provider-token");
+
+    // Sealed for one purpose, it won't open for another
+    assert_eq!(
+        sealer.open("delete_account", &first),
+        Err(SealError::Tampered)
+    );
+}
+
+#[test]
+fn test_tampering_is_detected() {
+    let sealer = Sealer::new("current-secret", &[]);
+    let sealed = sealer.seal("login", "provider-token").unwrap();
+
+    let mut tampered = sealed.clone().into_bytes();
+    let last = tampered.len() - 2;
+    tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+    let tampered = String::from_utf8(tampered).unwrap();
+    assert_eq!(sealer.open("login", &tampered), Err(SealError::Tampered));
+
+    assert_eq!(
+        sealer.open("login", "provider-token"),
+        Err(SealError::Malformed)
+    );
+    assert_eq!(
+        sealer.open("login", &sealed.replacen("v1.", "v2.", 1)),
+        Err(SealError::Malformed)
+    );
+}
+
+#[test]
+fn test_rotation_keeps_old_values_open() {
+    let old = Sealer::new("old-secret", &[]);
+    let sealed_before = old.seal("login", "provider-token").unwrap();
+
+    let rotated = Sealer::new("new-secret", &["old-secret".to_string()]);
+    assert_ne!(rotated.current_key_id(), old.current_key_id());
+    assert_eq!(
+        rotated.open("login", &sealed_before).unwrap(),
+        "provider-token"
+    );
+
+    // New values use the new key, which the old sealer doesn't know
+    let sealed_after = rotated.seal("login", "provider-token").unwrap();
+    assert_eq!(old.open("login", &sealed_after), Err(SealError::UnknownKey));
+
+    // Once the old secret is dropped, its values no longer open
+    let dropped = Sealer::new("new-secret", &[]);
+    assert_eq!(
+        dropped.open("login", &sealed_before),
+        Err(SealError::UnknownKey)
+    );
+}
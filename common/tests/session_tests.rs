@@ -0,0 +1,130 @@
+#[path = "common/mod.rs"]
+mod fixtures;
+
+use chrono::{Duration, TimeZone, Utc};
+use common::sessions::{
+    authenticate_session, create_session, delete_session_by_secret, get_sessions_by_user_id,
+    revoke_all_sessions, revoke_session, SESSION_IDLE_DAYS, SESSION_TOUCH_MINUTES,
+};
+use common::{create_user, delete_user};
+use fixtures::setup_test_database;
+
+#[tokio::test]
+async fn test_session_is_stored_hashed_and_slides() {
+    let (db, _temp_file) = setup_test_database().await;
+    let user = create_user(&db, "5551234567").await.unwrap();
+    let now = Utc.with_ymd_and_hms(2024, 3, 14, 15, 0, 0).unwrap();
+
+    let (session, secret) = create_session(&db, user.id, Some("Firefox"), Some("10.0.0.1"), now)
+        .await
+        .unwrap();
+    assert_eq!(session.user_id, user.id);
+    assert_eq!(session.user_agent.as_deref(), Some("Firefox"));
+    assert_eq!(session.expires_at, "2024-04-13 15:00:00");
+
+    let stored: String = sqlx::query_scalar("SELECT token_hash FROM sessions WHERE id = ?")
+        .bind(session.id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_ne!(stored, secret);
+    assert!(authenticate_session(&db, &stored, now)
+        .await
+        .unwrap()
+        .is_none());
+
+    // Each use pushes the expiry out, so an active session outlives the idle
+    // window
+    let mut seen = now;
+    for _ in 0..3 {
+        seen += Duration::days(SESSION_IDLE_DAYS - 1);
+        let session = authenticate_session(&db, &secret, seen)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            session.last_seen_at,
+            seen.format("%Y-%m-%d %H:%M:%S").to_string()
+        );
+    }
+
+    // Uses in quick succession don't write, so the expiry stays put
+    let soon = seen + Duration::minutes(SESSION_TOUCH_MINUTES - 1);
+    let session = authenticate_session(&db, &secret, soon)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        session.last_seen_at,
+        seen.format("%Y-%m-%d %H:%M:%S").to_string()
+    );
+
+    let idle = seen + Duration::days(SESSION_IDLE_DAYS);
+    assert!(authenticate_session(&db, &secret, idle)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(get_sessions_by_user_id(&db, user.id, idle)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_revoking_sessions() {
+    let (db, _temp_file) = setup_test_database().await;
+    let user = create_user(&db, "5551234567").await.unwrap();
+    let other = create_user(&db, "5559876543").await.unwrap();
+    let now = Utc.with_ymd_and_hms(2024, 3, 14, 15, 0, 0).unwrap();
+
+    let (laptop, laptop_secret) = create_session(&db, user.id, None, None, now).await.unwrap();
+    let (_, phone_secret) = create_session(&db, user.id, None, None, now).await.unwrap();
+    let (_, tablet_secret) = create_session(&db, user.id, None, None, now).await.unwrap();
+    let (_, other_secret) = create_session(&db, other.id, None, None, now)
+        .await
+        .unwrap();
+    assert_eq!(
+        get_sessions_by_user_id(&db, user.id, now)
+            .await
+            .unwrap()
+            .len(),
+        3
+    );
+
+    // Someone else can't revoke the user's session
+    assert!(!revoke_session(&db, other.id, laptop.id).await.unwrap());
+    assert!(revoke_session(&db, user.id, laptop.id).await.unwrap());
+    assert!(authenticate_session(&db, &laptop_secret, now)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(authenticate_session(&db, &phone_secret, now)
+        .await
+        .unwrap()
+        .is_some());
+
+    // Logging out ends just that session
+    delete_session_by_secret(&db, &phone_secret).await.unwrap();
+    assert!(authenticate_session(&db, &phone_secret, now)
+        .await
+        .unwrap()
+        .is_none());
+
+    create_session(&db, user.id, None, None, now).await.unwrap();
+    assert_eq!(revoke_all_sessions(&db, user.id).await.unwrap(), 2);
+    assert!(authenticate_session(&db, &tablet_secret, now)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(authenticate_session(&db, &other_secret, now)
+        .await
+        .unwrap()
+        .is_some());
+
+    // Deleting the account takes its sessions with it
+    delete_user(&db, other.id).await.unwrap();
+    assert!(authenticate_session(&db, &other_secret, now)
+        .await
+        .unwrap()
+        .is_none());
+}
@@ -0,0 +1,46 @@
+use common::sms::{FileSender, MemorySender, RecordedSms, SmsSender};
+use tempfile::NamedTempFile;
+
+#[tokio::test]
+async fn test_memory_sender_records_messages() {
+    let sender = MemorySender::new();
+
+    let first = sender
+        .send_sms("+11234567890", "John's 34th is today")
+        .await
+        .unwrap();
+    let second = sender
+        .send_sms("+11234567891", "Jane's 30th is tomorrow")
+        .await
+        .unwrap();
+
+    assert_eq!(sender.provider(), "memory");
+    assert_eq!(first.message_id.as_deref(), Some("memory-1"));
+    assert_eq!(second.message_id.as_deref(), Some("memory-2"));
+    assert_eq!(
+        sender.sent(),
+        vec![
+            RecordedSms {
+                to: "+11234567890".to_string(),
+                body: "John's 34th is today".to_string(),
+            },
+            RecordedSms {
+                to: "+11234567891".to_string(),
+                body: "Jane's 30th is tomorrow".to_string(),
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_file_sender_appends_to_outbox() {
+    let outbox = NamedTempFile::new().unwrap();
+    let sender = FileSender::new(outbox.path().to_path_buf());
+
+    sender.send_sms("+11234567890", "first").await.unwrap();
+    sender.send_sms("+11234567890", "second").await.unwrap();
+
+    let contents = std::fs::read_to_string(outbox.path()).unwrap();
+    assert!(contents.contains("to: +11234567890"));
+    assert!(contents.find("first").unwrap() < contents.find("second").unwrap());
+}
@@ -0,0 +1,30 @@
+-- Ledger of every digest the sweeper has tried to send
+CREATE TABLE sent_messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    provider TEXT NOT NULL,
+    provider_message_id TEXT,
+    body TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    error TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    sent_at DATETIME,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_sent_messages_user_id ON sent_messages(user_id);
+
+-- Reminder occurrences covered by each digest. The primary key is what keeps
+-- a reminder from being sent twice for the same birthday and offset.
+CREATE TABLE sent_message_reminders (
+    sent_message_id INTEGER NOT NULL,
+    reminder_id INTEGER NOT NULL,
+    birthday_date TEXT NOT NULL,
+    days_until INTEGER NOT NULL,
+    PRIMARY KEY (reminder_id, birthday_date, days_until),
+    FOREIGN KEY (sent_message_id) REFERENCES sent_messages(id) ON DELETE CASCADE,
+    FOREIGN KEY (reminder_id) REFERENCES reminders(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_sent_message_reminders_sent_message_id ON sent_message_reminders(sent_message_id);
@@ -0,0 +1,6 @@
+-- Retry bookkeeping for the sent_messages ledger. Failed digests move to
+-- 'retrying' with a next_attempt_at, or to 'dead' once they can't be delivered.
+ALTER TABLE sent_messages ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE sent_messages ADD COLUMN next_attempt_at DATETIME;
+
+CREATE INDEX idx_sent_messages_status_next_attempt_at ON sent_messages(status, next_attempt_at);
@@ -0,0 +1,2 @@
+-- Set when a user texts STOP, cleared when they text START
+ALTER TABLE users ADD COLUMN sms_opted_out_at DATETIME;
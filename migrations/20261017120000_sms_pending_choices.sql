@@ -0,0 +1,9 @@
+-- A command that matched several reminders and is waiting for the user to
+-- reply with a number. One per user; a new ambiguous command replaces it.
+CREATE TABLE sms_pending_choices (
+    user_id INTEGER PRIMARY KEY,
+    action TEXT NOT NULL,
+    reminder_ids TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
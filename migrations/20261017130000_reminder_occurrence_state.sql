@@ -0,0 +1,11 @@
+-- Per-occurrence state set by SMS replies. occurrence_date is the birthday
+-- being reminded about, so DONE and SNOOZE only ever affect one year.
+CREATE TABLE reminder_occurrence_state (
+    reminder_id INTEGER NOT NULL,
+    occurrence_date TEXT NOT NULL,
+    done_at DATETIME,
+    snoozed_until TEXT,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (reminder_id, occurrence_date),
+    FOREIGN KEY (reminder_id) REFERENCES reminders(id) ON DELETE CASCADE
+);
@@ -0,0 +1,12 @@
+-- Replace the single days_notice window with a list of offsets (days before
+-- the birthday), e.g. '14,3,0'. Existing users keep their furthest reminder
+-- and get the day before and the day itself.
+ALTER TABLE users ADD COLUMN notice_offsets TEXT NOT NULL DEFAULT '7,1,0';
+
+UPDATE users SET notice_offsets = CASE
+    WHEN days_notice <= 0 THEN '0'
+    WHEN days_notice = 1 THEN '1,0'
+    ELSE days_notice || ',1,0'
+END;
+
+ALTER TABLE users DROP COLUMN days_notice;
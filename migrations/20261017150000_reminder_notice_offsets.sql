@@ -0,0 +1,3 @@
+-- Optional per-reminder override of users.notice_offsets (same '14,3,0'
+-- format). NULL means use the user's default.
+ALTER TABLE reminders ADD COLUMN notice_offsets TEXT;
@@ -0,0 +1,4 @@
+-- Reminders where only the month and day are known. Their birthdate is stored
+-- in the placeholder year 2000 (a leap year, so Feb 29 works) and no age is
+-- shown or texted.
+ALTER TABLE reminders ADD COLUMN year_known INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,3 @@
+-- Which day Feb 29 birthdays are celebrated on in common years: 'feb28' or
+-- 'mar1'.
+ALTER TABLE users ADD COLUMN leap_day_policy TEXT NOT NULL DEFAULT 'feb28';
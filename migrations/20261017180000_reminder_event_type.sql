@@ -0,0 +1,5 @@
+-- What each reminder's date marks: birthday, wedding_anniversary,
+-- work_anniversary, memorial or custom. Custom occasions are named by
+-- event_label. Existing reminders are all birthdays.
+ALTER TABLE reminders ADD COLUMN event_type TEXT NOT NULL DEFAULT 'birthday';
+ALTER TABLE reminders ADD COLUMN event_label TEXT;
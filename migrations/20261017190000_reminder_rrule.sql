@@ -0,0 +1,3 @@
+-- Optional RFC 5545 recurrence rule (e.g. FREQ=MONTHLY;INTERVAL=6), with the
+-- reminder's date as DTSTART. NULL keeps the plain once-a-year behavior.
+ALTER TABLE reminders ADD COLUMN rrule TEXT;
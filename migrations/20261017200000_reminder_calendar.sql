@@ -0,0 +1,7 @@
+-- Reminders kept in the Chinese lunar or Hebrew calendar. birthdate stays the
+-- Gregorian date of birth; calendar_month (an ICU month code like 'M05L') and
+-- calendar_day hold the date in the reminder's calendar, which is converted
+-- to a new Gregorian date each year.
+ALTER TABLE reminders ADD COLUMN calendar TEXT NOT NULL DEFAULT 'gregorian';
+ALTER TABLE reminders ADD COLUMN calendar_month TEXT;
+ALTER TABLE reminders ADD COLUMN calendar_day INTEGER;
@@ -0,0 +1,4 @@
+-- Secret token in each user's calendar feed URL. Created on first visit to
+-- Settings and replaced when the user rotates it.
+ALTER TABLE users ADD COLUMN ics_token TEXT;
+CREATE UNIQUE INDEX idx_users_ics_token ON users(ics_token);
@@ -0,0 +1,17 @@
+-- Pending OTP verifications. The browser only holds the random session id;
+-- the phone the code was sent to is kept here, so the code can't be used to
+-- sign in as a different number.
+CREATE TABLE otp_sessions (
+    id TEXT PRIMARY KEY,
+    -- "login" or "delete_account"
+    purpose TEXT NOT NULL,
+    phone_number TEXT NOT NULL,
+    verification_id TEXT NOT NULL,
+    -- The OTP provider's auth token, encrypted
+    provider_token TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    expires_at DATETIME NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_otp_sessions_expires_at ON otp_sessions(expires_at);
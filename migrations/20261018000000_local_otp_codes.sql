@@ -0,0 +1,13 @@
+-- Codes issued by the built-in OTP provider (OTP_BACKEND=local). Only an
+-- HMAC of each code is kept.
+CREATE TABLE local_otp_codes (
+    id TEXT PRIMARY KEY,
+    phone_number TEXT NOT NULL,
+    code_hash TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    expires_at DATETIME NOT NULL,
+    used_at DATETIME,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_local_otp_codes_expires_at ON local_otp_codes(expires_at);
@@ -0,0 +1,15 @@
+-- Token buckets limiting how often /login texts a code, keyed e.g.
+-- "login:phone:5551234567", "login:ip:203.0.113.7" or "login:global".
+-- Kept in the database so every machine shares them.
+CREATE TABLE rate_limit_buckets (
+    key TEXT PRIMARY KEY,
+    tokens REAL NOT NULL,
+    -- Unix milliseconds of the last refill
+    updated_ms INTEGER NOT NULL
+);
+
+-- Codes texted per UTC day, checked against the daily budget
+CREATE TABLE otp_daily_counts (
+    day TEXT PRIMARY KEY,
+    sent INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,5 @@
+-- When a digest's claims were taken. A 'pending' digest whose run died before
+-- recording the send can be taken over once its claim is old enough.
+ALTER TABLE sent_messages ADD COLUMN claimed_at DATETIME;
+
+UPDATE sent_messages SET claimed_at = created_at WHERE status = 'pending';
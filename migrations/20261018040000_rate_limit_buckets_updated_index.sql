@@ -0,0 +1,2 @@
+-- Lets /login prune buckets that have refilled without scanning them all
+CREATE INDEX idx_rate_limit_buckets_updated_ms ON rate_limit_buckets (updated_ms);
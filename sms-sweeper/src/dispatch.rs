@@ -0,0 +1,274 @@
+use chrono::{DateTime, Utc};
+use common::clock::Clock;
+use common::ledger::{
+    claim_digest, claim_retry, get_due_retries, mark_digest_sent, record_failed_attempt,
+    STATUS_DEAD,
+};
+use common::sms::{SmsError, SmsSender};
+use common::{get_user_by_id, update_user_last_digest_at};
+use log::{error, info, warn};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tokio::time::{sleep, Duration};
+
+use crate::{plan_digests, SkipReason, SkippedUser, SmsMessage};
+
+/// Pause between sends so we stay under provider rate limits
+const SEND_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedSend {
+    pub user_id: i64,
+    pub sent_message_id: Option<i64>,
+    pub error: String,
+    /// False once the digest has been dead-lettered
+    pub will_retry: bool,
+}
+
+/// Summary of one sweeper run, returned by `/run-sweeper`
+#[derive(Debug, Clone, Serialize)]
+pub struct RunReport {
+    pub started_at: DateTime<Utc>,
+    /// Sends handed to the provider, retries included
+    pub attempted: usize,
+    pub sent: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    /// Users whose send hour it isn't. That's most users on any run, so
+    /// they're counted rather than listed in `skipped_users`.
+    pub not_send_time: usize,
+    pub failures: Vec<FailedSend>,
+    pub skipped_users: Vec<SkippedUser>,
+}
+
+impl RunReport {
+    fn new(started_at: DateTime<Utc>) -> Self {
+        Self {
+            started_at,
+            attempted: 0,
+            sent: 0,
+            failed: 0,
+            skipped: 0,
+            not_send_time: 0,
+            failures: Vec::new(),
+            skipped_users: Vec::new(),
+        }
+    }
+
+    fn record(&mut self, user_id: i64, sent_message_id: Option<i64>, outcome: DeliveryOutcome) {
+        if outcome != DeliveryOutcome::AlreadySent {
+            self.attempted += 1;
+        }
+
+        match outcome {
+            DeliveryOutcome::Sent => self.sent += 1,
+            DeliveryOutcome::AlreadySent => self.skipped_users.push(SkippedUser {
+                user_id,
+                reason: SkipReason::AlreadySent,
+            }),
+            DeliveryOutcome::RetryScheduled(error) => self.failures.push(FailedSend {
+                user_id,
+                sent_message_id,
+                error,
+                will_retry: true,
+            }),
+            DeliveryOutcome::DeadLettered(error) => self.failures.push(FailedSend {
+                user_id,
+                sent_message_id,
+                error,
+                will_retry: false,
+            }),
+        }
+
+        self.failed = self.failures.len();
+        self.skipped = self.skipped_users.len();
+    }
+}
+
+/// Runs one full sweep: drains the retry queue, then builds and sends every
+/// digest due at `clock.now()`. Both the `sms-sweeper` binary and the web
+/// server's `/run-sweeper` endpoint go through here.
+pub async fn run_sweeper(
+    db: &SqlitePool,
+    sender: &dyn SmsSender,
+    clock: &dyn Clock,
+) -> Result<RunReport, sqlx::Error> {
+    let current_utc = clock.now();
+    let mut report = RunReport::new(current_utc);
+    info!(
+        "Starting sweep at {} using the {} backend",
+        current_utc.format("%Y-%m-%d %H:%M:%S"),
+        sender.provider()
+    );
+
+    // Retry earlier failures first
+    for (user_id, sent_message_id, outcome) in retry_due(db, sender, current_utc).await? {
+        report.record(user_id, Some(sent_message_id), outcome);
+    }
+
+    let plan = plan_digests(db, current_utc).await?;
+    report.skipped_users.extend(plan.skipped);
+    report.skipped = report.skipped_users.len();
+    report.not_send_time = plan.not_send_time;
+
+    for (i, sms_message) in plan.messages.iter().enumerate() {
+        // Rate limit: sleep between messages (except before the first one)
+        if i > 0 {
+            sleep(SEND_INTERVAL).await;
+        }
+
+        let (sent_message_id, outcome) =
+            claim_and_deliver(db, sender, sms_message, current_utc).await?;
+        report.record(sms_message.user_id, sent_message_id, outcome);
+    }
+
+    info!(
+        "Sweep complete: attempted={}, sent={}, failed={}, skipped={}",
+        report.attempted, report.sent, report.failed, report.skipped
+    );
+    Ok(report)
+}
+
+/// What happened when a digest was handed to the provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DeliveryOutcome {
+    Sent,
+    /// Another run already claimed at least one of the digest's reminders
+    AlreadySent,
+    /// The send failed and the digest is back in the retry queue
+    RetryScheduled(String),
+    /// The send failed permanently or ran out of attempts
+    DeadLettered(String),
+}
+
+/// Claims the digest in the ledger, sends it and records the result. A digest
+/// whose reminders were already claimed by another run is not sent again, and
+/// a failed send goes to the retry queue.
+async fn claim_and_deliver(
+    db: &SqlitePool,
+    sender: &dyn SmsSender,
+    sms_message: &SmsMessage,
+    current_utc: DateTime<Utc>,
+) -> Result<(Option<i64>, DeliveryOutcome), sqlx::Error> {
+    let sent_message_id = match claim_digest(
+        db,
+        sms_message.user_id,
+        sender.provider(),
+        &sms_message.message,
+        &sms_message.entries,
+        current_utc,
+    )
+    .await?
+    {
+        Some(id) => id,
+        None => {
+            info!(
+                "Digest for user {} was already claimed by another run",
+                sms_message.user_id
+            );
+            return Ok((None, DeliveryOutcome::AlreadySent));
+        }
+    };
+
+    let outcome = deliver(
+        db,
+        sender,
+        sent_message_id,
+        sms_message.user_id,
+        &sms_message.phone_number,
+        &sms_message.message,
+        current_utc,
+    )
+    .await?;
+    Ok((Some(sent_message_id), outcome))
+}
+
+/// Retries every digest in the retry queue that is due at `current_utc`,
+/// leaving any another run claims first to that run. Returns the user,
+/// sent_messages id and outcome of each attempt.
+async fn retry_due(
+    db: &SqlitePool,
+    sender: &dyn SmsSender,
+    current_utc: DateTime<Utc>,
+) -> Result<Vec<(i64, i64, DeliveryOutcome)>, sqlx::Error> {
+    let due = get_due_retries(db, current_utc).await?;
+    info!("Found {} digests due for retry", due.len());
+
+    let mut outcomes = Vec::new();
+    for retry in due {
+        if !claim_retry(db, retry.id, current_utc).await? {
+            info!("Digest {} was already retried by another run", retry.id);
+            continue;
+        }
+
+        let user = match get_user_by_id(db, retry.user_id).await? {
+            Some(user) => user,
+            // Deleted users cascade, so this only happens mid-delete
+            None => continue,
+        };
+
+        // Never text someone who opted out after the digest was queued
+        if user.sms_opted_out_at.is_some() {
+            let message = "Recipient opted out of SMS".to_string();
+            record_failed_attempt(db, retry.id, &message, true, current_utc).await?;
+            warn!("Digest {} dropped: user {} opted out", retry.id, user.id);
+            outcomes.push((user.id, retry.id, DeliveryOutcome::DeadLettered(message)));
+            continue;
+        }
+
+        if !outcomes.is_empty() {
+            sleep(SEND_INTERVAL).await;
+        }
+
+        info!(
+            "Retrying digest {} for user {} (attempt {})",
+            retry.id,
+            retry.user_id,
+            retry.attempts + 1
+        );
+        let outcome = deliver(
+            db,
+            sender,
+            retry.id,
+            user.id,
+            &user.phone_number,
+            &retry.body,
+            current_utc,
+        )
+        .await?;
+        outcomes.push((user.id, retry.id, outcome));
+    }
+
+    Ok(outcomes)
+}
+
+async fn deliver(
+    db: &SqlitePool,
+    sender: &dyn SmsSender,
+    sent_message_id: i64,
+    user_id: i64,
+    phone_number: &str,
+    body: &str,
+    current_utc: DateTime<Utc>,
+) -> Result<DeliveryOutcome, sqlx::Error> {
+    match sender.send_sms(&format!("+1{}", phone_number), body).await {
+        Ok(receipt) => {
+            info!("Successfully sent SMS to {}", phone_number);
+            mark_digest_sent(db, sent_message_id, receipt.message_id.as_deref()).await?;
+            update_user_last_digest_at(db, user_id, current_utc).await?;
+            Ok(DeliveryOutcome::Sent)
+        }
+        Err(SmsError { message, permanent }) => {
+            error!("Failed to send SMS to {}: {}", phone_number, message);
+            let status =
+                record_failed_attempt(db, sent_message_id, &message, permanent, current_utc)
+                    .await?;
+            if status == STATUS_DEAD {
+                warn!("Digest {} moved to dead letters", sent_message_id);
+                Ok(DeliveryOutcome::DeadLettered(message))
+            } else {
+                Ok(DeliveryOutcome::RetryScheduled(message))
+            }
+        }
+    }
+}
@@ -1,13 +1,28 @@
-use chrono::{DateTime, Datelike, Timelike, Utc};
-use chrono_tz::Tz;
+use chrono::{DateTime, NaiveDate, Timelike, Utc};
+use common::birthday::{user_timezone, BirthdayEngine, BirthdayInfo};
+use common::events::{event_line, EventType};
+use common::ledger::{get_claimed_entries_by_user_id, DigestEntry};
+use common::occurrences::{get_occurrence_states_by_user_id, OccurrenceState};
+use common::sms::fit_lines;
 use common::{get_all_users, get_reminders_by_user_id, DbReminder, DbUser};
 use log::{error, info, warn};
+use serde::Serialize;
 use sqlx::SqlitePool;
+use std::collections::HashMap;
 
-struct BirthdayReminder {
+pub mod dispatch;
+
+pub use dispatch::{run_sweeper, FailedSend, RunReport};
+
+struct EventReminder {
+    reminder_id: i64,
     name: String,
-    days_until: i32,
-    age_turning: i32,
+    event_type: EventType,
+    birthday_date: NaiveDate,
+    days_until: i64,
+    /// Age turned or years since the original date; `None` when the year
+    /// isn't known
+    years: Option<i32>,
 }
 
 #[derive(Debug, Clone)]
@@ -15,76 +30,38 @@ pub struct SmsMessage {
     pub user_id: i64,
     pub phone_number: String,
     pub message: String,
+    /// Reminder occurrences this digest covers, recorded in the ledger on send
+    pub entries: Vec<DigestEntry>,
 }
 
-fn calculate_birthday_info(
-    birthdate_timestamp: i64,
-    user_tz: &Tz,
-    current_utc: DateTime<Utc>,
-) -> Result<(i32, i32), String> {
-    // Convert Unix timestamp (milliseconds) to UTC DateTime
-    let birthdate_utc =
-        DateTime::from_timestamp_millis(birthdate_timestamp).ok_or("Invalid timestamp")?;
-    let birthdate = birthdate_utc.naive_utc().date();
-
-    // Use the user's local timezone to determine "today"
-    let user_local_time = current_utc.with_timezone(user_tz);
-    let today = user_local_time.naive_local().date();
-    let current_year = today.year();
-
-    info!(
-        "Birthday calc: birthdate={}, user_local_today={}, current_year={}",
-        birthdate.format("%Y-%m-%d"),
-        today.format("%Y-%m-%d"),
-        current_year
-    );
-
-    // Calculate this year's birthday
-    let this_year_birthday = birthdate.with_year(current_year);
-
-    let (next_birthday, age_turning) = match this_year_birthday {
-        Some(this_year_bd) => {
-            if this_year_bd >= today {
-                // Birthday hasn't happened this year yet
-                (this_year_bd, current_year - birthdate.year())
-            } else {
-                // Birthday already happened this year, calculate for next year
-                let next_year_birthday = birthdate
-                    .with_year(current_year + 1)
-                    .ok_or("Failed to calculate next year's birthday")?;
-                (next_year_birthday, current_year + 1 - birthdate.year())
-            }
-        }
-        None => {
-            // Handle leap year edge case (Feb 29 -> Feb 28)
-            let next_year_birthday = birthdate
-                .with_year(current_year + 1)
-                .ok_or("Failed to calculate next year's birthday")?;
-            (next_year_birthday, current_year + 1 - birthdate.year())
-        }
-    };
-
-    let days_until = (next_birthday - today).num_days() as i32;
+/// Why a user didn't get a digest on this run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    NotifiedRecently,
+    NothingDue,
+    /// Every due reminder was already covered by an earlier digest
+    AlreadySent,
+    ReminderLookupFailed,
+    /// The user texted STOP
+    OptedOut,
+}
 
-    info!(
-        "Birthday result: next_birthday={}, days_until={}, age_turning={}",
-        next_birthday.format("%Y-%m-%d"),
-        days_until,
-        age_turning
-    );
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedUser {
+    pub user_id: i64,
+    pub reason: SkipReason,
+}
 
-    Ok((days_until, age_turning))
+/// Digests to send on this run, plus the users that were passed over
+pub(crate) struct DigestPlan {
+    pub messages: Vec<SmsMessage>,
+    pub skipped: Vec<SkippedUser>,
+    pub not_send_time: usize,
 }
 
 fn is_send_time_for_user(user: &DbUser, current_utc: DateTime<Utc>) -> bool {
-    // Parse the user's timezone
-    let user_tz: Tz = match user.iana_tz.parse() {
-        Ok(tz) => tz,
-        Err(_) => {
-            warn!("Invalid timezone for user {}: {}", user.id, user.iana_tz);
-            return false;
-        }
-    };
+    let user_tz = user_timezone(user);
 
     // Convert UTC time to user's timezone
     let user_local_time = current_utc.with_timezone(&user_tz);
@@ -147,44 +124,66 @@ fn was_notified_recently(user: &DbUser, current_utc: DateTime<Utc>) -> bool {
 fn get_reminders_to_send(
     user: &DbUser,
     reminders: Vec<DbReminder>,
+    occurrence_states: &HashMap<(i64, NaiveDate), OccurrenceState>,
     current_utc: DateTime<Utc>,
-) -> Vec<BirthdayReminder> {
+) -> Vec<EventReminder> {
     let mut birthday_reminders = Vec::new();
 
-    // Parse the user's timezone
-    let user_tz: Tz = match user.iana_tz.parse() {
-        Ok(tz) => tz,
-        Err(_) => {
-            warn!("Invalid timezone for user {}: {}", user.id, user.iana_tz);
-            return birthday_reminders;
-        }
-    };
+    let engine = BirthdayEngine::at(user_timezone(user), user.leap_day_policy, current_utc);
+    let today = engine.today();
 
     for reminder in reminders {
-        match calculate_birthday_info(reminder.birthdate, &user_tz, current_utc) {
-            Ok((days_until, age_turning)) => {
-                // Check if this birthday is within the user's notice period
+        match engine.info(&reminder) {
+            Some(BirthdayInfo {
+                next_date: birthday_date,
+                days_until,
+                age_turning,
+                ..
+            }) => {
+                info!(
+                    "Birthday calc for {}: user_local_today={}, next_birthday={}, days_until={}",
+                    reminder.name, today, birthday_date, days_until
+                );
+                let state = occurrence_states
+                    .get(&(reminder.id, birthday_date))
+                    .cloned()
+                    .unwrap_or_default();
+
+                // Only remind on the user's offset days, plus the day a
+                // snooze runs out
+                let notice_offsets = reminder
+                    .notice_offsets
+                    .as_deref()
+                    .unwrap_or(&user.notice_offsets);
+                let is_offset_day = notice_offsets.contains(&days_until);
+                let snooze_ends_today = state.snoozed_until == Some(today);
                 info!(
-                    "Reminder for {}: days_until={}, user.days_notice={}, in_range={}",
-                    reminder.name,
-                    days_until,
-                    user.days_notice,
-                    days_until >= 0 && days_until <= user.days_notice as i32
+                    "Reminder for {}: days_until={}, notice_offsets={:?}, offset_day={}, snooze_ends_today={}",
+                    reminder.name, days_until, notice_offsets, is_offset_day, snooze_ends_today
                 );
 
-                if days_until >= 0 && days_until <= user.days_notice as i32 {
+                if is_offset_day || snooze_ends_today {
+                    // Skip birthdays the user marked DONE or snoozed past today
+                    if state.suppresses(today) {
+                        info!("Skipping {} - marked done or snoozed", reminder.name);
+                        continue;
+                    }
+
                     info!("Adding birthday reminder for {}", reminder.name);
-                    birthday_reminders.push(BirthdayReminder {
+                    birthday_reminders.push(EventReminder {
+                        reminder_id: reminder.id,
                         name: reminder.name,
+                        event_type: reminder.event_type,
+                        birthday_date,
                         days_until,
-                        age_turning,
+                        years: age_turning,
                     });
                 }
             }
-            Err(e) => {
+            None => {
                 warn!(
-                    "Failed to calculate birthday info for reminder {}: {}",
-                    reminder.id, e
+                    "No upcoming date for reminder {}: invalid birthdate or finished rule",
+                    reminder.id
                 );
             }
         }
@@ -195,131 +194,61 @@ fn get_reminders_to_send(
     birthday_reminders
 }
 
-fn format_birthday_message(reminders: &[BirthdayReminder]) -> String {
-    let mut message = String::new();
-    let suffix = "\nhttps://hbd.bot";
-    let mut included_count = 0;
-
-    for (i, reminder) in reminders.iter().enumerate() {
-        let day_text = match reminder.days_until {
-            0 => "today".to_string(),
-            1 => "tomorrow".to_string(),
-            n => format!("in {} days", n),
-        };
-
-        let reminder_text = format!(
-            "{}'s {} is {}",
-            reminder.name,
-            ordinal_suffix(reminder.age_turning),
-            day_text
-        );
-
-        // Calculate potential message lengths
-        let current_with_newline = if message.is_empty() {
-            reminder_text.clone()
-        } else {
-            format!("{}\n{}", message, reminder_text)
-        };
-
-        let remaining_after_this = reminders.len() - i - 1;
-
-        // Option 1: Add this reminder + suffix
-        let with_suffix = format!("{}{}", current_with_newline, suffix);
-
-        if with_suffix.len() <= 160 {
-            // If this is the last reminder or we can fit everything, add it
-            if remaining_after_this == 0 {
-                message = current_with_newline;
-                included_count += 1;
-                break;
-            }
-
-            // Check if we can fit the next reminder too
-            if remaining_after_this == 1 {
-                let next_reminder = &reminders[i + 1];
-                let next_day_text = match next_reminder.days_until {
-                    0 => "today".to_string(),
-                    1 => "tomorrow".to_string(),
-                    n => format!("in {} days", n),
-                };
-                let next_reminder_text = format!(
-                    "{}'s {} is {}",
-                    next_reminder.name,
-                    ordinal_suffix(next_reminder.age_turning),
-                    next_day_text
-                );
-                let with_next =
-                    format!("{}\n{}{}", current_with_newline, next_reminder_text, suffix);
-
-                if with_next.len() <= 160 {
-                    // We can fit both, so add this one and continue to next iteration
-                    message = current_with_newline;
-                    included_count += 1;
-                    continue;
-                }
-            }
-
-            // Check if we should show "+ X more..." instead
-            if remaining_after_this > 1 {
-                let more_text = format!("\n+ {} more...{}", remaining_after_this, suffix);
-                let with_more = format!("{}{}", current_with_newline, more_text);
-
-                if with_more.len() <= 160 {
-                    message = current_with_newline;
-                    included_count += 1;
-                    break;
-                }
-            }
-
-            // We can fit this reminder, add it and continue
-            message = current_with_newline;
-            included_count += 1;
-        } else {
-            // This reminder won't fit, stop here
-            break;
-        }
-    }
+fn format_reminder_line(reminder: &EventReminder) -> String {
+    let day_text = match reminder.days_until {
+        0 => "today".to_string(),
+        1 => "tomorrow".to_string(),
+        n => format!("in {} days", n),
+    };
 
-    // Add suffix or "+ X more..." if there are remaining reminders
-    let remaining = reminders.len() - included_count;
-    if remaining > 1 {
-        let more_text = format!("\n+ {} more...{}", remaining, suffix);
-        let final_message = format!("{}{}", message, more_text);
-        if final_message.len() <= 160 {
-            return final_message;
-        }
-    }
+    event_line(
+        &reminder.name,
+        &reminder.event_type,
+        reminder.years,
+        &day_text,
+    )
+}
 
-    format!("{}{}", message, suffix)
+/// Packs as many reminders as fit into one SMS, in order, and returns the body
+/// with how many of them it names. The rest are counted in a "+ X more..."
+/// line, or `None` if not even the first reminder fits.
+fn format_birthday_message(reminders: &[EventReminder]) -> Option<(String, usize)> {
+    let lines: Vec<String> = reminders.iter().map(format_reminder_line).collect();
+    fit_lines(&lines, "\nhttps://hbd.bot")
 }
 
-fn ordinal_suffix(n: i32) -> String {
-    let suffix = match n % 100 {
-        11..=13 => "th",
-        _ => match n % 10 {
-            1 => "st",
-            2 => "nd",
-            3 => "rd",
-            _ => "th",
-        },
-    };
-    format!("{}{}", n, suffix)
+fn digest_entry(reminder: &EventReminder) -> DigestEntry {
+    DigestEntry {
+        reminder_id: reminder.reminder_id,
+        birthday_date: reminder.birthday_date,
+        days_until: reminder.days_until,
+    }
 }
 
 pub async fn get_birthday_messages(
     db: &SqlitePool,
     current_utc: DateTime<Utc>,
 ) -> Result<Vec<SmsMessage>, Box<dyn std::error::Error>> {
+    Ok(plan_digests(db, current_utc).await?.messages)
+}
+
+pub(crate) async fn plan_digests(
+    db: &SqlitePool,
+    current_utc: DateTime<Utc>,
+) -> Result<DigestPlan, sqlx::Error> {
     info!(
         "Getting birthday messages for current time: {}",
         current_utc.format("%Y-%m-%d %H:%M:%S")
     );
 
     // Get all users
-    let users = get_all_users(&db).await?;
+    let users = get_all_users(db).await?;
     info!("Found {} users", users.len());
 
     let mut messages = Vec::new();
+    let mut skipped = Vec::new();
+    let mut not_send_time = 0;
+    let mut skip = |user_id, reason| skipped.push(SkippedUser { user_id, reason });
 
     for user in users {
         info!(
@@ -327,15 +256,23 @@ pub async fn get_birthday_messages(
             user.id, user.phone_number, user.iana_tz, user.send_hour
         );
 
+        if user.sms_opted_out_at.is_some() {
+            info!("Skipping user {} - opted out of SMS", user.id);
+            skip(user.id, SkipReason::OptedOut);
+            continue;
+        }
+
         // Check if it's the right time to send for this user
         if !is_send_time_for_user(&user, current_utc) {
             info!("Skipping user {} - not send time", user.id);
+            not_send_time += 1;
             continue;
         }
 
         // Check if user was notified in the last 12 hours
         if was_notified_recently(&user, current_utc) {
             info!("Skipping user {} - notified within last 12 hours", user.id);
+            skip(user.id, SkipReason::NotifiedRecently);
             continue;
         }
 
@@ -345,18 +282,54 @@ pub async fn get_birthday_messages(
         );
 
         // Get reminders for this user
-        let reminders = match get_reminders_by_user_id(&db, user.id).await {
+        let reminders = match get_reminders_by_user_id(db, user.id).await {
             Ok(reminders) => reminders,
             Err(e) => {
                 error!("Failed to get reminders for user {}: {}", user.id, e);
+                skip(user.id, SkipReason::ReminderLookupFailed);
+                continue;
+            }
+        };
+
+        let occurrence_states = match get_occurrence_states_by_user_id(db, user.id).await {
+            Ok(states) => states,
+            Err(e) => {
+                error!("Failed to get reminder state for user {}: {}", user.id, e);
+                skip(user.id, SkipReason::ReminderLookupFailed);
                 continue;
             }
         };
 
         // Get birthday reminders that need to be sent
-        let birthday_reminders = get_reminders_to_send(&user, reminders, current_utc);
+        let mut birthday_reminders =
+            get_reminders_to_send(&user, reminders, &occurrence_states, current_utc);
+        if birthday_reminders.is_empty() {
+            skip(user.id, SkipReason::NothingDue);
+            continue;
+        }
+
+        // Drop anything a previous digest already covered
+        let claimed = match get_claimed_entries_by_user_id(db, user.id, current_utc).await {
+            Ok(claimed) => claimed,
+            Err(e) => {
+                error!("Failed to load sent messages for user {}: {}", user.id, e);
+                skip(user.id, SkipReason::ReminderLookupFailed);
+                continue;
+            }
+        };
+        birthday_reminders.retain(|reminder| {
+            let already_sent = claimed.contains(&digest_entry(reminder));
+            if already_sent {
+                info!(
+                    "Skipping {} for user {} - already sent for this birthday and offset",
+                    reminder.name, user.id
+                );
+            }
+            !already_sent
+        });
 
         if birthday_reminders.is_empty() {
+            skip(user.id, SkipReason::AlreadySent);
             continue;
         }
 
@@ -366,17 +339,34 @@ pub async fn get_birthday_messages(
             user.id
         );
 
-        // Format the message
-        let message = format_birthday_message(&birthday_reminders);
-        info!("Formatted message ({} chars): {}", message.len(), message);
-
-        messages.push(SmsMessage {
-            user_id: user.id,
-            phone_number: user.phone_number.clone(),
-            message,
-        });
+        // Each digest only covers the reminders it names; whatever didn't
+        // fit goes out in a follow-up
+        let mut rest = birthday_reminders.as_slice();
+        while !rest.is_empty() {
+            let Some((message, included)) = format_birthday_message(rest) else {
+                warn!(
+                    "Reminder {} for user {} is too long for one SMS",
+                    rest[0].reminder_id, user.id
+                );
+                break;
+            };
+            info!("Formatted message ({} chars): {}", message.len(), message);
+
+            let (named, remaining) = rest.split_at(included);
+            messages.push(SmsMessage {
+                user_id: user.id,
+                phone_number: user.phone_number.clone(),
+                message,
+                entries: named.iter().map(digest_entry).collect(),
+            });
+            rest = remaining;
+        }
     }
 
     info!("Generated {} SMS messages", messages.len());
-    Ok(messages)
+    Ok(DigestPlan {
+        messages,
+        skipped,
+        not_send_time,
+    })
 }
@@ -1,8 +1,20 @@
+use async_trait::async_trait;
 use chrono::{Datelike, NaiveDate, TimeZone, Utc};
+use common::birthday::LeapDayPolicy;
+use common::clock::FixedClock;
+use common::commands::{execute_command, parse_command};
+use common::events::EventType;
+use common::ledger::{
+    claim_digest, get_dead_letters, get_sent_messages_by_user_id, CLAIM_LEASE_MINUTES,
+    MAX_SEND_ATTEMPTS, STATUS_DEAD, STATUS_RETRYING, STATUS_SENT,
+};
+use common::sms::{MemorySender, SmsError, SmsReceipt, SmsSender};
 use common::{
-    create_reminder, create_user, init_database, update_user_last_digest_at, update_user_settings,
+    create_reminder, create_user, get_user_by_id, init_database, set_user_sms_opt_out,
+    update_reminder_event_type, update_reminder_notice_offsets, update_reminder_year_known,
+    update_user_leap_day_policy, update_user_settings, UNKNOWN_BIRTH_YEAR,
 };
-use sms_sweeper::get_birthday_messages;
+use sms_sweeper::{get_birthday_messages, run_sweeper, SkipReason};
 use tempfile::NamedTempFile;
 
 async fn setup_test_database() -> (sqlx::SqlitePool, tempfile::NamedTempFile) {
@@ -13,6 +25,11 @@ async fn setup_test_database() -> (sqlx::SqlitePool, tempfile::NamedTempFile) {
     (db, temp_file)
 }
 
+/// Offsets that remind every day for `days` days, like the old `days_notice`
+fn daily_offsets(days: i64) -> Vec<i64> {
+    (0..=days).rev().collect()
+}
+
 fn create_test_timestamp(year: i32, month: u32, day: u32) -> i64 {
     let naive_date = NaiveDate::from_ymd_opt(year, month, day).unwrap();
     let datetime = naive_date.and_hms_opt(0, 0, 0).unwrap();
@@ -26,14 +43,7 @@ async fn test_birthday_today_at_send_time() {
 
     // Create a user in Eastern timezone, send at 9 AM
     let user = create_user(&db, "1234567890").await.unwrap();
-    update_user_settings(&db, user.id, 7, 9, "America/New_York")
-        .await
-        .unwrap();
-
-    // Create a reminder for today's date
-    let today = Utc::now().naive_utc().date();
-    let birthday_timestamp = create_test_timestamp(1990, today.month(), today.day());
-    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+    update_user_settings(&db, user.id, &daily_offsets(7), 9, "America/New_York")
         .await
         .unwrap();
 
@@ -43,13 +53,20 @@ async fn test_birthday_today_at_send_time() {
         .unwrap();
     let utc_time = eastern_9am.with_timezone(&Utc);
 
+    // Create a reminder for today's date
+    let today = eastern_9am.date_naive();
+    let birthday_timestamp = create_test_timestamp(1990, today.month(), today.day());
+    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
     let messages = get_birthday_messages(&db, utc_time).await.unwrap();
 
     assert_eq!(messages.len(), 1);
     assert_eq!(messages[0].phone_number, "1234567890");
     assert!(messages[0].message.contains("John's"));
     assert!(messages[0].message.contains("today"));
-    assert!(messages[0].message.starts_with("hbd.bot"));
+    assert!(messages[0].message.ends_with("https://hbd.bot"));
     assert!(messages[0].message.len() <= 160);
 }
 
@@ -59,14 +76,7 @@ async fn test_birthday_tomorrow_at_send_time() {
 
     // Create a user in Pacific timezone, send at 10 AM
     let user = create_user(&db, "1234567891").await.unwrap();
-    update_user_settings(&db, user.id, 3, 10, "America/Los_Angeles")
-        .await
-        .unwrap();
-
-    // Create a reminder for tomorrow's date
-    let tomorrow = Utc::now().naive_utc().date() + chrono::Duration::days(1);
-    let birthday_timestamp = create_test_timestamp(1991, tomorrow.month(), tomorrow.day());
-    create_reminder(&db, user.id, "Jane", &birthday_timestamp.to_string())
+    update_user_settings(&db, user.id, &daily_offsets(3), 10, "America/Los_Angeles")
         .await
         .unwrap();
 
@@ -76,6 +86,13 @@ async fn test_birthday_tomorrow_at_send_time() {
         .unwrap();
     let utc_time = pacific_10am.with_timezone(&Utc);
 
+    // Create a reminder for tomorrow's date
+    let tomorrow = pacific_10am.date_naive() + chrono::Duration::days(1);
+    let birthday_timestamp = create_test_timestamp(1991, tomorrow.month(), tomorrow.day());
+    create_reminder(&db, user.id, "Jane", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
     let messages = get_birthday_messages(&db, utc_time).await.unwrap();
 
     assert_eq!(messages.len(), 1);
@@ -91,12 +108,18 @@ async fn test_multiple_birthdays_same_user() {
 
     // Create a user with 3 days notice
     let user = create_user(&db, "1234567892").await.unwrap();
-    update_user_settings(&db, user.id, 3, 9, "America/New_York")
+    update_user_settings(&db, user.id, &daily_offsets(3), 9, "America/New_York")
         .await
         .unwrap();
 
+    // Test at 9 AM Eastern
+    let eastern_9am = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap();
+    let utc_time = eastern_9am.with_timezone(&Utc);
+
     // Create multiple reminders
-    let today = Utc::now().naive_utc().date();
+    let today = eastern_9am.date_naive();
     let birthday_today = create_test_timestamp(1990, today.month(), today.day());
     let birthday_tomorrow = create_test_timestamp(
         1991,
@@ -119,12 +142,6 @@ async fn test_multiple_birthdays_same_user() {
         .await
         .unwrap();
 
-    // Test at 9 AM Eastern
-    let eastern_9am = chrono_tz::US::Eastern
-        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
-        .unwrap();
-    let utc_time = eastern_9am.with_timezone(&Utc);
-
     let messages = get_birthday_messages(&db, utc_time).await.unwrap();
 
     assert_eq!(messages.len(), 1);
@@ -144,14 +161,7 @@ async fn test_wrong_send_time_no_messages() {
 
     // Create a user who gets messages at 9 AM
     let user = create_user(&db, "1234567893").await.unwrap();
-    update_user_settings(&db, user.id, 7, 9, "America/New_York")
-        .await
-        .unwrap();
-
-    // Create a birthday today
-    let today = Utc::now().naive_utc().date();
-    let birthday_timestamp = create_test_timestamp(1990, today.month(), today.day());
-    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+    update_user_settings(&db, user.id, &daily_offsets(7), 9, "America/New_York")
         .await
         .unwrap();
 
@@ -161,6 +171,13 @@ async fn test_wrong_send_time_no_messages() {
         .unwrap();
     let utc_time = eastern_8am.with_timezone(&Utc);
 
+    // Create a birthday today
+    let today = eastern_8am.date_naive();
+    let birthday_timestamp = create_test_timestamp(1990, today.month(), today.day());
+    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
     let messages = get_birthday_messages(&db, utc_time).await.unwrap();
 
     assert_eq!(messages.len(), 0);
@@ -172,12 +189,18 @@ async fn test_birthday_outside_notice_period() {
 
     // Create a user with only 1 day notice
     let user = create_user(&db, "1234567894").await.unwrap();
-    update_user_settings(&db, user.id, 1, 9, "America/New_York")
+    update_user_settings(&db, user.id, &daily_offsets(1), 9, "America/New_York")
         .await
         .unwrap();
 
+    // Test at correct send time
+    let eastern_9am = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap();
+    let utc_time = eastern_9am.with_timezone(&Utc);
+
     // Create a birthday in 3 days (outside notice period)
-    let today = Utc::now().naive_utc().date();
+    let today = eastern_9am.date_naive();
     let birthday_in_3_days = create_test_timestamp(
         1990,
         (today + chrono::Duration::days(3)).month(),
@@ -187,12 +210,6 @@ async fn test_birthday_outside_notice_period() {
         .await
         .unwrap();
 
-    // Test at correct send time
-    let eastern_9am = chrono_tz::US::Eastern
-        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
-        .unwrap();
-    let utc_time = eastern_9am.with_timezone(&Utc);
-
     let messages = get_birthday_messages(&db, utc_time).await.unwrap();
 
     assert_eq!(messages.len(), 0);
@@ -204,12 +221,18 @@ async fn test_message_length_truncation() {
 
     // Create a user
     let user = create_user(&db, "1234567895").await.unwrap();
-    update_user_settings(&db, user.id, 7, 9, "America/New_York")
+    update_user_settings(&db, user.id, &daily_offsets(7), 9, "America/New_York")
         .await
         .unwrap();
 
+    // Test at correct send time
+    let eastern_9am = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap();
+    let utc_time = eastern_9am.with_timezone(&Utc);
+
     // Create many reminders to test truncation
-    let today = Utc::now().naive_utc().date();
+    let today = eastern_9am.date_naive();
     for i in 0..10 {
         let birthday_timestamp = create_test_timestamp(1990 + i, today.month(), today.day());
         create_reminder(
@@ -222,20 +245,23 @@ async fn test_message_length_truncation() {
         .unwrap();
     }
 
-    // Test at correct send time
-    let eastern_9am = chrono_tz::US::Eastern
-        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
-        .unwrap();
-    let utc_time = eastern_9am.with_timezone(&Utc);
-
     let messages = get_birthday_messages(&db, utc_time).await.unwrap();
 
-    assert_eq!(messages.len(), 1);
-    let message = &messages[0].message;
-    assert!(message.len() <= 160);
-    assert!(message.contains("hbd.bot"));
-    // Should contain "and X more" since not all reminders fit
-    assert!(message.contains("and ") && message.contains("more"));
+    // Not all ten fit in one SMS, so the rest go out as follow-ups
+    assert!(messages.len() > 1);
+    assert!(messages[0].message.contains("more..."));
+    let mut covered = Vec::new();
+    for message in &messages {
+        assert!(message.message.len() <= 160);
+        assert!(message.message.ends_with("https://hbd.bot"));
+        // Each digest only records the reminders it names
+        let named = message.message.matches("VeryLongNamePersonNumber").count();
+        assert_eq!(message.entries.len(), named);
+        covered.extend(message.entries.iter().map(|entry| entry.reminder_id));
+    }
+    covered.sort();
+    covered.dedup();
+    assert_eq!(covered.len(), 10);
 }
 
 #[tokio::test]
@@ -244,18 +270,24 @@ async fn test_multiple_users_different_timezones() {
 
     // Create user in Eastern timezone
     let user1 = create_user(&db, "1234567896").await.unwrap();
-    update_user_settings(&db, user1.id, 7, 9, "America/New_York")
+    update_user_settings(&db, user1.id, &daily_offsets(7), 9, "America/New_York")
         .await
         .unwrap();
 
     // Create user in Pacific timezone
     let user2 = create_user(&db, "1234567897").await.unwrap();
-    update_user_settings(&db, user2.id, 7, 10, "America/Los_Angeles")
+    update_user_settings(&db, user2.id, &daily_offsets(7), 10, "America/Los_Angeles")
         .await
         .unwrap();
 
+    // Test at 9 AM Eastern (only user1 should get message)
+    let eastern_9am = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap();
+    let utc_time = eastern_9am.with_timezone(&Utc);
+
     // Create birthdays for both users
-    let today = Utc::now().naive_utc().date();
+    let today = eastern_9am.date_naive();
     let birthday_timestamp = create_test_timestamp(1990, today.month(), today.day());
 
     create_reminder(&db, user1.id, "John", &birthday_timestamp.to_string())
@@ -265,12 +297,6 @@ async fn test_multiple_users_different_timezones() {
         .await
         .unwrap();
 
-    // Test at 9 AM Eastern (only user1 should get message)
-    let eastern_9am = chrono_tz::US::Eastern
-        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
-        .unwrap();
-    let utc_time = eastern_9am.with_timezone(&Utc);
-
     let messages = get_birthday_messages(&db, utc_time).await.unwrap();
 
     assert_eq!(messages.len(), 1);
@@ -296,14 +322,7 @@ async fn test_twelve_hour_notification_guard() {
 
     // Create a user who receives messages at both 9 AM and 10 PM
     let user = create_user(&db, "1234567898").await.unwrap();
-    update_user_settings(&db, user.id, 7, 9, "America/New_York")
-        .await
-        .unwrap();
-
-    // Create a birthday today
-    let today = Utc::now().naive_utc().date();
-    let birthday_timestamp = create_test_timestamp(1990, today.month(), today.day());
-    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+    update_user_settings(&db, user.id, &daily_offsets(7), 9, "America/New_York")
         .await
         .unwrap();
 
@@ -313,13 +332,23 @@ async fn test_twelve_hour_notification_guard() {
         .unwrap();
     let utc_time = eastern_9am.with_timezone(&Utc);
 
+    // Create a birthday today
+    let today = eastern_9am.date_naive();
+    let birthday_timestamp = create_test_timestamp(1990, today.month(), today.day());
+    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
     let messages = get_birthday_messages(&db, utc_time).await.unwrap();
     assert_eq!(messages.len(), 1);
     assert_eq!(messages[0].phone_number, "1234567898");
     assert!(messages[0].message.contains("John's"));
 
-    // Simulate the actual sending process by updating last_digest_at
-    update_user_last_digest_at(&db, user.id).await.unwrap();
+    // Send it, which records the digest and updates last_digest_at
+    let sender = MemorySender::new();
+    run_sweeper(&db, &sender, &FixedClock(utc_time))
+        .await
+        .unwrap();
 
     // Second call 1 hour later (10 AM Eastern) - should NOT send message due to 12-hour guard
     let eastern_10am = chrono_tz::US::Eastern
@@ -330,8 +359,9 @@ async fn test_twelve_hour_notification_guard() {
     let messages_2 = get_birthday_messages(&db, utc_time_2).await.unwrap();
     assert_eq!(messages_2.len(), 0);
 
-    // Update user to receive messages at 10 PM and test 13 hours later (same day at 10 PM Eastern) - should send message again
-    update_user_settings(&db, user.id, 7, 22, "America/New_York")
+    // Update user to receive messages at 10 PM and test 13 hours later (same day at 10 PM Eastern) - the 12-hour guard has passed, but
+    // the ledger already has John's birthday at this offset, so nothing is sent again
+    update_user_settings(&db, user.id, &daily_offsets(7), 22, "America/New_York")
         .await
         .unwrap();
     let eastern_10pm_same_day = chrono_tz::US::Eastern
@@ -342,3 +372,920 @@ async fn test_twelve_hour_notification_guard() {
     let messages_3 = get_birthday_messages(&db, utc_time_3).await.unwrap();
     assert_eq!(messages_3.len(), 0);
 }
+
+#[tokio::test]
+async fn test_sent_digest_is_recorded_in_ledger() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let user = create_user(&db, "1234567899").await.unwrap();
+    update_user_settings(&db, user.id, &daily_offsets(7), 9, "America/New_York")
+        .await
+        .unwrap();
+
+    let birthday_timestamp = create_test_timestamp(1990, 1, 16);
+    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
+    let utc_time = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc);
+
+    let messages = get_birthday_messages(&db, utc_time).await.unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].entries.len(), 1);
+    assert_eq!(
+        messages[0].entries[0].birthday_date,
+        NaiveDate::from_ymd_opt(2024, 1, 16).unwrap()
+    );
+    assert_eq!(messages[0].entries[0].days_until, 1);
+
+    let sender = MemorySender::new();
+    let report = run_sweeper(&db, &sender, &FixedClock(utc_time))
+        .await
+        .unwrap();
+    assert_eq!(report.sent, 1);
+    assert_eq!(sender.sent().len(), 1);
+    assert_eq!(sender.sent()[0].to, "+11234567899");
+
+    let ledger = get_sent_messages_by_user_id(&db, user.id).await.unwrap();
+    assert_eq!(ledger.len(), 1);
+    assert_eq!(ledger[0].status, STATUS_SENT);
+    assert_eq!(ledger[0].provider, "memory");
+    assert_eq!(ledger[0].provider_message_id.as_deref(), Some("memory-1"));
+    assert_eq!(ledger[0].body, messages[0].message);
+}
+
+#[tokio::test]
+async fn test_overlapping_runs_send_once() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let user = create_user(&db, "1234567800").await.unwrap();
+    update_user_settings(&db, user.id, &daily_offsets(7), 9, "America/New_York")
+        .await
+        .unwrap();
+
+    let birthday_timestamp = create_test_timestamp(1990, 1, 15);
+    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
+    let utc_time = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc);
+
+    // Two runs (e.g. the hourly cron and a manual /run-sweeper) both build the
+    // digest before either has sent it
+    let first_run = get_birthday_messages(&db, utc_time).await.unwrap();
+    let second_run = get_birthday_messages(&db, utc_time).await.unwrap();
+    assert_eq!(first_run.len(), 1);
+    assert_eq!(second_run.len(), 1);
+
+    let sender = MemorySender::new();
+    let report = run_sweeper(&db, &sender, &FixedClock(utc_time))
+        .await
+        .unwrap();
+    assert_eq!(report.sent, 1);
+
+    // The first run's claim holds, so the second digest is never sent
+    let second = claim_digest(
+        &db,
+        user.id,
+        "memory",
+        &second_run[0].message,
+        &second_run[0].entries,
+        utc_time,
+    )
+    .await
+    .unwrap();
+    assert!(second.is_none());
+    assert_eq!(sender.sent().len(), 1);
+}
+
+#[tokio::test]
+async fn test_stale_claim_is_taken_over() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let user = create_user(&db, "1234567821").await.unwrap();
+    update_user_settings(&db, user.id, &daily_offsets(7), 9, "America/New_York")
+        .await
+        .unwrap();
+
+    let birthday_timestamp = create_test_timestamp(1990, 1, 15);
+    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
+    let utc_time = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc);
+
+    // A run claims the digest and dies before sending it
+    let messages = get_birthday_messages(&db, utc_time).await.unwrap();
+    let stale_id = claim_digest(
+        &db,
+        user.id,
+        "memory",
+        &messages[0].message,
+        &messages[0].entries,
+        utc_time,
+    )
+    .await
+    .unwrap()
+    .unwrap();
+
+    // While the claim is fresh, the dying run might still be sending
+    let sender = MemorySender::new();
+    let soon = utc_time + chrono::Duration::minutes(CLAIM_LEASE_MINUTES - 1);
+    let report = run_sweeper(&db, &sender, &FixedClock(soon)).await.unwrap();
+    assert_eq!(report.sent, 0);
+    assert!(sender.sent().is_empty());
+
+    let later = utc_time + chrono::Duration::minutes(CLAIM_LEASE_MINUTES);
+    let report = run_sweeper(&db, &sender, &FixedClock(later)).await.unwrap();
+    assert_eq!(report.sent, 1);
+    assert_eq!(sender.sent().len(), 1);
+
+    let dead = get_dead_letters(&db).await.unwrap();
+    assert_eq!(dead.len(), 1);
+    assert_eq!(dead[0].id, stale_id);
+}
+
+#[tokio::test]
+async fn test_same_reminder_sent_again_at_new_offset() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let user = create_user(&db, "1234567801").await.unwrap();
+    update_user_settings(&db, user.id, &daily_offsets(7), 9, "America/New_York")
+        .await
+        .unwrap();
+
+    let birthday_timestamp = create_test_timestamp(1990, 1, 16);
+    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
+    let sender = MemorySender::new();
+
+    // "Tomorrow" on the 15th
+    let day_one = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc);
+    let messages = get_birthday_messages(&db, day_one).await.unwrap();
+    assert_eq!(messages.len(), 1);
+    run_sweeper(&db, &sender, &FixedClock(day_one))
+        .await
+        .unwrap();
+
+    // "Today" on the 16th is a different offset, so it still goes out
+    let day_two = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 16, 9, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc);
+    let messages = get_birthday_messages(&db, day_two).await.unwrap();
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].message.contains("today"));
+}
+
+fn transient_error() -> SmsError {
+    SmsError {
+        message: "503 Service Unavailable".to_string(),
+        permanent: false,
+    }
+}
+
+#[tokio::test]
+async fn test_failed_send_is_retried_with_backoff() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let user = create_user(&db, "1234567802").await.unwrap();
+    update_user_settings(&db, user.id, &daily_offsets(7), 9, "America/New_York")
+        .await
+        .unwrap();
+
+    let birthday_timestamp = create_test_timestamp(1990, 1, 15);
+    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
+    let utc_time = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc);
+
+    let sender = MemorySender::new();
+    sender.fail_next(transient_error());
+
+    let report = run_sweeper(&db, &sender, &FixedClock(utc_time))
+        .await
+        .unwrap();
+    assert!(report.failures[0].will_retry);
+    assert!(sender.sent().is_empty());
+
+    let ledger = get_sent_messages_by_user_id(&db, user.id).await.unwrap();
+    assert_eq!(ledger[0].status, STATUS_RETRYING);
+    assert_eq!(ledger[0].attempts, 1);
+    assert_eq!(
+        ledger[0].next_attempt_at.as_deref(),
+        Some("2024-01-15 14:05:00")
+    );
+
+    // The queued digest still holds its reminders, so a new run doesn't
+    // build a second copy
+    let messages = get_birthday_messages(&db, utc_time + chrono::Duration::minutes(1))
+        .await
+        .unwrap();
+    assert!(messages.is_empty());
+
+    // Not due yet
+    let report = run_sweeper(
+        &db,
+        &sender,
+        &FixedClock(utc_time + chrono::Duration::minutes(4)),
+    )
+    .await
+    .unwrap();
+    assert_eq!(report.attempted, 0);
+
+    // Due after the 5 minute backoff
+    let report = run_sweeper(
+        &db,
+        &sender,
+        &FixedClock(utc_time + chrono::Duration::minutes(5)),
+    )
+    .await
+    .unwrap();
+    assert_eq!(report.attempted, 1);
+    assert_eq!(report.sent, 1);
+    assert_eq!(sender.sent().len(), 1);
+    assert!(sender.sent()[0].body.contains("John's"));
+
+    let ledger = get_sent_messages_by_user_id(&db, user.id).await.unwrap();
+    assert_eq!(ledger[0].status, STATUS_SENT);
+    assert_eq!(ledger[0].attempts, 2);
+    assert!(ledger[0].next_attempt_at.is_none());
+}
+
+/// Takes a moment over each send, so an overlapping run gets going meanwhile
+struct SlowSender(MemorySender);
+
+#[async_trait]
+impl SmsSender for SlowSender {
+    fn provider(&self) -> &'static str {
+        self.0.provider()
+    }
+
+    async fn send_sms(&self, to: &str, body: &str) -> Result<SmsReceipt, SmsError> {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        self.0.send_sms(to, body).await
+    }
+}
+
+#[tokio::test]
+async fn test_overlapping_runs_retry_once() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let user = create_user(&db, "1234567822").await.unwrap();
+    update_user_settings(&db, user.id, &daily_offsets(7), 9, "America/New_York")
+        .await
+        .unwrap();
+
+    let birthday_timestamp = create_test_timestamp(1990, 1, 15);
+    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
+    let utc_time = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc);
+
+    let sender = SlowSender(MemorySender::new());
+    sender.0.fail_next(transient_error());
+    run_sweeper(&db, &sender, &FixedClock(utc_time))
+        .await
+        .unwrap();
+    assert!(sender.0.sent().is_empty());
+
+    // The cron run and a manual /run-sweeper both find the retry due
+    let due = FixedClock(utc_time + chrono::Duration::minutes(5));
+    let (first, second) = tokio::join!(
+        run_sweeper(&db, &sender, &due),
+        run_sweeper(&db, &sender, &due),
+    );
+    assert_eq!(first.unwrap().sent + second.unwrap().sent, 1);
+    assert_eq!(sender.0.sent().len(), 1);
+
+    let ledger = get_sent_messages_by_user_id(&db, user.id).await.unwrap();
+    assert_eq!(ledger.len(), 1);
+    assert_eq!(ledger[0].status, STATUS_SENT);
+    assert_eq!(ledger[0].attempts, 2);
+}
+
+#[tokio::test]
+async fn test_permanent_failure_goes_to_dead_letters() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let user = create_user(&db, "1234567803").await.unwrap();
+    update_user_settings(&db, user.id, &daily_offsets(7), 9, "America/New_York")
+        .await
+        .unwrap();
+
+    let birthday_timestamp = create_test_timestamp(1990, 1, 15);
+    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
+    let utc_time = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc);
+
+    let sender = MemorySender::new();
+    sender.fail_next(SmsError {
+        message: "Invalid phone number".to_string(),
+        permanent: true,
+    });
+
+    let report = run_sweeper(&db, &sender, &FixedClock(utc_time))
+        .await
+        .unwrap();
+    assert_eq!(report.failures[0].error, "Invalid phone number");
+    assert!(!report.failures[0].will_retry);
+
+    // Dead letters are never retried automatically
+    let report = run_sweeper(
+        &db,
+        &sender,
+        &FixedClock(utc_time + chrono::Duration::days(1)),
+    )
+    .await
+    .unwrap();
+    assert_eq!(report.attempted, 0);
+    assert!(sender.sent().is_empty());
+
+    let dead_letters = get_dead_letters(&db).await.unwrap();
+    assert_eq!(dead_letters.len(), 1);
+    assert_eq!(dead_letters[0].user_id, user.id);
+    assert_eq!(dead_letters[0].status, STATUS_DEAD);
+    assert_eq!(
+        dead_letters[0].error.as_deref(),
+        Some("Invalid phone number")
+    );
+}
+
+#[tokio::test]
+async fn test_retries_stop_after_max_attempts() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let user = create_user(&db, "1234567804").await.unwrap();
+    update_user_settings(&db, user.id, &daily_offsets(7), 9, "America/New_York")
+        .await
+        .unwrap();
+
+    let birthday_timestamp = create_test_timestamp(1990, 1, 15);
+    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
+    let mut utc_time = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc);
+
+    let sender = MemorySender::new();
+    for _ in 0..MAX_SEND_ATTEMPTS {
+        sender.fail_next(transient_error());
+    }
+
+    run_sweeper(&db, &sender, &FixedClock(utc_time))
+        .await
+        .unwrap();
+
+    let mut will_retry = true;
+    for _ in 1..MAX_SEND_ATTEMPTS {
+        utc_time += chrono::Duration::hours(6);
+        let report = run_sweeper(&db, &sender, &FixedClock(utc_time))
+            .await
+            .unwrap();
+        assert_eq!(report.attempted, 1);
+        will_retry = report.failures[0].will_retry;
+    }
+
+    assert!(!will_retry);
+    let ledger = get_sent_messages_by_user_id(&db, user.id).await.unwrap();
+    assert_eq!(ledger[0].status, STATUS_DEAD);
+    assert_eq!(ledger[0].attempts, MAX_SEND_ATTEMPTS);
+    assert!(sender.sent().is_empty());
+}
+
+#[tokio::test]
+async fn test_run_sweeper_sends_every_due_digest() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let first = create_user(&db, "1234567805").await.unwrap();
+    let second = create_user(&db, "1234567806").await.unwrap();
+    let not_yet = create_user(&db, "1234567807").await.unwrap();
+    update_user_settings(&db, first.id, &daily_offsets(7), 9, "America/New_York")
+        .await
+        .unwrap();
+    update_user_settings(&db, second.id, &daily_offsets(7), 9, "America/New_York")
+        .await
+        .unwrap();
+    update_user_settings(&db, not_yet.id, &daily_offsets(7), 10, "America/New_York")
+        .await
+        .unwrap();
+
+    let birthday_timestamp = create_test_timestamp(1990, 1, 15);
+    for user in [&first, &second, &not_yet] {
+        create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+            .await
+            .unwrap();
+    }
+
+    let clock = FixedClock(
+        chrono_tz::US::Eastern
+            .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc),
+    );
+    let sender = MemorySender::new();
+
+    let report = run_sweeper(&db, &sender, &clock).await.unwrap();
+
+    // The first digest of the run goes out too
+    let recipients: Vec<String> = sender.sent().into_iter().map(|sms| sms.to).collect();
+    assert_eq!(recipients, vec!["+11234567805", "+11234567806"]);
+    assert_eq!(report.attempted, 2);
+    assert_eq!(report.sent, 2);
+    assert_eq!(report.failed, 0);
+    assert_eq!(report.skipped, 0);
+    assert_eq!(report.not_send_time, 1);
+
+    // Running again at the same time sends nothing new
+    let report = run_sweeper(&db, &sender, &clock).await.unwrap();
+    assert_eq!(report.attempted, 0);
+    assert_eq!(sender.sent().len(), 2);
+    assert!(report
+        .skipped_users
+        .iter()
+        .any(|skip| skip.user_id == first.id && skip.reason == SkipReason::NotifiedRecently));
+}
+
+#[tokio::test]
+async fn test_run_sweeper_reports_failures() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let user = create_user(&db, "1234567808").await.unwrap();
+    update_user_settings(&db, user.id, &daily_offsets(7), 9, "America/New_York")
+        .await
+        .unwrap();
+
+    let birthday_timestamp = create_test_timestamp(1990, 1, 15);
+    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
+    let utc_time = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc);
+    let sender = MemorySender::new();
+    sender.fail_next(transient_error());
+
+    let report = run_sweeper(&db, &sender, &FixedClock(utc_time))
+        .await
+        .unwrap();
+    assert_eq!(report.attempted, 1);
+    assert_eq!(report.sent, 0);
+    assert_eq!(report.failed, 1);
+    assert_eq!(report.failures[0].user_id, user.id);
+    assert!(report.failures[0].will_retry);
+
+    // The next hourly run picks it up from the retry queue
+    let report = run_sweeper(
+        &db,
+        &sender,
+        &FixedClock(utc_time + chrono::Duration::hours(1)),
+    )
+    .await
+    .unwrap();
+    assert_eq!(report.attempted, 1);
+    assert_eq!(report.sent, 1);
+    assert_eq!(sender.sent().len(), 1);
+}
+
+#[tokio::test]
+async fn test_opted_out_user_is_skipped_until_start() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let user = create_user(&db, "1234567809").await.unwrap();
+    update_user_settings(&db, user.id, &daily_offsets(7), 9, "America/New_York")
+        .await
+        .unwrap();
+
+    let birthday_timestamp = create_test_timestamp(1990, 1, 15);
+    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
+    let utc_time = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc);
+
+    set_user_sms_opt_out(&db, user.id, true).await.unwrap();
+    assert!(get_birthday_messages(&db, utc_time)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let sender = MemorySender::new();
+    let report = run_sweeper(&db, &sender, &FixedClock(utc_time))
+        .await
+        .unwrap();
+    assert_eq!(report.attempted, 0);
+    assert!(report
+        .skipped_users
+        .iter()
+        .any(|skip| skip.user_id == user.id && skip.reason == SkipReason::OptedOut));
+
+    set_user_sms_opt_out(&db, user.id, false).await.unwrap();
+    let messages = get_birthday_messages(&db, utc_time).await.unwrap();
+    assert_eq!(messages.len(), 1);
+}
+
+#[tokio::test]
+async fn test_retry_is_dropped_after_opt_out() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let user = create_user(&db, "1234567810").await.unwrap();
+    update_user_settings(&db, user.id, &daily_offsets(7), 9, "America/New_York")
+        .await
+        .unwrap();
+
+    let birthday_timestamp = create_test_timestamp(1990, 1, 15);
+    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
+    let utc_time = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc);
+    let sender = MemorySender::new();
+    sender.fail_next(transient_error());
+    run_sweeper(&db, &sender, &FixedClock(utc_time))
+        .await
+        .unwrap();
+
+    set_user_sms_opt_out(&db, user.id, true).await.unwrap();
+    let later = utc_time + chrono::Duration::hours(1);
+    let report = run_sweeper(&db, &sender, &FixedClock(later)).await.unwrap();
+
+    assert_eq!(report.failures.len(), 1);
+    assert!(!report.failures[0].will_retry);
+    assert!(sender.sent().is_empty());
+    assert_eq!(get_dead_letters(&db).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_snooze_and_done_replies() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let user = create_user(&db, "1234567811").await.unwrap();
+    update_user_settings(&db, user.id, &[5, 1, 0], 9, "America/New_York")
+        .await
+        .unwrap();
+
+    let birthday_timestamp = create_test_timestamp(1990, 1, 20);
+    create_reminder(&db, user.id, "Jane", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
+    let at_9am = |day: u32| {
+        chrono_tz::US::Eastern
+            .with_ymd_and_hms(2024, 1, day, 9, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    };
+    let sender = MemorySender::new();
+
+    run_sweeper(&db, &sender, &FixedClock(at_9am(15)))
+        .await
+        .unwrap();
+    assert_eq!(sender.sent().len(), 1);
+
+    // SNOOZE 2 holds Jane back on the 16th and brings her back on the 17th
+    let user = get_user_by_id(&db, user.id).await.unwrap().unwrap();
+    let reply = execute_command(
+        &db,
+        &user,
+        parse_command("SNOOZE 2").unwrap(),
+        at_9am(15) + chrono::Duration::minutes(5),
+    )
+    .await
+    .unwrap();
+    assert_eq!(reply, "Snoozed Jane for 2 days.");
+
+    let report = run_sweeper(&db, &sender, &FixedClock(at_9am(16)))
+        .await
+        .unwrap();
+    assert_eq!(report.attempted, 0);
+    assert!(report
+        .skipped_users
+        .iter()
+        .any(|skip| skip.user_id == user.id && skip.reason == SkipReason::NothingDue));
+
+    run_sweeper(&db, &sender, &FixedClock(at_9am(17)))
+        .await
+        .unwrap();
+    assert_eq!(sender.sent().len(), 2);
+    assert!(sender.sent()[1].body.contains("Jane's 34th is in 3 days"));
+
+    // DONE drops her for the rest of this year's reminders
+    let reply = execute_command(&db, &user, parse_command("DONE jane").unwrap(), at_9am(17))
+        .await
+        .unwrap();
+    assert_eq!(reply, "Nice! No more reminders for Jane until next year.");
+
+    for day in 18..=20 {
+        let report = run_sweeper(&db, &sender, &FixedClock(at_9am(day)))
+            .await
+            .unwrap();
+        assert_eq!(report.attempted, 0);
+    }
+    assert_eq!(sender.sent().len(), 2);
+}
+
+#[tokio::test]
+async fn test_snooze_stops_at_the_birthday() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let user = create_user(&db, "1234567812").await.unwrap();
+    update_user_settings(&db, user.id, &[1, 0], 9, "America/New_York")
+        .await
+        .unwrap();
+
+    let birthday_timestamp = create_test_timestamp(1990, 1, 16);
+    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
+    let utc_time = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc);
+    let sender = MemorySender::new();
+    run_sweeper(&db, &sender, &FixedClock(utc_time))
+        .await
+        .unwrap();
+
+    let user = get_user_by_id(&db, user.id).await.unwrap().unwrap();
+    execute_command(&db, &user, parse_command("SNOOZE 5").unwrap(), utc_time)
+        .await
+        .unwrap();
+
+    // Snoozing can't skip the day itself
+    run_sweeper(
+        &db,
+        &sender,
+        &FixedClock(utc_time + chrono::Duration::days(1)),
+    )
+    .await
+    .unwrap();
+    assert_eq!(sender.sent().len(), 2);
+    assert!(sender.sent()[1].body.contains("John's 34th is today"));
+}
+
+#[tokio::test]
+async fn test_reminders_only_on_offset_days() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let user = create_user(&db, "1234567813").await.unwrap();
+    update_user_settings(&db, user.id, &[14, 3, 0], 9, "America/New_York")
+        .await
+        .unwrap();
+
+    // Jan 29 is 14 days after the test date
+    let birthday_timestamp = create_test_timestamp(1990, 1, 29);
+    create_reminder(&db, user.id, "Jane", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
+    let sender = MemorySender::new();
+    let mut sent_on = Vec::new();
+    for day in 15..=29 {
+        let utc_time = chrono_tz::US::Eastern
+            .with_ymd_and_hms(2024, 1, day, 9, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc);
+        let report = run_sweeper(&db, &sender, &FixedClock(utc_time))
+            .await
+            .unwrap();
+        if report.sent > 0 {
+            sent_on.push(day);
+        }
+    }
+
+    assert_eq!(sent_on, vec![15, 26, 29]);
+    let sent = sender.sent();
+    assert!(sent[0].body.contains("Jane's 34th is in 14 days"));
+    assert!(sent[1].body.contains("Jane's 34th is in 3 days"));
+    assert!(sent[2].body.contains("Jane's 34th is today"));
+}
+
+#[tokio::test]
+async fn test_reminder_notice_override() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let user = create_user(&db, "1234567814").await.unwrap();
+    update_user_settings(&db, user.id, &[0], 9, "America/New_York")
+        .await
+        .unwrap();
+
+    // Both birthdays are 10 days out; only Jane's reminder asks for that much notice
+    let birthday_timestamp = create_test_timestamp(1990, 1, 25);
+    let jane = create_reminder(&db, user.id, "Jane", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+    update_reminder_notice_offsets(&db, jane.id, Some(&[30, 10]))
+        .await
+        .unwrap();
+
+    let at_9am = |day: u32| {
+        chrono_tz::US::Eastern
+            .with_ymd_and_hms(2024, 1, day, 9, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    };
+
+    let messages = get_birthday_messages(&db, at_9am(15)).await.unwrap();
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].message.contains("Jane's"));
+    assert!(!messages[0].message.contains("John's"));
+
+    // On the day, John (user default) is due but Jane's override has no day-of
+    let messages = get_birthday_messages(&db, at_9am(25)).await.unwrap();
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].message.contains("John's"));
+    assert!(!messages[0].message.contains("Jane's"));
+
+    // Clearing the override falls back to the user's offsets
+    update_reminder_notice_offsets(&db, jane.id, None)
+        .await
+        .unwrap();
+    let messages = get_birthday_messages(&db, at_9am(25)).await.unwrap();
+    assert!(messages[0].message.contains("Jane's"));
+}
+
+#[tokio::test]
+async fn test_birthday_without_year_has_no_age() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let user = create_user(&db, "1234567815").await.unwrap();
+    update_user_settings(&db, user.id, &[1, 0], 9, "America/New_York")
+        .await
+        .unwrap();
+
+    let birthday_timestamp = create_test_timestamp(UNKNOWN_BIRTH_YEAR, 1, 16);
+    let jane = create_reminder(&db, user.id, "Jane", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+    update_reminder_year_known(&db, jane.id, false)
+        .await
+        .unwrap();
+
+    let utc_time = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc);
+    let messages = get_birthday_messages(&db, utc_time).await.unwrap();
+
+    assert_eq!(messages.len(), 1);
+    assert_eq!(
+        messages[0].message,
+        "Jane's birthday is tomorrow\nhttps://hbd.bot"
+    );
+}
+
+#[tokio::test]
+async fn test_leap_day_birthday_in_common_year() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let early = create_user(&db, "1234567816").await.unwrap();
+    let late = create_user(&db, "1234567817").await.unwrap();
+    for user in [&early, &late] {
+        update_user_settings(&db, user.id, &[0], 9, "America/New_York")
+            .await
+            .unwrap();
+        let birthday_timestamp = create_test_timestamp(1992, 2, 29);
+        create_reminder(&db, user.id, "Leap", &birthday_timestamp.to_string())
+            .await
+            .unwrap();
+    }
+    update_user_leap_day_policy(&db, late.id, LeapDayPolicy::Mar1)
+        .await
+        .unwrap();
+
+    let at_9am = |month: u32, day: u32| {
+        chrono_tz::US::Eastern
+            .with_ymd_and_hms(2023, month, day, 9, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    };
+
+    let messages = get_birthday_messages(&db, at_9am(2, 28)).await.unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].user_id, early.id);
+    assert!(messages[0].message.contains("Leap's 31st is today"));
+
+    let messages = get_birthday_messages(&db, at_9am(3, 1)).await.unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].user_id, late.id);
+    assert!(messages[0].message.contains("Leap's 31st is today"));
+}
+
+#[tokio::test]
+async fn test_event_types_use_their_own_wording() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let events = [
+        (
+            "1234567818",
+            "Sam & Alex",
+            2014,
+            15,
+            EventType::WeddingAnniversary,
+        ),
+        ("1234567819", "Dad", 2019, 16, EventType::Memorial),
+        (
+            "1234567820",
+            "Rex",
+            2022,
+            17,
+            EventType::Custom("adoption day".to_string()),
+        ),
+    ];
+    for (phone, name, year, day, event_type) in events {
+        let user = create_user(&db, phone).await.unwrap();
+        update_user_settings(&db, user.id, &daily_offsets(3), 9, "America/New_York")
+            .await
+            .unwrap();
+        let timestamp = create_test_timestamp(year, 1, day);
+        let reminder = create_reminder(&db, user.id, name, &timestamp.to_string())
+            .await
+            .unwrap();
+        update_reminder_event_type(&db, reminder.id, &event_type)
+            .await
+            .unwrap();
+    }
+
+    let utc_time = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc);
+    let messages = get_birthday_messages(&db, utc_time).await.unwrap();
+
+    let bodies: Vec<&str> = messages.iter().map(|m| m.message.as_str()).collect();
+    assert_eq!(
+        bodies,
+        vec![
+            "Sam & Alex's 10th anniversary is today\nhttps://hbd.bot",
+            "Remembering Dad - 5 years tomorrow\nhttps://hbd.bot",
+            "Rex's 2nd adoption day is in 2 days\nhttps://hbd.bot",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_invalid_timezone_falls_back_to_new_york() {
+    let (db, _temp_file) = setup_test_database().await;
+
+    let user = create_user(&db, "1234567822").await.unwrap();
+    update_user_settings(&db, user.id, &daily_offsets(3), 9, "Not/A_Zone")
+        .await
+        .unwrap();
+
+    let birthday_timestamp = create_test_timestamp(1990, 1, 15);
+    create_reminder(&db, user.id, "John", &birthday_timestamp.to_string())
+        .await
+        .unwrap();
+
+    // Same send hour and "today" as a New York user, like the dashboard shows
+    let utc_time = chrono_tz::US::Eastern
+        .with_ymd_and_hms(2024, 1, 15, 9, 0, 0)
+        .unwrap()
+        .with_timezone(&Utc);
+    let messages = get_birthday_messages(&db, utc_time).await.unwrap();
+
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].message.contains("John's 34th is today"));
+}
@@ -20,12 +20,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Check that the defaults were applied
     println!("Created user with ID: {}", user.id);
     println!("Phone number: {}", user.phone_number);
-    println!("Days notice: {}", user.days_notice);
+    println!("Notice offsets: {:?}", user.notice_offsets);
     println!("Send hour: {}", user.send_hour);
     println!("Timezone: {}", user.iana_tz);
 
     // Verify the defaults
-    assert_eq!(user.days_notice, 7, "days_notice should default to 7");
+    assert_eq!(user.notice_offsets, vec![7, 1, 0], "notice_offsets should default to 7,1,0");
     assert_eq!(user.send_hour, 9, "send_hour should default to 9");
     assert_eq!(user.iana_tz, "America/New_York", "iana_tz should default to America/New_York");
 
@@ -0,0 +1,356 @@
+use askama::Template;
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Redirect},
+    Form,
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar};
+use chrono::Utc;
+use common::export::{build_export, export_json, reminders_csv, settings_csv, UserExport};
+use common::otp_sessions::{
+    begin_otp_attempt, create_otp_session, delete_otp_session, OtpAttempt, OtpPurpose,
+};
+use common::rate_limit::check_login_limits;
+use common::{delete_user, get_reminders_by_user_id, get_user_by_id};
+use log::{error, info, warn};
+use serde::Deserialize;
+
+use crate::controllers::auth::{verify_session_cookie, AppState, AUTH_COOKIE};
+
+/// Holds the id of the pending deletion's OTP session
+const DELETE_VERIFICATION_COOKIE: &str = "delete_verification_data";
+
+#[derive(Template)]
+#[template(path = "delete_account.html")]
+pub struct DeleteAccountTemplate {
+    pub error_message: String,
+    /// Last four digits of the phone the code was sent to
+    pub phone_hint: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAccountForm {
+    code: String,
+}
+
+pub async fn export_json_download(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, Redirect> {
+    let export = load_export(&state, &jar).await?;
+    Ok(download(
+        "application/json",
+        "hbd-bot-export.json",
+        export_json(&export),
+    ))
+}
+
+pub async fn export_reminders_csv(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, Redirect> {
+    let export = load_export(&state, &jar).await?;
+    Ok(download(
+        "text/csv; charset=utf-8",
+        "hbd-bot-reminders.csv",
+        reminders_csv(&export),
+    ))
+}
+
+pub async fn export_settings_csv(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, Redirect> {
+    let export = load_export(&state, &jar).await?;
+    Ok(download(
+        "text/csv; charset=utf-8",
+        "hbd-bot-settings.csv",
+        settings_csv(&export),
+    ))
+}
+
+async fn load_export(state: &AppState, jar: &CookieJar) -> Result<UserExport, Redirect> {
+    let user_id = match verify_session_cookie(jar, &state.db).await {
+        Ok(user_id) => user_id,
+        Err(_) => return Err(Redirect::to("/login")),
+    };
+
+    let user = match get_user_by_id(&state.db, user_id).await {
+        Ok(Some(user)) => user,
+        _ => return Err(Redirect::to("/login")),
+    };
+    let reminders = match get_reminders_by_user_id(&state.db, user_id).await {
+        Ok(reminders) => reminders,
+        Err(e) => {
+            error!("Failed to load reminders for export: {}", e);
+            return Err(Redirect::to("/settings"));
+        }
+    };
+
+    Ok(build_export(&user, &reminders, Utc::now()))
+}
+
+fn download(content_type: &'static str, filename: &str, body: String) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+            (header::CACHE_CONTROL, "no-store".to_string()),
+        ],
+        body,
+    )
+}
+
+/// The last four digits, e.g. "4567"
+fn phone_hint(phone_number: &str) -> String {
+    let start = phone_number.len().saturating_sub(4);
+    phone_number[start..].to_string()
+}
+
+/// Texts a code to the account's phone and asks for it before deleting. The
+/// code counts against the same per-phone and daily limits as a login code.
+pub async fn delete_account_start(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<(CookieJar, Html<String>), Redirect> {
+    let user_id = match verify_session_cookie(&jar, &state.db).await {
+        Ok(user_id) => user_id,
+        Err(_) => return Err(Redirect::to("/login")),
+    };
+    let user = match get_user_by_id(&state.db, user_id).await {
+        Ok(Some(user)) => user,
+        _ => return Err(Redirect::to("/login")),
+    };
+    let phone_hint = phone_hint(&user.phone_number);
+
+    let refused = match check_login_limits(
+        &state.db,
+        &state.config.login_limits,
+        &user.phone_number,
+        None,
+        Utc::now(),
+    )
+    .await
+    {
+        Ok(None) => None,
+        Ok(Some(refusal)) => {
+            warn!(
+                "Refused deletion code for user {}: {}",
+                user_id,
+                refusal.code()
+            );
+            Some(refusal.message())
+        }
+        Err(e) => {
+            error!("Failed to check limits for user {}: {}", user_id, e);
+            Some("Failed to send a code. Please go back and try again.".to_string())
+        }
+    };
+    if let Some(error_message) = refused {
+        let template = DeleteAccountTemplate {
+            error_message,
+            phone_hint,
+        };
+        return Ok((jar, Html(template.render().unwrap())));
+    }
+
+    let sent = state
+        .otp
+        .send_code(&user.phone_number)
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|challenge| {
+            let encrypted_token = state
+                .config
+                .sealer
+                .seal(
+                    OtpPurpose::DeleteAccount.as_str(),
+                    &challenge.provider_token,
+                )
+                .map_err(|e| e.to_string())?;
+            Ok((encrypted_token, challenge))
+        });
+    let session_id = match sent {
+        Ok((encrypted_token, challenge)) => {
+            let now = Utc::now();
+            create_otp_session(
+                &state.db,
+                OtpPurpose::DeleteAccount,
+                &user.phone_number,
+                &challenge.verification_id,
+                &encrypted_token,
+                now + chrono::Duration::seconds(challenge.validity_seconds),
+                now,
+            )
+            .await
+            .map_err(|e| e.to_string())
+        }
+        Err(e) => Err(e),
+    };
+
+    let (jar, error_message) = match session_id {
+        Ok(session_id) => {
+            let cookie = Cookie::build((DELETE_VERIFICATION_COOKIE, session_id))
+                .http_only(true)
+                .secure(false) // Set to false for development (non-HTTPS)
+                .path("/")
+                .build();
+            (jar.add(cookie), String::new())
+        }
+        Err(e) => {
+            error!("Failed to send deletion code to user {}: {}", user_id, e);
+            (
+                jar,
+                "Failed to send a code. Please go back and try again.".to_string(),
+            )
+        }
+    };
+
+    let template = DeleteAccountTemplate {
+        error_message,
+        phone_hint,
+    };
+    Ok((jar, Html(template.render().unwrap())))
+}
+
+/// Deletes the account once the texted code checks out, then logs out.
+pub async fn delete_account_confirm(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Form(form): Form<DeleteAccountForm>,
+) -> Result<(CookieJar, Redirect), Html<String>> {
+    let user_id = match verify_session_cookie(&jar, &state.db).await {
+        Ok(user_id) => user_id,
+        Err(_) => return Ok((jar, Redirect::to("/login"))),
+    };
+    let user = match get_user_by_id(&state.db, user_id).await {
+        Ok(Some(user)) => user,
+        _ => return Ok((jar, Redirect::to("/login"))),
+    };
+    let phone_hint = phone_hint(&user.phone_number);
+    let error_page = |message: &str| {
+        let template = DeleteAccountTemplate {
+            error_message: message.to_string(),
+            phone_hint: phone_hint.clone(),
+        };
+        Html(template.render().unwrap())
+    };
+
+    let expired = "Your code has expired. Please go back to Settings and start again.";
+    let Some(session_id) = jar
+        .get(DELETE_VERIFICATION_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+    else {
+        return Err(error_page(expired));
+    };
+    let session = match begin_otp_attempt(
+        &state.db,
+        &session_id,
+        OtpPurpose::DeleteAccount,
+        Utc::now(),
+    )
+    .await
+    {
+        Ok(OtpAttempt::Allowed(session)) => session,
+        Ok(OtpAttempt::Expired) => return Err(error_page(expired)),
+        Ok(OtpAttempt::TooManyAttempts) => {
+            return Err(error_page(
+                "Too many incorrect codes. Please go back to Settings and start again.",
+            ))
+        }
+        Err(e) => {
+            error!("Failed to load deletion OTP session: {}", e);
+            return Err(error_page("Something went wrong. Please try again."));
+        }
+    };
+
+    // The code must have gone to this account's phone
+    if session.phone_number != user.phone_number {
+        warn!(
+            "Deletion OTP session for another phone presented by user {}",
+            user_id
+        );
+        return Err(error_page(expired));
+    }
+    let Ok(auth_token) = state
+        .config
+        .sealer
+        .open(OtpPurpose::DeleteAccount.as_str(), &session.provider_token)
+    else {
+        return Err(error_page(expired));
+    };
+
+    if let Err(e) = state
+        .otp
+        .check_code(&session.verification_id, &auth_token, form.code.trim())
+        .await
+    {
+        warn!("Deletion code rejected for user {}: {}", user_id, e);
+        return Err(error_page("Invalid code. Please try again."));
+    }
+    if let Err(e) = delete_otp_session(&state.db, &session.id).await {
+        error!("Failed to delete OTP session: {}", e);
+    }
+
+    if let Err(e) = delete_user(&state.db, user_id).await {
+        error!("Failed to delete user {}: {}", user_id, e);
+        return Err(error_page(
+            "Failed to delete your account. Please try again.",
+        ));
+    }
+    info!("Deleted account for user {}", user_id);
+
+    let clear_auth_cookie = Cookie::build((AUTH_COOKIE, ""))
+        .http_only(true)
+        .secure(false)
+        .path("/")
+        .max_age(time::Duration::seconds(0))
+        .build();
+    let clear_verification_cookie = Cookie::build((DELETE_VERIFICATION_COOKIE, ""))
+        .http_only(true)
+        .secure(false)
+        .path("/")
+        .max_age(time::Duration::seconds(0))
+        .build();
+    let jar = jar.add(clear_auth_cookie).add(clear_verification_cookie);
+
+    Ok((jar, Redirect::to("/login")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controllers::auth::tests::test_state;
+    use common::create_user;
+    use common::sessions::create_session;
+
+    #[tokio::test]
+    async fn test_deletion_codes_are_throttled() {
+        let (state, sender, _temp_file) = test_state().await;
+        let user = create_user(&state.db, "5550000001").await.unwrap();
+        let (_, secret) = create_session(&state.db, user.id, None, None, Utc::now())
+            .await
+            .unwrap();
+        let jar = CookieJar::new().add(Cookie::new(AUTH_COOKIE, secret));
+
+        let capacity = state.config.login_limits.per_phone.capacity as usize;
+        for _ in 0..capacity {
+            let (_, Html(page)) = delete_account_start(State(state.clone()), jar.clone())
+                .await
+                .unwrap();
+            assert!(!page.contains("error-message"));
+        }
+        assert_eq!(sender.sent().len(), capacity);
+
+        let (_, Html(page)) = delete_account_start(State(state.clone()), jar)
+            .await
+            .unwrap();
+        assert!(page.contains("Too many codes sent to this number"));
+        assert_eq!(sender.sent().len(), capacity);
+    }
+}
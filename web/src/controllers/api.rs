@@ -0,0 +1,354 @@
+use axum::{
+    extract::{
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+        FromRequestParts, Path, Query, State,
+    },
+    http::{request::Parts, Method, StatusCode},
+    response::{IntoResponse, Json, Response},
+    routing::get,
+    Router,
+};
+use axum_extra::{
+    extract::cookie::CookieJar,
+    headers::{authorization::Bearer, Authorization, HeaderMapExt},
+};
+use common::api::{
+    upcoming_events, InvalidField, ReminderInput, SettingsInput, UpcomingEvent, ValidReminder,
+    MAX_UPCOMING_DAYS,
+};
+use common::api_tokens::{authenticate_api_token, TokenScope};
+use common::birthday::{birthdate_to_timestamp, BirthdayEngine};
+use common::clock::SystemClock;
+use common::export::{export_reminder, export_settings, ExportReminder, ExportSettings};
+use common::{
+    delete_reminder, get_reminder_by_id, get_reminders_by_user_id, get_user_by_id, save_reminder,
+    update_user_leap_day_policy, update_user_settings, DbUser, ReminderFields,
+};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::controllers::auth::{verify_session_cookie, AppState};
+
+/// Routes mounted at `/api/v1`. Every route acts on the caller's own data
+/// only, whether they signed in with the browser cookie or a personal access
+/// token.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/reminders",
+            get(list_reminders).post(create_reminder_handler),
+        )
+        .route(
+            "/reminders/{id}",
+            get(get_reminder)
+                .put(replace_reminder)
+                .delete(delete_reminder_handler),
+        )
+        .route("/settings", get(get_settings).patch(update_settings))
+        .route("/upcoming", get(list_upcoming))
+        .fallback(not_found)
+}
+
+/// An error response: `{"error": {"code": "...", "message": "..."}}`, plus
+/// `field` for validation errors.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    body: ErrorDetail,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorBody<'a> {
+    error: &'a ErrorDetail,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorDetail {
+    code: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<&'static str>,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            body: ErrorDetail {
+                code,
+                message: message.into(),
+                field: None,
+            },
+        }
+    }
+
+    pub fn unauthorized() -> Self {
+        Self::new(
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+            "Sign in or send a valid API token",
+        )
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "insufficient_scope", message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", message)
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+
+    pub fn internal() -> Self {
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal",
+            "Something went wrong. Please try again.",
+        )
+    }
+}
+
+impl From<InvalidField> for ApiError {
+    fn from(invalid: InvalidField) -> Self {
+        let mut error = Self::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_field",
+            invalid.message,
+        );
+        error.body.field = Some(invalid.field);
+        error
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(e: sqlx::Error) -> Self {
+        error!("API database error: {}", e);
+        Self::internal()
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::new(rejection.status(), "bad_request", rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::bad_request(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::bad_request(rejection.body_text())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody { error: &self.body };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+/// The user making an API request. A request with an `Authorization: Bearer`
+/// header is authenticated by that token alone, and the token needs the
+/// read scope for GET requests and the write scope for anything else.
+/// Otherwise the browser's login cookie is used.
+pub struct ApiUser(pub i64);
+
+impl FromRequestParts<AppState> for ApiUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, ApiError> {
+        if let Some(auth) = parts.headers.typed_get::<Authorization<Bearer>>() {
+            let Some(token) = authenticate_api_token(&state.db, auth.token()).await? else {
+                warn!("Rejected unknown or revoked API token");
+                return Err(ApiError::unauthorized());
+            };
+
+            let scope = match parts.method {
+                Method::GET | Method::HEAD => TokenScope::Read,
+                _ => TokenScope::Write,
+            };
+            if !token.has_scope(scope) {
+                return Err(ApiError::forbidden(format!(
+                    "This token doesn't have the {} scope",
+                    scope.as_str()
+                )));
+            }
+            return Ok(ApiUser(token.user_id));
+        }
+
+        let jar = CookieJar::from_headers(&parts.headers);
+        verify_session_cookie(&jar, &state.db)
+            .await
+            .map(ApiUser)
+            .map_err(|_| ApiError::unauthorized())
+    }
+}
+
+async fn not_found() -> ApiError {
+    ApiError::not_found("No such endpoint")
+}
+
+async fn load_user(state: &AppState, user_id: i64) -> Result<DbUser, ApiError> {
+    // A valid token for a deleted account
+    get_user_by_id(&state.db, user_id)
+        .await?
+        .ok_or_else(ApiError::unauthorized)
+}
+
+/// The caller's reminder, or 404 for anyone else's
+async fn load_reminder(
+    state: &AppState,
+    user_id: i64,
+    reminder_id: i64,
+) -> Result<common::DbReminder, ApiError> {
+    get_reminder_by_id(&state.db, reminder_id)
+        .await?
+        .filter(|reminder| reminder.user_id == user_id)
+        .ok_or_else(|| ApiError::not_found("No such reminder"))
+}
+
+async fn list_reminders(
+    State(state): State<AppState>,
+    ApiUser(user_id): ApiUser,
+) -> Result<Json<Vec<ExportReminder>>, ApiError> {
+    let reminders = get_reminders_by_user_id(&state.db, user_id).await?;
+    Ok(Json(reminders.iter().map(export_reminder).collect()))
+}
+
+async fn get_reminder(
+    State(state): State<AppState>,
+    ApiUser(user_id): ApiUser,
+    path: Result<Path<i64>, PathRejection>,
+) -> Result<Json<ExportReminder>, ApiError> {
+    let Path(reminder_id) = path?;
+    let reminder = load_reminder(&state, user_id, reminder_id).await?;
+    Ok(Json(export_reminder(&reminder)))
+}
+
+async fn create_reminder_handler(
+    State(state): State<AppState>,
+    ApiUser(user_id): ApiUser,
+    body: Result<Json<ReminderInput>, JsonRejection>,
+) -> Result<(StatusCode, Json<ExportReminder>), ApiError> {
+    let Json(input) = body?;
+    let reminder = input.validate()?;
+
+    let created = save(&state, user_id, None, &reminder)
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)?;
+    Ok((StatusCode::CREATED, Json(export_reminder(&created))))
+}
+
+async fn replace_reminder(
+    State(state): State<AppState>,
+    ApiUser(user_id): ApiUser,
+    path: Result<Path<i64>, PathRejection>,
+    body: Result<Json<ReminderInput>, JsonRejection>,
+) -> Result<Json<ExportReminder>, ApiError> {
+    let Path(reminder_id) = path?;
+    load_reminder(&state, user_id, reminder_id).await?;
+    let Json(input) = body?;
+    let reminder = input.validate()?;
+
+    let updated = save(&state, user_id, Some(reminder_id), &reminder)
+        .await?
+        .ok_or_else(|| ApiError::not_found("No such reminder"))?;
+    Ok(Json(export_reminder(&updated)))
+}
+
+/// Creates the reminder, or overwrites `reminder_id`, in one statement
+async fn save(
+    state: &AppState,
+    user_id: i64,
+    reminder_id: Option<i64>,
+    reminder: &ValidReminder,
+) -> Result<Option<common::DbReminder>, sqlx::Error> {
+    let birthdate = birthdate_to_timestamp(reminder.birthdate).to_string();
+    let fields = ReminderFields {
+        name: &reminder.name,
+        birthdate: &birthdate,
+        year_known: reminder.year_known,
+        notice_offsets: reminder.notice_offsets.as_deref(),
+        event_type: &reminder.event_type,
+        recurrence: reminder.recurrence.as_ref(),
+        calendar_date: reminder.calendar_date.as_ref(),
+    };
+    save_reminder(&state.db, user_id, reminder_id, &fields).await
+}
+
+async fn delete_reminder_handler(
+    State(state): State<AppState>,
+    ApiUser(user_id): ApiUser,
+    path: Result<Path<i64>, PathRejection>,
+) -> Result<StatusCode, ApiError> {
+    let Path(reminder_id) = path?;
+    load_reminder(&state, user_id, reminder_id).await?;
+    delete_reminder(&state.db, reminder_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_settings(
+    State(state): State<AppState>,
+    ApiUser(user_id): ApiUser,
+) -> Result<Json<ExportSettings>, ApiError> {
+    let user = load_user(&state, user_id).await?;
+    Ok(Json(export_settings(&user)))
+}
+
+async fn update_settings(
+    State(state): State<AppState>,
+    ApiUser(user_id): ApiUser,
+    body: Result<Json<SettingsInput>, JsonRejection>,
+) -> Result<Json<ExportSettings>, ApiError> {
+    let user = load_user(&state, user_id).await?;
+    let Json(input) = body?;
+    let settings = input.apply(&user)?;
+
+    update_user_settings(
+        &state.db,
+        user_id,
+        &settings.notice_offsets,
+        settings.send_hour,
+        &settings.iana_tz,
+    )
+    .await?;
+    update_user_leap_day_policy(&state.db, user_id, settings.leap_day_policy).await?;
+
+    let user = load_user(&state, user_id).await?;
+    Ok(Json(export_settings(&user)))
+}
+
+#[derive(Deserialize)]
+struct UpcomingQuery {
+    /// How many days ahead to look; defaults to 30
+    days: Option<i64>,
+}
+
+async fn list_upcoming(
+    State(state): State<AppState>,
+    ApiUser(user_id): ApiUser,
+    query: Result<Query<UpcomingQuery>, QueryRejection>,
+) -> Result<Json<Vec<UpcomingEvent>>, ApiError> {
+    let Query(query) = query?;
+    let days = query.days.unwrap_or(30);
+    if !(0..=MAX_UPCOMING_DAYS).contains(&days) {
+        return Err(InvalidField {
+            field: "days",
+            message: format!("days must be between 0 and {}", MAX_UPCOMING_DAYS),
+        }
+        .into());
+    }
+
+    let user = load_user(&state, user_id).await?;
+    let reminders = get_reminders_by_user_id(&state.db, user_id).await?;
+    let engine = BirthdayEngine::for_user(&user, &SystemClock);
+    Ok(Json(upcoming_events(&engine, reminders, days)))
+}
@@ -5,16 +5,22 @@ use axum::{
     Form,
 };
 use axum_extra::extract::cookie::CookieJar;
-use chrono::{DateTime, NaiveDate};
+use chrono::{DateTime, Datelike, NaiveDate};
+use common::birthday::{birthdate_to_timestamp, BirthdayEngine};
+use common::calendars::{parse_calendar_form, CalendarSystem};
+use common::clock::SystemClock;
+use common::events::{parse_event_form, EventType};
+use common::notice::{display_notice_offsets, parse_notice_override};
+use common::recurrence::RecurrencePreset;
 use common::{
-    create_reminder, delete_reminder, get_reminder_by_id, get_reminders_by_user_id, get_user_by_id,
-    update_reminder,
+    delete_reminder, get_reminder_by_id, get_reminders_by_user_id, get_user_by_id, save_reminder,
+    ReminderFields, UNKNOWN_BIRTH_YEAR,
 };
 use serde::Deserialize;
 use std::collections::HashMap;
 
 use crate::controllers::auth::{
-    convert_reminders_to_display, verify_jwt_cookie, AppState, AppTemplate,
+    convert_reminders_to_display, verify_session_cookie, AppState, AppTemplate,
 };
 
 #[derive(Template)]
@@ -23,6 +29,19 @@ pub struct AddTemplate {
     pub error_message: String,
     pub name: String,
     pub birthdate: String,
+    /// Blank means the user's default offsets
+    pub notice_offsets: String,
+    pub year_unknown: bool,
+    /// `EventType::as_str`, e.g. "birthday"
+    pub event_type: String,
+    /// Name of a custom occasion
+    pub event_label: String,
+    /// `RecurrencePreset::as_str`, e.g. "yearly"
+    pub recurrence: String,
+    /// The rule typed in for the custom preset
+    pub rrule: String,
+    /// `CalendarSystem::as_str`, e.g. "gregorian"
+    pub calendar: String,
 }
 
 #[derive(Template)]
@@ -32,6 +51,19 @@ pub struct EditTemplate {
     pub error_message: String,
     pub name: String,
     pub birthdate: String,
+    /// Blank means the user's default offsets
+    pub notice_offsets: String,
+    pub year_unknown: bool,
+    /// `EventType::as_str`, e.g. "birthday"
+    pub event_type: String,
+    /// Name of a custom occasion
+    pub event_label: String,
+    /// `RecurrencePreset::as_str`, e.g. "yearly"
+    pub recurrence: String,
+    /// The rule typed in for the custom preset
+    pub rrule: String,
+    /// `CalendarSystem::as_str`, e.g. "gregorian"
+    pub calendar: String,
 }
 
 #[derive(Template)]
@@ -42,6 +74,17 @@ pub struct LandingTemplate;
 pub struct AddBirthdayForm {
     name: String,
     birthdate: String,
+    #[serde(default)]
+    notice_offsets: String,
+    /// Checkbox: present when the birth year isn't known
+    year_unknown: Option<String>,
+    event_type: String,
+    #[serde(default)]
+    event_label: String,
+    recurrence: String,
+    #[serde(default)]
+    rrule: String,
+    calendar: String,
 }
 
 #[derive(Deserialize)]
@@ -49,11 +92,22 @@ pub struct EditBirthdayForm {
     reminder_id: i64,
     name: String,
     birthdate: String,
+    #[serde(default)]
+    notice_offsets: String,
+    /// Checkbox: present when the birth year isn't known
+    year_unknown: Option<String>,
+    event_type: String,
+    #[serde(default)]
+    event_label: String,
+    recurrence: String,
+    #[serde(default)]
+    rrule: String,
+    calendar: String,
 }
 
 pub async fn root(State(state): State<AppState>, jar: CookieJar) -> Html<String> {
-    // Verify JWT cookie and get user ID
-    let user_id = match verify_jwt_cookie(&jar, &state.config.jwt_secret) {
+    // Verify session cookie and get user ID
+    let user_id = match verify_session_cookie(&jar, &state.db).await {
         Ok(user_id) => user_id,
         Err(_) => {
             // Show landing page if authentication fails
@@ -63,7 +117,7 @@ pub async fn root(State(state): State<AppState>, jar: CookieJar) -> Html<String>
     };
 
     // Get user from database
-    let _user = match get_user_by_id(&state.db, user_id).await {
+    let user = match get_user_by_id(&state.db, user_id).await {
         Ok(Some(user)) => user,
         Ok(None) => {
             // User not found in database, show landing page
@@ -79,7 +133,9 @@ pub async fn root(State(state): State<AppState>, jar: CookieJar) -> Html<String>
 
     // Get reminders for this user
     let reminders = match get_reminders_by_user_id(&state.db, user_id).await {
-        Ok(reminders) => convert_reminders_to_display(reminders),
+        Ok(reminders) => {
+            convert_reminders_to_display(reminders, &BirthdayEngine::for_user(&user, &SystemClock))
+        }
         Err(_) => {
             // If we can't get reminders, just show empty list
             Vec::new()
@@ -96,14 +152,21 @@ pub async fn add_form(
     State(state): State<AppState>,
     jar: CookieJar,
 ) -> Result<Html<String>, Redirect> {
-    // Verify JWT cookie and get user ID
-    match verify_jwt_cookie(&jar, &state.config.jwt_secret) {
+    // Verify session cookie and get user ID
+    match verify_session_cookie(&jar, &state.db).await {
         Ok(_) => {
             // User is authenticated, show the form
             let template = AddTemplate {
                 error_message: String::new(),
                 name: String::new(),
                 birthdate: String::new(),
+                notice_offsets: String::new(),
+                year_unknown: false,
+                event_type: EventType::Birthday.as_str().to_string(),
+                event_label: String::new(),
+                recurrence: RecurrencePreset::Yearly.as_str().to_string(),
+                rrule: String::new(),
+                calendar: CalendarSystem::Gregorian.as_str().to_string(),
             };
             Ok(Html(template.render().unwrap()))
         }
@@ -119,8 +182,8 @@ pub async fn add_birthday(
     jar: CookieJar,
     Form(form): Form<AddBirthdayForm>,
 ) -> Result<Redirect, Html<String>> {
-    // Verify JWT cookie and get user ID
-    let user_id = match verify_jwt_cookie(&jar, &state.config.jwt_secret) {
+    // Verify session cookie and get user ID
+    let user_id = match verify_session_cookie(&jar, &state.db).await {
         Ok(user_id) => user_id,
         Err(_) => {
             // Redirect to login if authentication fails
@@ -134,15 +197,29 @@ pub async fn add_birthday(
             error_message: "Please enter a name".to_string(),
             name: form.name,
             birthdate: form.birthdate,
+            notice_offsets: form.notice_offsets,
+            year_unknown: form.year_unknown.is_some(),
+            event_type: form.event_type,
+            event_label: form.event_label,
+            recurrence: form.recurrence,
+            rrule: form.rrule,
+            calendar: form.calendar,
         };
         return Err(Html(template.render().unwrap()));
     }
 
     if form.birthdate.trim().is_empty() {
         let template = AddTemplate {
-            error_message: "Please select a birthday".to_string(),
+            error_message: "Please select a date".to_string(),
             name: form.name,
             birthdate: form.birthdate,
+            notice_offsets: form.notice_offsets,
+            year_unknown: form.year_unknown.is_some(),
+            event_type: form.event_type,
+            event_label: form.event_label,
+            recurrence: form.recurrence,
+            rrule: form.rrule,
+            calendar: form.calendar,
         };
         return Err(Html(template.render().unwrap()));
     }
@@ -155,33 +232,146 @@ pub async fn add_birthday(
                 error_message: "Invalid date format".to_string(),
                 name: form.name,
                 birthdate: form.birthdate,
+                notice_offsets: form.notice_offsets,
+                year_unknown: form.year_unknown.is_some(),
+                event_type: form.event_type,
+                event_label: form.event_label,
+                recurrence: form.recurrence,
+                rrule: form.rrule,
+                calendar: form.calendar,
+            };
+            return Err(Html(template.render().unwrap()));
+        }
+    };
+
+    let notice_offsets = match parse_notice_override(&form.notice_offsets) {
+        Ok(offsets) => offsets,
+        Err(e) => {
+            let template = AddTemplate {
+                error_message: e,
+                name: form.name,
+                birthdate: form.birthdate,
+                notice_offsets: form.notice_offsets,
+                year_unknown: form.year_unknown.is_some(),
+                event_type: form.event_type,
+                event_label: form.event_label,
+                recurrence: form.recurrence,
+                rrule: form.rrule,
+                calendar: form.calendar,
+            };
+            return Err(Html(template.render().unwrap()));
+        }
+    };
+
+    let event_type = match parse_event_form(&form.event_type, &form.event_label) {
+        Ok(event_type) => event_type,
+        Err(e) => {
+            let template = AddTemplate {
+                error_message: e,
+                name: form.name,
+                birthdate: form.birthdate,
+                notice_offsets: form.notice_offsets,
+                year_unknown: form.year_unknown.is_some(),
+                event_type: form.event_type,
+                event_label: form.event_label,
+                recurrence: form.recurrence,
+                rrule: form.rrule,
+                calendar: form.calendar,
             };
             return Err(Html(template.render().unwrap()));
         }
     };
 
+    // Presets are worked out from the date as entered, before any
+    // placeholder year is swapped in
+    let recurrence = match RecurrencePreset::parse(&form.recurrence)
+        .ok_or_else(|| "Invalid repeat option selected".to_string())
+        .and_then(|preset| preset.to_recurrence(parsed_date, &form.rrule))
+    {
+        Ok(recurrence) => recurrence,
+        Err(e) => {
+            let template = AddTemplate {
+                error_message: e,
+                name: form.name,
+                birthdate: form.birthdate,
+                notice_offsets: form.notice_offsets,
+                year_unknown: form.year_unknown.is_some(),
+                event_type: form.event_type,
+                event_label: form.event_label,
+                recurrence: form.recurrence,
+                rrule: form.rrule,
+                calendar: form.calendar,
+            };
+            return Err(Html(template.render().unwrap()));
+        }
+    };
+
+    let calendar_date = match parse_calendar_form(
+        &form.calendar,
+        parsed_date,
+        form.year_unknown.is_none(),
+        recurrence.is_some(),
+    ) {
+        Ok(calendar_date) => calendar_date,
+        Err(e) => {
+            let template = AddTemplate {
+                error_message: e,
+                name: form.name,
+                birthdate: form.birthdate,
+                notice_offsets: form.notice_offsets,
+                year_unknown: form.year_unknown.is_some(),
+                event_type: form.event_type,
+                event_label: form.event_label,
+                recurrence: form.recurrence,
+                rrule: form.rrule,
+                calendar: form.calendar,
+            };
+            return Err(Html(template.render().unwrap()));
+        }
+    };
+
+    // Birthdays without a known year are stored in a placeholder year
+    let year_known = form.year_unknown.is_none();
+    let parsed_date = if year_known {
+        parsed_date
+    } else {
+        parsed_date.with_year(UNKNOWN_BIRTH_YEAR).unwrap()
+    };
+
     // Convert to timestamp for database storage
-    let timestamp = parsed_date
-        .and_hms_opt(0, 0, 0)
-        .unwrap()
-        .and_utc()
-        .timestamp_millis();
+    let timestamp = birthdate_to_timestamp(parsed_date);
 
-    // Convert timestamp back to string for the create_reminder function
+    // Convert timestamp back to string for storage
     let timestamp_str = timestamp.to_string();
 
     // Create the reminder
-    match create_reminder(&state.db, user_id, &form.name.trim(), &timestamp_str).await {
-        Ok(_) => {
+    let fields = ReminderFields {
+        name: form.name.trim(),
+        birthdate: &timestamp_str,
+        year_known,
+        notice_offsets: notice_offsets.as_deref(),
+        event_type: &event_type,
+        recurrence: recurrence.as_ref(),
+        calendar_date: calendar_date.as_ref(),
+    };
+    match save_reminder(&state.db, user_id, None, &fields).await {
+        Ok(Some(_)) => {
             // Success - redirect to dashboard
             Ok(Redirect::to("/"))
         }
-        Err(_) => {
+        Ok(None) | Err(_) => {
             // Database error
             let template = AddTemplate {
                 error_message: "Failed to save birthday. Please try again.".to_string(),
                 name: form.name,
                 birthdate: form.birthdate,
+                notice_offsets: form.notice_offsets,
+                year_unknown: form.year_unknown.is_some(),
+                event_type: form.event_type,
+                event_label: form.event_label,
+                recurrence: form.recurrence,
+                rrule: form.rrule,
+                calendar: form.calendar,
             };
             Err(Html(template.render().unwrap()))
         }
@@ -193,8 +383,8 @@ pub async fn edit_form(
     jar: CookieJar,
     Query(params): Query<HashMap<String, String>>,
 ) -> Result<Html<String>, Redirect> {
-    // Verify JWT cookie and get user ID
-    let user_id = match verify_jwt_cookie(&jar, &state.config.jwt_secret) {
+    // Verify session cookie and get user ID
+    let user_id = match verify_session_cookie(&jar, &state.db).await {
         Ok(user_id) => user_id,
         Err(_) => {
             // Redirect to login if authentication fails
@@ -231,10 +421,14 @@ pub async fn edit_form(
     }
 
     // Convert timestamp to date string for form
-    let formatted_date = match DateTime::from_timestamp_millis(reminder.birthdate) {
-        Some(dt) => dt.naive_utc().date().format("%Y-%m-%d").to_string(),
-        None => String::new(),
+    let birthdate = match DateTime::from_timestamp_millis(reminder.birthdate) {
+        Some(dt) => dt.naive_utc().date(),
+        None => {
+            // Unreadable date, redirect to dashboard
+            return Err(Redirect::to("/"));
+        }
     };
+    let formatted_date = birthdate.format("%Y-%m-%d").to_string();
 
     // Render edit form with reminder data
     let template = EditTemplate {
@@ -242,6 +436,28 @@ pub async fn edit_form(
         error_message: String::new(),
         name: reminder.name,
         birthdate: formatted_date,
+        notice_offsets: reminder
+            .notice_offsets
+            .as_deref()
+            .map(display_notice_offsets)
+            .unwrap_or_default(),
+        year_unknown: !reminder.year_known,
+        event_type: reminder.event_type.as_str().to_string(),
+        event_label: reminder.event_type.label().unwrap_or_default().to_string(),
+        recurrence: RecurrencePreset::for_recurrence(reminder.recurrence.as_ref(), birthdate)
+            .as_str()
+            .to_string(),
+        rrule: reminder
+            .recurrence
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default(),
+        calendar: reminder
+            .calendar_date
+            .as_ref()
+            .map_or(CalendarSystem::Gregorian, |date| date.system)
+            .as_str()
+            .to_string(),
     };
 
     Ok(Html(template.render().unwrap()))
@@ -252,8 +468,8 @@ pub async fn update_birthday(
     jar: CookieJar,
     Form(form): Form<EditBirthdayForm>,
 ) -> Result<Redirect, Html<String>> {
-    // Verify JWT cookie and get user ID
-    let user_id = match verify_jwt_cookie(&jar, &state.config.jwt_secret) {
+    // Verify session cookie and get user ID
+    let user_id = match verify_session_cookie(&jar, &state.db).await {
         Ok(user_id) => user_id,
         Err(_) => {
             // Redirect to login if authentication fails
@@ -287,6 +503,13 @@ pub async fn update_birthday(
             error_message: "Please enter a name".to_string(),
             name: form.name,
             birthdate: form.birthdate,
+            notice_offsets: form.notice_offsets,
+            year_unknown: form.year_unknown.is_some(),
+            event_type: form.event_type,
+            event_label: form.event_label,
+            recurrence: form.recurrence,
+            rrule: form.rrule,
+            calendar: form.calendar,
         };
         return Err(Html(template.render().unwrap()));
     }
@@ -294,9 +517,16 @@ pub async fn update_birthday(
     if form.birthdate.trim().is_empty() {
         let template = EditTemplate {
             reminder_id: form.reminder_id,
-            error_message: "Please select a birthday".to_string(),
+            error_message: "Please select a date".to_string(),
             name: form.name,
             birthdate: form.birthdate,
+            notice_offsets: form.notice_offsets,
+            year_unknown: form.year_unknown.is_some(),
+            event_type: form.event_type,
+            event_label: form.event_label,
+            recurrence: form.recurrence,
+            rrule: form.rrule,
+            calendar: form.calendar,
         };
         return Err(Html(template.render().unwrap()));
     }
@@ -310,41 +540,151 @@ pub async fn update_birthday(
                 error_message: "Invalid date format".to_string(),
                 name: form.name,
                 birthdate: form.birthdate,
+                notice_offsets: form.notice_offsets,
+                year_unknown: form.year_unknown.is_some(),
+                event_type: form.event_type,
+                event_label: form.event_label,
+                recurrence: form.recurrence,
+                rrule: form.rrule,
+                calendar: form.calendar,
             };
             return Err(Html(template.render().unwrap()));
         }
     };
 
+    let notice_offsets = match parse_notice_override(&form.notice_offsets) {
+        Ok(offsets) => offsets,
+        Err(e) => {
+            let template = EditTemplate {
+                reminder_id: form.reminder_id,
+                error_message: e,
+                name: form.name,
+                birthdate: form.birthdate,
+                notice_offsets: form.notice_offsets,
+                year_unknown: form.year_unknown.is_some(),
+                event_type: form.event_type,
+                event_label: form.event_label,
+                recurrence: form.recurrence,
+                rrule: form.rrule,
+                calendar: form.calendar,
+            };
+            return Err(Html(template.render().unwrap()));
+        }
+    };
+
+    let event_type = match parse_event_form(&form.event_type, &form.event_label) {
+        Ok(event_type) => event_type,
+        Err(e) => {
+            let template = EditTemplate {
+                reminder_id: form.reminder_id,
+                error_message: e,
+                name: form.name,
+                birthdate: form.birthdate,
+                notice_offsets: form.notice_offsets,
+                year_unknown: form.year_unknown.is_some(),
+                event_type: form.event_type,
+                event_label: form.event_label,
+                recurrence: form.recurrence,
+                rrule: form.rrule,
+                calendar: form.calendar,
+            };
+            return Err(Html(template.render().unwrap()));
+        }
+    };
+
+    // Presets are worked out from the date as entered, before any
+    // placeholder year is swapped in
+    let recurrence = match RecurrencePreset::parse(&form.recurrence)
+        .ok_or_else(|| "Invalid repeat option selected".to_string())
+        .and_then(|preset| preset.to_recurrence(parsed_date, &form.rrule))
+    {
+        Ok(recurrence) => recurrence,
+        Err(e) => {
+            let template = EditTemplate {
+                error_message: e,
+                reminder_id: form.reminder_id,
+                name: form.name,
+                birthdate: form.birthdate,
+                notice_offsets: form.notice_offsets,
+                year_unknown: form.year_unknown.is_some(),
+                event_type: form.event_type,
+                event_label: form.event_label,
+                recurrence: form.recurrence,
+                rrule: form.rrule,
+                calendar: form.calendar,
+            };
+            return Err(Html(template.render().unwrap()));
+        }
+    };
+
+    let calendar_date = match parse_calendar_form(
+        &form.calendar,
+        parsed_date,
+        form.year_unknown.is_none(),
+        recurrence.is_some(),
+    ) {
+        Ok(calendar_date) => calendar_date,
+        Err(e) => {
+            let template = EditTemplate {
+                error_message: e,
+                reminder_id: form.reminder_id,
+                name: form.name,
+                birthdate: form.birthdate,
+                notice_offsets: form.notice_offsets,
+                year_unknown: form.year_unknown.is_some(),
+                event_type: form.event_type,
+                event_label: form.event_label,
+                recurrence: form.recurrence,
+                rrule: form.rrule,
+                calendar: form.calendar,
+            };
+            return Err(Html(template.render().unwrap()));
+        }
+    };
+
+    // Birthdays without a known year are stored in a placeholder year
+    let year_known = form.year_unknown.is_none();
+    let parsed_date = if year_known {
+        parsed_date
+    } else {
+        parsed_date.with_year(UNKNOWN_BIRTH_YEAR).unwrap()
+    };
+
     // Convert to timestamp for database storage
-    let timestamp = parsed_date
-        .and_hms_opt(0, 0, 0)
-        .unwrap()
-        .and_utc()
-        .timestamp_millis();
+    let timestamp = birthdate_to_timestamp(parsed_date);
 
-    // Convert timestamp back to string for the update_reminder function
+    // Convert timestamp back to string for storage
     let timestamp_str = timestamp.to_string();
 
     // Update the reminder
-    match update_reminder(
-        &state.db,
-        form.reminder_id,
-        &form.name.trim(),
-        &timestamp_str,
-    )
-    .await
-    {
-        Ok(_) => {
+    let fields = ReminderFields {
+        name: form.name.trim(),
+        birthdate: &timestamp_str,
+        year_known,
+        notice_offsets: notice_offsets.as_deref(),
+        event_type: &event_type,
+        recurrence: recurrence.as_ref(),
+        calendar_date: calendar_date.as_ref(),
+    };
+    match save_reminder(&state.db, user_id, Some(form.reminder_id), &fields).await {
+        Ok(Some(_)) => {
             // Success - redirect to dashboard
             Ok(Redirect::to("/"))
         }
-        Err(_) => {
+        Ok(None) | Err(_) => {
             // Database error
             let template = EditTemplate {
                 reminder_id: form.reminder_id,
                 error_message: "Failed to update birthday. Please try again.".to_string(),
                 name: form.name,
                 birthdate: form.birthdate,
+                notice_offsets: form.notice_offsets,
+                year_unknown: form.year_unknown.is_some(),
+                event_type: form.event_type,
+                event_label: form.event_label,
+                recurrence: form.recurrence,
+                rrule: form.rrule,
+                calendar: form.calendar,
             };
             Err(Html(template.render().unwrap()))
         }
@@ -356,8 +696,8 @@ pub async fn delete_reminder_handler(
     jar: CookieJar,
     Query(params): Query<HashMap<String, String>>,
 ) -> Result<Redirect, Redirect> {
-    // Verify JWT cookie and get user ID
-    let user_id = match verify_jwt_cookie(&jar, &state.config.jwt_secret) {
+    // Verify session cookie and get user ID
+    let user_id = match verify_session_cookie(&jar, &state.db).await {
         Ok(user_id) => user_id,
         Err(_) => {
             // Redirect to login if authentication fails
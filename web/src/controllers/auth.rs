@@ -1,26 +1,45 @@
-use aes_gcm::{AeadInPlace, Aes256Gcm, Key, KeyInit, Nonce};
 use askama::Template;
 use axum::{
-    extract::State,
-    http::{HeaderMap, StatusCode},
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, StatusCode},
     response::{Html, Json, Redirect},
 };
 use axum_extra::extract::cookie::{Cookie, CookieJar};
-use base64::{engine::general_purpose, Engine as _};
-use chrono::{DateTime, Datelike, Utc};
+use chrono::Utc;
+use common::birthday::BirthdayEngine;
+use common::calendars::CalendarDate;
+use common::events::EventType;
+use common::otp_sessions::{
+    begin_otp_attempt, create_otp_session, delete_otp_session, OtpAttempt, OtpPurpose,
+};
+use common::rate_limit::{
+    check_login_limits, normalize_login_phone, LoginRefusal, LoginRefusalKind,
+};
+use common::sessions::{authenticate_session, create_session, delete_session_by_secret, DbSession};
 use common::DbReminder;
-use common::{create_user, get_user_by_phone, message_central::MessageCentralClient};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use common::{create_user, get_user_by_phone, otp::OtpProvider, sms::SmsSender};
 use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::net::SocketAddr;
+use std::sync::Arc;
 use time;
 
+/// Holds the secret of the signed-in session
+pub const AUTH_COOKIE: &str = "auth_token";
+
+/// How long a browser keeps the auth cookie, however active the session
+const AUTH_COOKIE_MAX_AGE_DAYS: i64 = 90;
+
+/// Holds the id of the pending login's OTP session
+const OTP_SESSION_COOKIE: &str = "otp_session";
+
 #[derive(Clone)]
 pub struct AppState {
     pub db: SqlitePool,
     pub config: crate::Config,
+    pub sms: Arc<dyn SmsSender>,
+    pub otp: Arc<dyn OtpProvider>,
 }
 
 #[derive(Template)]
@@ -37,9 +56,16 @@ pub struct AppTemplate {
 pub struct ReminderDisplay {
     pub id: i64,
     pub name: String,
+    /// e.g. "Anniversary"; empty for birthdays
+    pub occasion: String,
     pub birthdate: String,
-    pub days_until_birthday: i32,
-    pub age_turning: i32,
+    /// e.g. "Fri", the weekday of the next birthday
+    pub weekday: String,
+    /// The lunar or Hebrew date, e.g. "15 Nisan"; empty for Gregorian
+    pub calendar_note: String,
+    pub days_until_birthday: i64,
+    /// `None` when the birth year isn't known
+    pub age_turning: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -54,9 +80,20 @@ pub struct LoginResponse {
     validity_seconds: u32,
 }
 
+/// Body of a refused `/login`, alongside a 4xx status
+#[derive(Serialize, Deserialize)]
+pub struct LoginRefusedResponse {
+    success: bool,
+    message: String,
+    /// e.g. "phone_throttled"
+    error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after_seconds: Option<i64>,
+}
+
+/// Only the code: the phone comes from the server-side OTP session
 #[derive(Serialize, Deserialize)]
 pub struct VerifyOtpRequest {
-    phone: String,
     code: String,
 }
 
@@ -66,201 +103,127 @@ pub struct VerifyOtpResponse {
     message: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    user_id: i64,
-    exp: usize,
-}
-
-fn encrypt_token(token: &str, secret: &str) -> Result<String, Box<dyn std::error::Error>> {
-    use sha2::{Digest, Sha256};
-
-    // Create a 32-byte key from the JWT secret
-    let mut hasher = Sha256::new();
-    hasher.update(secret.as_bytes());
-    let key_bytes = hasher.finalize();
-    let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
-
-    let cipher = Aes256Gcm::new(key);
-
-    // Generate a random nonce
-    let nonce_bytes = [0u8; 12]; // AES-GCM standard nonce size
-    let nonce = Nonce::from_slice(&nonce_bytes);
-
-    let mut buffer = token.as_bytes().to_vec();
-    cipher
-        .encrypt_in_place(nonce, b"", &mut buffer)
-        .map_err(|e| format!("Encryption failed: {}", e))?;
-
-    // Combine nonce + ciphertext and base64 encode
-    let mut result = nonce_bytes.to_vec();
-    result.extend(buffer);
-
-    Ok(general_purpose::STANDARD.encode(result))
-}
-
-fn decrypt_token(
-    encrypted_token: &str,
-    secret: &str,
-) -> Result<String, Box<dyn std::error::Error>> {
-    use sha2::{Digest, Sha256};
-
-    // Create a 32-byte key from the JWT secret
-    let mut hasher = Sha256::new();
-    hasher.update(secret.as_bytes());
-    let key_bytes = hasher.finalize();
-    let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
-
-    let cipher = Aes256Gcm::new(key);
-
-    // Base64 decode
-    let combined = general_purpose::STANDARD.decode(encrypted_token)?;
-
-    if combined.len() < 12 {
-        return Err("Invalid encrypted token".into());
-    }
-
-    // Split nonce and ciphertext
-    let (nonce_bytes, ciphertext) = combined.split_at(12);
-    let nonce = Nonce::from_slice(nonce_bytes);
-    let mut buffer = ciphertext.to_vec();
-
-    cipher
-        .decrypt_in_place(nonce, b"", &mut buffer)
-        .map_err(|e| format!("Decryption failed: {}", e))?;
-
-    Ok(String::from_utf8(buffer)?)
-}
-
-pub fn calculate_birthday_info(birthdate_timestamp: i64) -> Result<(i32, i32), String> {
-    // Convert Unix timestamp (milliseconds) to UTC DateTime
-    let birthdate_utc =
-        DateTime::from_timestamp_millis(birthdate_timestamp).ok_or("Invalid timestamp")?;
-    let birthdate = birthdate_utc.naive_utc().date();
-
-    let today = Utc::now().naive_utc().date();
-    let current_year = today.year();
-
-    // Calculate this year's birthday
-    let this_year_birthday = birthdate.with_year(current_year);
-
-    let (next_birthday, age_turning) = match this_year_birthday {
-        Some(this_year_bd) => {
-            if this_year_bd >= today {
-                // Birthday hasn't happened this year yet
-                (this_year_bd, current_year - birthdate.year())
-            } else {
-                // Birthday already happened this year, calculate for next year
-                let next_year_birthday = birthdate
-                    .with_year(current_year + 1)
-                    .ok_or("Failed to calculate next year's birthday")?;
-                (next_year_birthday, current_year + 1 - birthdate.year())
-            }
-        }
-        None => {
-            // Handle leap year edge case (Feb 29 -> Feb 28)
-            let next_year_birthday = birthdate
-                .with_year(current_year + 1)
-                .ok_or("Failed to calculate next year's birthday")?;
-            (next_year_birthday, current_year + 1 - birthdate.year())
-        }
-    };
-
-    let days_until = (next_birthday - today).num_days() as i32;
-
-    Ok((days_until, age_turning))
-}
+/// Builds dashboard rows, soonest birthday first, using the user's local date.
+pub fn convert_reminders_to_display(
+    mut reminders: Vec<DbReminder>,
+    engine: &BirthdayEngine,
+) -> Vec<ReminderDisplay> {
+    engine.sort_upcoming(&mut reminders);
 
-pub fn convert_reminders_to_display(reminders: Vec<DbReminder>) -> Vec<ReminderDisplay> {
     reminders
         .into_iter()
-        .filter_map(|reminder| {
-            match calculate_birthday_info(reminder.birthdate) {
-                Ok((days_until, age_turning)) => {
-                    // Convert timestamp to formatted date string for display
-                    let formatted_date = match DateTime::from_timestamp_millis(reminder.birthdate) {
-                        Some(dt) => dt.naive_utc().date().format("%-m/%-d").to_string(),
-                        None => "Unknown".to_string(),
-                    };
-
-                    Some(ReminderDisplay {
-                        id: reminder.id,
-                        name: reminder.name,
-                        birthdate: formatted_date,
-                        days_until_birthday: days_until,
-                        age_turning,
-                    })
-                }
-                Err(e) => {
-                    warn!(
-                        "Failed to calculate birthday info for reminder {}: {}",
-                        reminder.id, e
-                    );
-                    None
-                }
+        .filter_map(|reminder| match engine.info(&reminder) {
+            Some(info) => Some(ReminderDisplay {
+                id: reminder.id,
+                name: reminder.name,
+                occasion: match reminder.event_type {
+                    EventType::Birthday => String::new(),
+                    ref event_type => event_type.display_name().to_string(),
+                },
+                birthdate: info.next_date.format("%-m/%-d").to_string(),
+                weekday: info.weekday.to_string(),
+                calendar_note: reminder
+                    .calendar_date
+                    .as_ref()
+                    .map(CalendarDate::describe)
+                    .unwrap_or_default(),
+                days_until_birthday: info.days_until,
+                age_turning: info.age_turning,
+            }),
+            None => {
+                warn!(
+                    "No upcoming date for reminder {}: invalid birthdate or finished rule",
+                    reminder.id
+                );
+                None
             }
         })
         .collect()
 }
 
-pub fn verify_jwt_cookie(jar: &CookieJar, jwt_secret: &str) -> Result<i64, String> {
+/// The signed-in session the auth cookie holds. Using it slides the
+/// session's expiry forward.
+pub async fn current_session(jar: &CookieJar, db: &SqlitePool) -> Result<DbSession, String> {
     let auth_cookie = jar
-        .get("auth_token")
+        .get(AUTH_COOKIE)
         .ok_or("No auth cookie found".to_string())?;
 
-    let token = auth_cookie.value();
-
-    let decoding_key = DecodingKey::from_secret(jwt_secret.as_ref());
-    let validation = Validation::default();
-
-    match decode::<Claims>(token, &decoding_key, &validation) {
-        Ok(token_data) => {
-            let claims = token_data.claims;
+    match authenticate_session(db, auth_cookie.value(), Utc::now()).await {
+        Ok(Some(session)) => Ok(session),
+        Ok(None) => Err("Session expired or revoked".to_string()),
+        Err(e) => Err(format!("Failed to load session: {}", e)),
+    }
+}
 
-            // Check if token is expired
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as usize;
+pub async fn verify_session_cookie(jar: &CookieJar, db: &SqlitePool) -> Result<i64, String> {
+    current_session(jar, db)
+        .await
+        .map(|session| session.user_id)
+}
 
-            if claims.exp < now {
-                return Err("Token expired".to_string());
-            }
+/// The client's IP: from the configured proxy header when set, otherwise
+/// the peer address of the connection.
+fn client_ip(headers: &HeaderMap, peer: SocketAddr, header: Option<&str>) -> String {
+    header
+        .and_then(|name| headers.get(name))
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(',').next().unwrap_or("").trim().to_string())
+        .filter(|ip| !ip.is_empty())
+        .unwrap_or_else(|| peer.ip().to_string())
+}
 
-            Ok(claims.user_id)
-        }
-        Err(e) => Err(format!("Invalid JWT: {}", e)),
+fn login_refused(refusal: LoginRefusal) -> (StatusCode, HeaderMap, Json<LoginRefusedResponse>) {
+    let status = match refusal.kind {
+        LoginRefusalKind::InvalidPhone => StatusCode::BAD_REQUEST,
+        LoginRefusalKind::CountryNotAllowed => StatusCode::FORBIDDEN,
+        _ => StatusCode::TOO_MANY_REQUESTS,
+    };
+    let mut headers = HeaderMap::new();
+    if let Some(seconds) = refusal.retry_after_seconds {
+        headers.insert(header::RETRY_AFTER, seconds.into());
     }
+
+    let response = LoginRefusedResponse {
+        success: false,
+        message: refusal.message(),
+        error: refusal.code().to_string(),
+        retry_after_seconds: refusal.retry_after_seconds,
+    };
+    (status, headers, Json(response))
 }
 
+/// Texts a login code, within the per-phone, per-IP and global limits.
 pub async fn login(
     State(app_state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request_headers: HeaderMap,
     jar: CookieJar,
     Json(payload): Json<LoginRequest>,
-) -> Result<(CookieJar, Json<LoginResponse>), StatusCode> {
+) -> Result<(CookieJar, Json<LoginResponse>), (StatusCode, HeaderMap, Json<LoginRefusedResponse>)> {
     info!("Login request for phone: {}", payload.phone);
 
-    if payload.phone.is_empty() {
-        let response = LoginResponse {
-            success: false,
-            message: "Phone number is required".to_string(),
-            validity_seconds: 0,
-        };
-        return Ok((jar, Json(response)));
-    }
+    let limits = &app_state.config.login_limits;
+    let phone =
+        normalize_login_phone(&payload.phone, &limits.country_codes).map_err(login_refused)?;
 
-    // Initialize MessageCentral client using config from app state
-    let client = MessageCentralClient::new(
-        app_state.config.message_central_customer_id.clone(),
-        app_state.config.message_central_email.clone(),
-        app_state.config.message_central_password_b64.clone(),
+    let ip = client_ip(
+        &request_headers,
+        peer,
+        app_state.config.client_ip_header.as_deref(),
     );
-
-    // Generate token
-    let token = match client.generate_token().await {
-        Ok(token) => token,
+    match check_login_limits(&app_state.db, limits, &phone, Some(&ip), Utc::now()).await {
+        Ok(None) => {}
+        Ok(Some(refusal)) => {
+            warn!(
+                "Refused login code for phone {} from {}: {}",
+                phone,
+                ip,
+                refusal.code()
+            );
+            return Err(login_refused(refusal));
+        }
         Err(e) => {
-            error!("Failed to generate MessageCentral token: {}", e);
+            error!("Failed to check login limits: {}", e);
             let response = LoginResponse {
                 success: false,
                 message: "Failed to send OTP. Please try again.".to_string(),
@@ -268,16 +231,12 @@ pub async fn login(
             };
             return Ok((jar, Json(response)));
         }
-    };
+    }
 
-    // Send OTP
-    let otp_data = match client
-        .send_otp(token.clone(), "1".to_string(), 6, payload.phone.clone())
-        .await
-    {
-        Ok(data) => data,
+    let challenge = match app_state.otp.send_code(&phone).await {
+        Ok(challenge) => challenge,
         Err(e) => {
-            error!("Failed to send OTP: {}", e);
+            error!("Failed to send OTP via {}: {}", app_state.otp.provider(), e);
             let response = LoginResponse {
                 success: false,
                 message: "Failed to send OTP. Please try again.".to_string(),
@@ -287,8 +246,12 @@ pub async fn login(
         }
     };
 
-    // Encrypt the auth token before storing
-    let encrypted_token = match encrypt_token(&token, &app_state.config.jwt_secret) {
+    // Seal the provider's auth token before storing
+    let encrypted_token = match app_state
+        .config
+        .sealer
+        .seal(OtpPurpose::Login.as_str(), &challenge.provider_token)
+    {
         Ok(encrypted) => encrypted,
         Err(e) => {
             error!("Failed to encrypt auth token: {}", e);
@@ -301,149 +264,152 @@ pub async fn login(
         }
     };
 
-    // Store verification ID and encrypted auth token in the cookie (JSON encoded)
-    let verification_data = serde_json::json!({
-        "verification_id": otp_data.verification_id,
-        "auth_token": encrypted_token
-    });
+    // Remember which phone the code went to; the browser only gets the
+    // session id
+    let now = Utc::now();
+    let session_id = match create_otp_session(
+        &app_state.db,
+        OtpPurpose::Login,
+        &phone,
+        &challenge.verification_id,
+        &encrypted_token,
+        now + chrono::Duration::seconds(challenge.validity_seconds),
+        now,
+    )
+    .await
+    {
+        Ok(session_id) => session_id,
+        Err(e) => {
+            error!("Failed to store OTP session: {}", e);
+            let response = LoginResponse {
+                success: false,
+                message: "Failed to send OTP. Please try again.".to_string(),
+                validity_seconds: 0,
+            };
+            return Ok((jar, Json(response)));
+        }
+    };
 
-    let verification_cookie =
-        Cookie::build(("otp_verification_data", verification_data.to_string()))
-            .http_only(true)
-            .secure(false) // Set to false for development (non-HTTPS)
-            .path("/")
-            .build();
+    let session_cookie = Cookie::build((OTP_SESSION_COOKIE, session_id))
+        .http_only(true)
+        .secure(false) // Set to false for development (non-HTTPS)
+        .path("/")
+        .build();
 
-    info!("Setting verification cookie: {}", otp_data.verification_id);
-    let jar = jar.add(verification_cookie);
+    info!(
+        "Started OTP session for verification {}",
+        challenge.verification_id
+    );
+    let jar = jar.add(session_cookie);
 
     info!(
         "Successfully sent OTP to phone {} with {} seconds validity",
-        payload.phone, otp_data.timeout
+        phone, challenge.validity_seconds
     );
 
     let response = LoginResponse {
         success: true,
         message: "OTP sent successfully".to_string(),
-        validity_seconds: otp_data.timeout.parse::<f64>().unwrap_or(600.0) as u32,
+        validity_seconds: challenge.validity_seconds as u32,
     };
 
     Ok((jar, Json(response)))
 }
 
+/// Checks the code against the session `login` started, and signs in as the
+/// phone that session sent the code to.
 pub async fn verify_otp(
     State(app_state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request_headers: HeaderMap,
     jar: CookieJar,
     Json(payload): Json<VerifyOtpRequest>,
 ) -> Result<(CookieJar, HeaderMap, Json<VerifyOtpResponse>), StatusCode> {
-    info!(
-        "OTP verification for phone: {}, code: {}",
-        payload.phone, payload.code
-    );
-
     let mut headers = HeaderMap::new();
+    let failure = |message: &str| VerifyOtpResponse {
+        success: false,
+        message: message.to_string(),
+    };
 
-    // Get verification data from cookie
-    let (verification_id, auth_token) = match jar.get("otp_verification_data") {
-        Some(cookie) => {
-            match serde_json::from_str::<serde_json::Value>(cookie.value()) {
-                Ok(data) => {
-                    let verification_id =
-                        data["verification_id"].as_str().unwrap_or("").to_string();
-                    let encrypted_token = data["auth_token"].as_str().unwrap_or("").to_string();
-
-                    // Decrypt the auth token
-                    match decrypt_token(&encrypted_token, &app_state.config.jwt_secret) {
-                        Ok(auth_token) => (verification_id, auth_token),
-                        Err(e) => {
-                            error!("Failed to decrypt auth token: {}", e);
-                            let response = VerifyOtpResponse {
-                                success: false,
-                                message: "Verification session expired. Please try again."
-                                    .to_string(),
-                            };
-                            return Ok((jar, headers, Json(response)));
-                        }
-                    }
-                }
-                Err(_) => {
-                    warn!(
-                        "Invalid verification cookie format for phone: {}",
-                        payload.phone
-                    );
-                    let response = VerifyOtpResponse {
-                        success: false,
-                        message: "Verification session expired. Please try again.".to_string(),
-                    };
-                    return Ok((jar, headers, Json(response)));
-                }
+    let Some(session_id) = jar
+        .get(OTP_SESSION_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+    else {
+        warn!("OTP verification attempted without a session cookie");
+        let response = failure("Verification session expired. Please try again.");
+        return Ok((jar, headers, Json(response)));
+    };
+
+    let session =
+        match begin_otp_attempt(&app_state.db, &session_id, OtpPurpose::Login, Utc::now()).await {
+            Ok(OtpAttempt::Allowed(session)) => session,
+            Ok(OtpAttempt::Expired) => {
+                let response = failure("Verification session expired. Please try again.");
+                return Ok((jar, headers, Json(response)));
             }
-        }
-        None => {
-            warn!(
-                "OTP verification attempted without valid session cookie for phone: {}",
-                payload.phone
-            );
-            let response = VerifyOtpResponse {
-                success: false,
-                message: "Verification session expired. Please try again.".to_string(),
-            };
+            Ok(OtpAttempt::TooManyAttempts) => {
+                warn!("Too many OTP attempts for session {}", session_id);
+                let response = failure("Too many incorrect codes. Please request a new one.");
+                return Ok((jar, headers, Json(response)));
+            }
+            Err(e) => {
+                error!("Failed to load OTP session: {}", e);
+                let response = failure("Database error. Please try again.");
+                return Ok((jar, headers, Json(response)));
+            }
+        };
+
+    // Open the sealed auth token
+    let auth_token = match app_state
+        .config
+        .sealer
+        .open(OtpPurpose::Login.as_str(), &session.provider_token)
+    {
+        Ok(auth_token) => auth_token,
+        Err(e) => {
+            error!("Failed to decrypt auth token: {}", e);
+            let response = failure("Verification session expired. Please try again.");
             return Ok((jar, headers, Json(response)));
         }
     };
 
-    // Clean up verification cookies now that we've read the data
-    let remove_old_verification_cookie = Cookie::build(("otp_verification_id", ""))
-        .http_only(true)
-        .secure(false)
-        .path("/")
-        .max_age(time::Duration::seconds(0))
-        .build();
+    if let Err(e) = app_state
+        .otp
+        .check_code(&session.verification_id, &auth_token, &payload.code)
+        .await
+    {
+        warn!(
+            "OTP verification failed for phone {} (attempt {}): {}",
+            session.phone_number, session.attempts, e
+        );
+        let response = failure("Invalid OTP code. Please try again.");
+        return Ok((jar, headers, Json(response)));
+    }
 
-    let remove_verification_cookie = Cookie::build(("otp_verification_data", ""))
+    // The code is single use
+    if let Err(e) = delete_otp_session(&app_state.db, &session.id).await {
+        error!("Failed to delete OTP session: {}", e);
+    }
+    let remove_session_cookie = Cookie::build((OTP_SESSION_COOKIE, ""))
         .http_only(true)
         .secure(false)
         .path("/")
         .max_age(time::Duration::seconds(0))
         .build();
+    let jar = jar.add(remove_session_cookie);
 
-    let jar = jar
-        .add(remove_old_verification_cookie)
-        .add(remove_verification_cookie);
-
-    // Initialize MessageCentral client using config from app state
-    let client = MessageCentralClient::new(
-        app_state.config.message_central_customer_id.clone(),
-        app_state.config.message_central_email.clone(),
-        app_state.config.message_central_password_b64.clone(),
-    );
-
-    // Verify OTP using the stored auth token
-    if let Err(e) = client
-        .verify_otp(auth_token, verification_id, payload.code)
-        .await
-    {
-        warn!("OTP verification failed for phone {}: {}", payload.phone, e);
-        let response = VerifyOtpResponse {
-            success: false,
-            message: "Invalid OTP code. Please try again.".to_string(),
-        };
-        return Ok((jar, headers, Json(response)));
-    }
+    let phone = session.phone_number;
 
     // Find or create user
-    let user = match get_user_by_phone(&app_state.db, &payload.phone).await {
+    let user = match get_user_by_phone(&app_state.db, &phone).await {
         Ok(Some(user)) => user,
         Ok(None) => {
             // Create new user
-            match create_user(&app_state.db, &payload.phone).await {
+            match create_user(&app_state.db, &phone).await {
                 Ok(user) => user,
                 Err(e) => {
-                    error!("Failed to create user for phone {}: {}", payload.phone, e);
-                    let response = VerifyOtpResponse {
-                        success: false,
-                        message: "Failed to create user account.".to_string(),
-                    };
+                    error!("Failed to create user for phone {}: {}", phone, e);
+                    let response = failure("Failed to create user account.");
                     return Ok((jar, headers, Json(response)));
                 }
             }
@@ -451,56 +417,48 @@ pub async fn verify_otp(
         Err(e) => {
             error!(
                 "Database error when looking up user by phone {}: {}",
-                payload.phone, e
+                phone, e
             );
-            let response = VerifyOtpResponse {
-                success: false,
-                message: "Database error. Please try again.".to_string(),
-            };
+            let response = failure("Database error. Please try again.");
             return Ok((jar, headers, Json(response)));
         }
     };
 
-    // Generate JWT token using config from app state
-
-    let claims = Claims {
-        user_id: user.id,
-        exp: (SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
-            + (90 * 24 * 60 * 60)) as usize, // 90 days
-    };
-
-    let jwt_token = match encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(app_state.config.jwt_secret.as_ref()),
-    ) {
-        Ok(token) => token,
-        Err(e) => {
-            error!("Failed to generate JWT token for user {}: {}", user.id, e);
-            let response = VerifyOtpResponse {
-                success: false,
-                message: "Failed to generate authentication token.".to_string(),
-            };
-            return Ok((jar, headers, Json(response)));
-        }
-    };
+    let user_agent = request_headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok());
+    let ip = client_ip(
+        &request_headers,
+        peer,
+        app_state.config.client_ip_header.as_deref(),
+    );
+    let session_secret =
+        match create_session(&app_state.db, user.id, user_agent, Some(&ip), Utc::now()).await {
+            Ok((session, secret)) => {
+                info!("Started session {} for user {}", session.id, user.id);
+                secret
+            }
+            Err(e) => {
+                error!("Failed to create session for user {}: {}", user.id, e);
+                let response = failure("Failed to sign you in. Please try again.");
+                return Ok((jar, headers, Json(response)));
+            }
+        };
 
-    // Set JWT cookie
-    let auth_cookie = Cookie::build(("auth_token", jwt_token))
+    // The server ends idle sessions; this only caps how long a browser keeps
+    // the cookie around
+    let auth_cookie = Cookie::build((AUTH_COOKIE, session_secret))
         .http_only(true)
         .secure(false) // Set to false for development (non-HTTPS)
         .path("/")
-        .max_age(time::Duration::days(90))
+        .max_age(time::Duration::days(AUTH_COOKIE_MAX_AGE_DAYS))
         .build();
 
     let jar = jar.add(auth_cookie);
 
     info!(
         "Successfully authenticated user {} with phone {}",
-        user.id, payload.phone
+        user.id, phone
     );
 
     let response = VerifyOtpResponse {
@@ -518,17 +476,24 @@ pub async fn login_page() -> Html<String> {
     Html(template.render().unwrap())
 }
 
-pub async fn logout(jar: CookieJar) -> (CookieJar, Redirect) {
+/// Ends this browser's session, server-side as well as the cookie.
+pub async fn logout(State(app_state): State<AppState>, jar: CookieJar) -> (CookieJar, Redirect) {
+    if let Some(auth_cookie) = jar.get(AUTH_COOKIE) {
+        if let Err(e) = delete_session_by_secret(&app_state.db, auth_cookie.value()).await {
+            error!("Failed to delete session on logout: {}", e);
+        }
+    }
+
     // Clear auth token cookie
-    let clear_auth_cookie = Cookie::build(("auth_token", ""))
+    let clear_auth_cookie = Cookie::build((AUTH_COOKIE, ""))
         .http_only(true)
         .secure(false)
         .path("/")
         .max_age(time::Duration::seconds(0))
         .build();
 
-    // Clear any remaining verification cookies
-    let clear_verification_cookie = Cookie::build(("otp_verification_data", ""))
+    // Clear any pending verification
+    let clear_verification_cookie = Cookie::build((OTP_SESSION_COOKIE, ""))
         .http_only(true)
         .secure(false)
         .path("/")
@@ -539,3 +504,103 @@ pub async fn logout(jar: CookieJar) -> (CookieJar, Redirect) {
 
     (jar, Redirect::to("/login"))
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use common::init_database;
+    use common::otp::{LocalOtpProvider, OtpBackend, OtpKeys};
+    use common::rate_limit::LoginLimits;
+    use common::sealing::Sealer;
+    use common::sms::{MemorySender, SmsBackend};
+    use tempfile::NamedTempFile;
+
+    pub(crate) async fn test_state() -> (AppState, Arc<MemorySender>, NamedTempFile) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database_url = format!("sqlite:{}", temp_file.path().display());
+        let db = init_database(&database_url).await.unwrap();
+
+        let sender = Arc::new(MemorySender::new());
+        let sms: Arc<dyn SmsSender> = sender.clone();
+        let otp_keys = OtpKeys::new("jwt-secret", &[]);
+        let otp = Arc::new(LocalOtpProvider::new(
+            db.clone(),
+            sms.clone(),
+            otp_keys.clone(),
+        ));
+        let config = crate::Config {
+            database_url,
+            otp_backend: OtpBackend::Local,
+            otp_keys,
+            sealer: Sealer::new("jwt-secret", &[]),
+            admin_token: "admin-token".to_string(),
+            sms_backend: SmsBackend::Console,
+            inbound_sms_secret: None,
+            login_limits: LoginLimits::default(),
+            client_ip_header: None,
+        };
+        let state = AppState {
+            db,
+            config,
+            sms,
+            otp,
+        };
+        (state, sender, temp_file)
+    }
+
+    fn peer() -> ConnectInfo<SocketAddr> {
+        ConnectInfo("127.0.0.1:4000".parse().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_verify_signs_in_the_phone_the_code_was_texted_to() {
+        let (state, sender, _temp_file) = test_state().await;
+
+        // The victim has an account; the attacker requests a code for their
+        // own number and then names the victim's when verifying
+        let victim = create_user(&state.db, "5550000001").await.unwrap();
+        let (jar, _) = login(
+            State(state.clone()),
+            peer(),
+            HeaderMap::new(),
+            CookieJar::new(),
+            Json(LoginRequest {
+                phone: "5550000002".to_string(),
+            }),
+        )
+        .await
+        .ok()
+        .unwrap();
+
+        let sent = sender.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, "+15550000002");
+        // "Your HBD Bot code is 123456. It expires in ..."
+        let code = sent[0].body.split_whitespace().nth(5).unwrap();
+        let code = code.trim_end_matches('.');
+
+        let payload = serde_json::from_value(serde_json::json!({
+            "phone": victim.phone_number,
+            "code": code,
+        }))
+        .unwrap();
+        let (jar, _, Json(response)) = verify_otp(
+            State(state.clone()),
+            peer(),
+            HeaderMap::new(),
+            jar,
+            Json(payload),
+        )
+        .await
+        .unwrap();
+        assert!(response.success, "{}", response.message);
+
+        let session = current_session(&jar, &state.db).await.unwrap();
+        let attacker = get_user_by_phone(&state.db, "5550000002")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(session.user_id, attacker.id);
+        assert_ne!(session.user_id, victim.id);
+    }
+}
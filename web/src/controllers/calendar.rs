@@ -0,0 +1,73 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Redirect},
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use common::ics::{get_user_id_by_ics_token, render_feed, rotate_ics_token};
+use common::{get_reminders_by_user_id, get_user_by_id};
+use log::error;
+
+use super::auth::{verify_session_cookie, AppState};
+
+/// Path of the feed for a token, as shown on the settings page
+pub fn feed_path(token: &str) -> String {
+    format!("/calendar/{}/birthdays.ics", token)
+}
+
+/// Serves a user's reminders as an iCalendar feed. The token in the URL is the
+/// only credential, since calendar apps can't log in.
+pub async fn ics_feed(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let user_id = get_user_id_by_ics_token(&state.db, &token)
+        .await
+        .map_err(|e| {
+            error!("Failed to look up calendar token: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let user = get_user_by_id(&state.db, user_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load user {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let reminders = get_reminders_by_user_id(&state.db, user_id)
+        .await
+        .map_err(|e| {
+            error!("Failed to load reminders for user {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/calendar; charset=utf-8"),
+            (header::CACHE_CONTROL, "private, max-age=3600"),
+        ],
+        render_feed(&user, &reminders, Utc::now()),
+    ))
+}
+
+/// Issues a new feed token, so anyone holding the old URL loses access.
+pub async fn rotate_token(State(state): State<AppState>, jar: CookieJar) -> Redirect {
+    let Ok(user_id) = verify_session_cookie(&jar, &state.db).await else {
+        return Redirect::to("/login");
+    };
+
+    match rotate_ics_token(&state.db, user_id).await {
+        Ok(_) => Redirect::to("/settings?rotated=1"),
+        Err(e) => {
+            error!(
+                "Failed to rotate calendar token for user {}: {}",
+                user_id, e
+            );
+            Redirect::to("/settings")
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use askama::Template;
+use axum::{
+    extract::{Multipart, State},
+    response::{Html, Redirect},
+    Form,
+};
+use axum_extra::extract::cookie::CookieJar;
+use common::events::EventType;
+use common::get_reminders_by_user_id;
+use common::import::{
+    find_duplicates, import_reminders, parse_import, CsvColumns, ImportFormat, ImportRow,
+};
+use log::error;
+use std::collections::HashMap;
+
+use crate::controllers::auth::{verify_session_cookie, AppState};
+
+#[derive(Template)]
+#[template(path = "import.html")]
+pub struct ImportTemplate {
+    pub error_message: String,
+    /// `ImportFormat::as_str`, e.g. "csv"
+    pub format: String,
+    pub name_column: String,
+    pub date_column: String,
+}
+
+#[derive(Template)]
+#[template(path = "import_preview.html")]
+pub struct ImportPreviewTemplate {
+    pub format: String,
+    pub name_column: String,
+    pub date_column: String,
+    /// The uploaded file, posted back so the import reads the same rows
+    pub content: String,
+    pub rows: Vec<ImportPreviewRow>,
+    /// Entries that couldn't be read
+    pub skipped: Vec<String>,
+}
+
+pub struct ImportPreviewRow {
+    pub index: usize,
+    pub name: String,
+    /// e.g. "3/14/1990", or "3/14" when the year isn't known
+    pub date: String,
+    /// e.g. "Anniversary"; empty for birthdays
+    pub occasion: String,
+    /// Matches an existing reminder or an earlier row. The import skips these,
+    /// so they get no checkbox.
+    pub duplicate: bool,
+}
+
+impl ImportTemplate {
+    fn new(error_message: String, format: &str, columns: CsvColumns) -> Self {
+        ImportTemplate {
+            error_message,
+            format: format.to_string(),
+            name_column: columns.name,
+            date_column: columns.date,
+        }
+    }
+}
+
+pub async fn import_form(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<Html<String>, Redirect> {
+    if verify_session_cookie(&jar, &state.db).await.is_err() {
+        return Err(Redirect::to("/login"));
+    }
+
+    let template = ImportTemplate::new(
+        String::new(),
+        ImportFormat::Csv.as_str(),
+        CsvColumns::default(),
+    );
+    Ok(Html(template.render().unwrap()))
+}
+
+/// Reads the uploaded file and shows what would be imported.
+pub async fn import_preview(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    mut multipart: Multipart,
+) -> Result<Html<String>, Redirect> {
+    let user_id = match verify_session_cookie(&jar, &state.db).await {
+        Ok(user_id) => user_id,
+        Err(_) => return Err(Redirect::to("/login")),
+    };
+
+    let mut fields: HashMap<String, String> = HashMap::new();
+    loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) => {
+                let name = field.name().unwrap_or_default().to_string();
+                let Ok(bytes) = field.bytes().await else {
+                    return Ok(upload_error(
+                        &fields,
+                        "Couldn't read the upload. Please try again.",
+                    ));
+                };
+                let value = String::from_utf8_lossy(&bytes);
+                fields.insert(name, value.trim_start_matches('\u{feff}').to_string());
+            }
+            Ok(None) => break,
+            Err(_) => {
+                return Ok(upload_error(
+                    &fields,
+                    "Couldn't read the upload. Please try again.",
+                ));
+            }
+        }
+    }
+
+    let format_value = fields.get("format").cloned().unwrap_or_default();
+    let columns = csv_columns(&fields);
+    let content = fields.get("file").cloned().unwrap_or_default();
+    let Some(format) = ImportFormat::parse(&format_value) else {
+        return Ok(upload_error(&fields, "Invalid file type selected"));
+    };
+    if content.trim().is_empty() {
+        return Ok(upload_error(&fields, "Please choose a file to import"));
+    }
+
+    let parsed = match parse_import(format, &content, &columns) {
+        Ok(parsed) => parsed,
+        Err(e) => return Ok(upload_error(&fields, &e)),
+    };
+    if parsed.rows.is_empty() && parsed.skipped.is_empty() {
+        return Ok(upload_error(&fields, "No dates found in that file"));
+    }
+
+    let existing = match get_reminders_by_user_id(&state.db, user_id).await {
+        Ok(reminders) => reminders,
+        Err(_) => return Err(Redirect::to("/login")),
+    };
+    let duplicates = find_duplicates(&existing, &parsed.rows);
+
+    let rows = parsed
+        .rows
+        .iter()
+        .zip(duplicates)
+        .enumerate()
+        .map(|(index, (row, duplicate))| ImportPreviewRow {
+            index,
+            name: row.name.clone(),
+            date: display_date(row),
+            occasion: match row.event_type {
+                EventType::Birthday => String::new(),
+                ref event_type => event_type.display_name().to_string(),
+            },
+            duplicate,
+        })
+        .collect();
+
+    let template = ImportPreviewTemplate {
+        format: format.as_str().to_string(),
+        name_column: columns.name,
+        date_column: columns.date,
+        content,
+        rows,
+        skipped: parsed.skipped,
+    };
+    Ok(Html(template.render().unwrap()))
+}
+
+/// Adds the rows ticked on the preview, all or nothing.
+pub async fn import_commit(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<Redirect, Html<String>> {
+    let user_id = match verify_session_cookie(&jar, &state.db).await {
+        Ok(user_id) => user_id,
+        Err(_) => return Ok(Redirect::to("/login")),
+    };
+
+    let format_value = form.get("format").cloned().unwrap_or_default();
+    let columns = csv_columns(&form);
+    let content = form.get("content").cloned().unwrap_or_default();
+    let parsed = ImportFormat::parse(&format_value)
+        .ok_or_else(|| "Invalid file type selected".to_string())
+        .and_then(|format| parse_import(format, &content, &columns));
+    let parsed = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            let template = ImportTemplate::new(e, &format_value, columns);
+            return Err(Html(template.render().unwrap()));
+        }
+    };
+
+    let selected: Vec<ImportRow> = parsed
+        .rows
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| form.contains_key(&format!("row_{}", index)))
+        .map(|(_, row)| row)
+        .collect();
+    if selected.is_empty() {
+        return Ok(Redirect::to("/"));
+    }
+
+    match import_reminders(&state.db, user_id, &selected).await {
+        Ok(_) => Ok(Redirect::to("/")),
+        Err(e) => {
+            error!("Import failed for user {}: {}", user_id, e);
+            let template = ImportTemplate::new(
+                "Failed to import. Nothing was added; please try again.".to_string(),
+                &format_value,
+                columns,
+            );
+            Err(Html(template.render().unwrap()))
+        }
+    }
+}
+
+fn csv_columns(fields: &HashMap<String, String>) -> CsvColumns {
+    let defaults = CsvColumns::default();
+    let column = |key: &str, default: String| {
+        fields
+            .get(key)
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .unwrap_or(default)
+    };
+
+    CsvColumns {
+        name: column("name_column", defaults.name),
+        date: column("date_column", defaults.date),
+    }
+}
+
+fn upload_error(fields: &HashMap<String, String>, message: &str) -> Html<String> {
+    let format = fields.get("format").map(String::as_str).unwrap_or("csv");
+    let template = ImportTemplate::new(message.to_string(), format, csv_columns(fields));
+    Html(template.render().unwrap())
+}
+
+fn display_date(row: &ImportRow) -> String {
+    if row.year_known {
+        row.birthdate.format("%-m/%-d/%Y").to_string()
+    } else {
+        row.birthdate.format("%-m/%-d").to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preview_row(index: usize, name: &str, duplicate: bool) -> ImportPreviewRow {
+        ImportPreviewRow {
+            index,
+            name: name.to_string(),
+            date: "3/14/1990".to_string(),
+            occasion: String::new(),
+            duplicate,
+        }
+    }
+
+    #[test]
+    fn test_duplicates_cannot_be_ticked() {
+        let template = ImportPreviewTemplate {
+            format: "csv".to_string(),
+            name_column: "Name".to_string(),
+            date_column: "Birthday".to_string(),
+            content: String::new(),
+            rows: vec![preview_row(0, "Jane", false), preview_row(1, "Jane", true)],
+            skipped: Vec::new(),
+        };
+        let html = template.render().unwrap();
+
+        assert!(html.contains("name=\"row_0\""));
+        assert!(!html.contains("name=\"row_1\""));
+        assert!(html.contains("Duplicate, skipped"));
+    }
+}
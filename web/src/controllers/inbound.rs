@@ -0,0 +1,135 @@
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
+use chrono::Utc;
+use common::commands::{execute_command, parse_command};
+use common::inbound::{
+    normalize_phone_number, parse_compliance_keyword, verify_signature, ComplianceKeyword,
+    HELP_REPLY, START_REPLY, STOP_REPLY,
+};
+use common::{get_user_by_phone, set_user_sms_opt_out};
+use log::{error, info, warn};
+use serde::Deserialize;
+
+use super::auth::AppState;
+
+#[derive(Deserialize)]
+pub struct InboundSms {
+    from: String,
+    body: String,
+}
+
+/// Receives replies forwarded by the SMS provider: compliance keywords
+/// (STOP/START/HELP) and reminder commands like `ADD Jane 3/14/1990`.
+/// Requests must carry an `X-Signature` header signed with
+/// `INBOUND_SMS_SECRET`.
+pub async fn inbound_sms_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    let Some(secret) = state.config.inbound_sms_secret.as_deref() else {
+        warn!("Inbound SMS received but INBOUND_SMS_SECRET is not set");
+        return StatusCode::SERVICE_UNAVAILABLE;
+    };
+
+    let signature = headers
+        .get("x-signature")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !verify_signature(secret, signature, &body, Utc::now().timestamp()) {
+        warn!("Rejected inbound SMS with a bad signature");
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    let message: InboundSms = match serde_json::from_slice(&body) {
+        Ok(message) => message,
+        Err(e) => {
+            warn!("Malformed inbound SMS payload: {}", e);
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+
+    let phone_number = match normalize_phone_number(&message.from) {
+        Some(phone_number) => phone_number,
+        None => {
+            warn!("Inbound SMS from unsupported number {}", message.from);
+            return StatusCode::OK;
+        }
+    };
+
+    let user = match get_user_by_phone(&state.db, &phone_number).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            // Acknowledge so the provider doesn't keep redelivering
+            info!("Inbound SMS from unknown number {}", phone_number);
+            return StatusCode::OK;
+        }
+        Err(e) => {
+            error!("Failed to look up inbound SMS sender: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    };
+
+    let Some(keyword) = parse_compliance_keyword(&message.body) else {
+        // Anything else is a reminder command, unless they've opted out
+        if user.sms_opted_out_at.is_some() {
+            info!("Ignoring command from opted-out user {}", user.id);
+            return StatusCode::OK;
+        }
+
+        let reply = match parse_command(&message.body) {
+            Ok(command) => match execute_command(&state.db, &user, command, Utc::now()).await {
+                Ok(reply) => reply,
+                Err(e) => {
+                    error!("Failed to run SMS command for user {}: {}", user.id, e);
+                    return StatusCode::INTERNAL_SERVER_ERROR;
+                }
+            },
+            Err(usage) => usage,
+        };
+        send_reply(&state, &user.phone_number, &reply).await;
+        return StatusCode::OK;
+    };
+
+    let reply = match keyword {
+        ComplianceKeyword::Stop | ComplianceKeyword::Start => {
+            let opted_out = keyword == ComplianceKeyword::Stop;
+            if let Err(e) = set_user_sms_opt_out(&state.db, user.id, opted_out).await {
+                error!("Failed to update opt-out for user {}: {}", user.id, e);
+                return StatusCode::INTERNAL_SERVER_ERROR;
+            }
+            info!(
+                "User {} {}",
+                user.id,
+                if opted_out {
+                    "opted out"
+                } else {
+                    "opted back in"
+                }
+            );
+            if opted_out {
+                STOP_REPLY
+            } else {
+                START_REPLY
+            }
+        }
+        ComplianceKeyword::Help => HELP_REPLY,
+    };
+
+    send_reply(&state, &user.phone_number, reply).await;
+
+    StatusCode::OK
+}
+
+async fn send_reply(state: &AppState, phone_number: &str, reply: &str) {
+    if let Err(e) = state
+        .sms
+        .send_sms(&format!("+1{}", phone_number), reply)
+        .await
+    {
+        error!("Failed to send reply to {}: {}", phone_number, e);
+    }
+}
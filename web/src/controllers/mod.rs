@@ -1,5 +1,12 @@
+pub mod account;
+pub mod api;
 pub mod app;
 pub mod auth;
+pub mod calendar;
+pub mod import;
+pub mod inbound;
 pub mod pages;
+pub mod sessions;
 pub mod settings;
 pub mod sweeper;
+pub mod tokens;
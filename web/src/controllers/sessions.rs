@@ -0,0 +1,131 @@
+use axum::{
+    extract::{Path, State},
+    response::Redirect,
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar};
+use chrono::Utc;
+use common::sessions::{get_sessions_by_user_id, revoke_all_sessions, revoke_session, DbSession};
+use log::{error, info};
+
+use crate::controllers::auth::{current_session, AppState, AUTH_COOKIE};
+
+/// Longest user agent shown on the settings page, in characters
+const MAX_DEVICE_LEN: usize = 60;
+
+/// A signed-in browser as listed on the settings page
+#[derive(Debug, Clone)]
+pub struct SessionDisplay {
+    pub id: i64,
+    /// The browser's user agent, shortened; "Unknown device" without one
+    pub device: String,
+    pub ip: String,
+    pub created_at: String,
+    pub last_seen_at: String,
+    /// The session viewing the page
+    pub current: bool,
+}
+
+/// The user's active sessions for the settings page, marking `current`;
+/// empty if they can't be loaded.
+pub async fn session_displays(state: &AppState, current: &DbSession) -> Vec<SessionDisplay> {
+    let sessions = match get_sessions_by_user_id(&state.db, current.user_id, Utc::now()).await {
+        Ok(sessions) => sessions,
+        Err(e) => {
+            error!(
+                "Failed to load sessions for user {}: {}",
+                current.user_id, e
+            );
+            return Vec::new();
+        }
+    };
+
+    sessions
+        .into_iter()
+        .map(|session| SessionDisplay {
+            id: session.id,
+            device: match session.user_agent {
+                Some(agent) if agent.chars().count() > MAX_DEVICE_LEN => {
+                    format!(
+                        "{}…",
+                        agent.chars().take(MAX_DEVICE_LEN).collect::<String>()
+                    )
+                }
+                Some(agent) if !agent.trim().is_empty() => agent,
+                _ => "Unknown device".to_string(),
+            },
+            ip: session.ip.unwrap_or_default(),
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+            current: session.id == current.id,
+        })
+        .collect()
+}
+
+fn clear_auth_cookie(jar: CookieJar) -> CookieJar {
+    let cookie = Cookie::build((AUTH_COOKIE, ""))
+        .http_only(true)
+        .secure(false)
+        .path("/")
+        .max_age(time::Duration::seconds(0))
+        .build();
+    jar.add(cookie)
+}
+
+/// Signs one session out. Revoking the current one signs this browser out.
+pub async fn revoke_session_handler(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Path(session_id): Path<i64>,
+) -> (CookieJar, Redirect) {
+    let Ok(current) = current_session(&jar, &state.db).await else {
+        return (jar, Redirect::to("/login"));
+    };
+
+    match revoke_session(&state.db, current.user_id, session_id).await {
+        Ok(true) => {
+            info!(
+                "Revoked session {} for user {}",
+                session_id, current.user_id
+            );
+            if session_id == current.id {
+                return (clear_auth_cookie(jar), Redirect::to("/login"));
+            }
+            (jar, Redirect::to("/settings?session_revoked=1"))
+        }
+        Ok(false) => (jar, Redirect::to("/settings")),
+        Err(e) => {
+            error!(
+                "Failed to revoke session {} for user {}: {}",
+                session_id, current.user_id, e
+            );
+            (jar, Redirect::to("/settings"))
+        }
+    }
+}
+
+/// Signs the user out on every device, this one included.
+pub async fn revoke_all_sessions_handler(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> (CookieJar, Redirect) {
+    let Ok(current) = current_session(&jar, &state.db).await else {
+        return (jar, Redirect::to("/login"));
+    };
+
+    match revoke_all_sessions(&state.db, current.user_id).await {
+        Ok(count) => {
+            info!(
+                "Revoked all {} sessions for user {}",
+                count, current.user_id
+            );
+            (clear_auth_cookie(jar), Redirect::to("/login"))
+        }
+        Err(e) => {
+            error!(
+                "Failed to revoke sessions for user {}: {}",
+                current.user_id, e
+            );
+            (jar, Redirect::to("/settings"))
+        }
+    }
+}
@@ -5,27 +5,43 @@ use axum::{
     Form,
 };
 use axum_extra::extract::cookie::CookieJar;
-use common::{get_user_by_id, update_user_settings};
+use common::birthday::LeapDayPolicy;
+use common::ics::get_or_create_ics_token;
+use common::notice::{display_notice_offsets, parse_notice_offsets};
+use common::{
+    get_user_by_id, update_user_leap_day_policy, update_user_settings, SUPPORTED_TIMEZONES,
+};
 use serde::Deserialize;
 use std::collections::HashMap;
 
-use crate::controllers::auth::{verify_jwt_cookie, AppState};
+use crate::controllers::auth::{current_session, AppState};
+use crate::controllers::calendar::feed_path;
+use crate::controllers::sessions::{session_displays, SessionDisplay};
+use crate::controllers::tokens::{token_displays, ApiTokenDisplay};
 
 #[derive(Template)]
 #[template(path = "settings.html")]
 pub struct SettingsTemplate {
     pub error_message: String,
     pub success_message: String,
-    pub days_notice: i64,
+    pub notice_offsets: String,
     pub send_hour: i64,
     pub iana_tz: String,
+    /// "feb28" or "mar1"
+    pub leap_day_policy: String,
+    /// Path of the calendar feed; the page prefixes it with its own origin
+    pub ics_path: String,
+    pub api_tokens: Vec<ApiTokenDisplay>,
+    pub sessions: Vec<SessionDisplay>,
 }
 
 #[derive(Deserialize)]
 pub struct SettingsForm {
-    days_notice: i64,
+    notice_offsets: String,
     send_hour: i64,
     iana_tz: String,
+    #[serde(default)]
+    leap_day_policy: String,
 }
 
 pub async fn settings_form(
@@ -33,14 +49,15 @@ pub async fn settings_form(
     jar: CookieJar,
     Query(params): Query<HashMap<String, String>>,
 ) -> Result<Html<String>, Redirect> {
-    // Verify JWT cookie and get user ID
-    let user_id = match verify_jwt_cookie(&jar, &state.config.jwt_secret) {
-        Ok(user_id) => user_id,
+    // Verify session cookie and get user ID
+    let session = match current_session(&jar, &state.db).await {
+        Ok(session) => session,
         Err(_) => {
             // Redirect to login if authentication fails
             return Err(Redirect::to("/login"));
         }
     };
+    let user_id = session.user_id;
 
     // Get user from database
     let user = match get_user_by_id(&state.db, user_id).await {
@@ -55,9 +72,22 @@ pub async fn settings_form(
         }
     };
 
+    let ics_path = match get_or_create_ics_token(&state.db, user_id).await {
+        Ok(token) => feed_path(&token),
+        Err(_) => return Err(Redirect::to("/login")),
+    };
+    let api_tokens = token_displays(&state, user_id).await;
+    let sessions = session_displays(&state, &session).await;
+
     // Check for success message from query parameters
-    let success_message = if params.get("success").is_some() {
+    let success_message = if params.contains_key("success") {
         "Settings saved successfully!".to_string()
+    } else if params.contains_key("rotated") {
+        "Calendar link reset. Update it in your calendar app.".to_string()
+    } else if params.contains_key("token_revoked") {
+        "Token revoked.".to_string()
+    } else if params.contains_key("session_revoked") {
+        "Signed out that device.".to_string()
     } else {
         String::new()
     };
@@ -66,9 +96,13 @@ pub async fn settings_form(
     let template = SettingsTemplate {
         error_message: String::new(),
         success_message,
-        days_notice: user.days_notice,
+        notice_offsets: display_notice_offsets(&user.notice_offsets),
         send_hour: user.send_hour,
         iana_tz: user.iana_tz,
+        leap_day_policy: user.leap_day_policy.as_str().to_string(),
+        ics_path,
+        api_tokens,
+        sessions,
     };
 
     Ok(Html(template.render().unwrap()))
@@ -79,69 +113,103 @@ pub async fn update_settings(
     jar: CookieJar,
     Form(form): Form<SettingsForm>,
 ) -> Result<Redirect, Html<String>> {
-    // Verify JWT cookie and get user ID
-    let user_id = match verify_jwt_cookie(&jar, &state.config.jwt_secret) {
-        Ok(user_id) => user_id,
+    // Verify session cookie and get user ID
+    let session = match current_session(&jar, &state.db).await {
+        Ok(session) => session,
         Err(_) => {
             // Redirect to login if authentication fails
             return Ok(Redirect::to("/login"));
         }
     };
+    let user_id = session.user_id;
+
+    // The error pages below show the feed link too
+    let ics_path = get_or_create_ics_token(&state.db, user_id)
+        .await
+        .map(|token| feed_path(&token))
+        .unwrap_or_default();
+    let api_tokens = token_displays(&state, user_id).await;
+    let sessions = session_displays(&state, &session).await;
 
     // Validate form data
-    if form.days_notice < 1 || form.days_notice > 14 {
+    let notice_offsets = match parse_notice_offsets(&form.notice_offsets) {
+        Ok(offsets) => offsets,
+        Err(e) => {
+            let template = SettingsTemplate {
+                error_message: e,
+                success_message: String::new(),
+                notice_offsets: form.notice_offsets,
+                send_hour: form.send_hour,
+                iana_tz: form.iana_tz,
+                leap_day_policy: form.leap_day_policy,
+                ics_path,
+                api_tokens,
+                sessions,
+            };
+            return Err(Html(template.render().unwrap()));
+        }
+    };
+
+    if form.send_hour < 0 || form.send_hour > 23 {
         let template = SettingsTemplate {
-            error_message: "Days notice must be between 1 and 14".to_string(),
+            error_message: "Send hour must be between 0 and 23".to_string(),
             success_message: String::new(),
-            days_notice: form.days_notice,
+            notice_offsets: form.notice_offsets,
             send_hour: form.send_hour,
             iana_tz: form.iana_tz,
+            leap_day_policy: form.leap_day_policy,
+            ics_path,
+            api_tokens,
+            sessions,
         };
         return Err(Html(template.render().unwrap()));
     }
 
-    if form.send_hour < 0 || form.send_hour > 23 {
+    // Validate timezone (US timezones only)
+    if !SUPPORTED_TIMEZONES.contains(&form.iana_tz.as_str()) {
         let template = SettingsTemplate {
-            error_message: "Send hour must be between 0 and 23".to_string(),
+            error_message: "Invalid timezone selected".to_string(),
             success_message: String::new(),
-            days_notice: form.days_notice,
+            notice_offsets: form.notice_offsets,
             send_hour: form.send_hour,
             iana_tz: form.iana_tz,
+            leap_day_policy: form.leap_day_policy,
+            ics_path,
+            api_tokens,
+            sessions,
         };
         return Err(Html(template.render().unwrap()));
     }
 
-    // Validate timezone (US timezones only)
-    let valid_timezones = vec![
-        "America/New_York",
-        "America/Chicago",
-        "America/Denver",
-        "America/Los_Angeles",
-        "America/Anchorage",
-        "Pacific/Honolulu",
-    ];
-
-    if !valid_timezones.contains(&form.iana_tz.as_str()) {
+    let Some(leap_day_policy) = LeapDayPolicy::parse(&form.leap_day_policy) else {
         let template = SettingsTemplate {
-            error_message: "Invalid timezone selected".to_string(),
+            error_message: "Invalid leap day option selected".to_string(),
             success_message: String::new(),
-            days_notice: form.days_notice,
+            notice_offsets: form.notice_offsets,
             send_hour: form.send_hour,
             iana_tz: form.iana_tz,
+            leap_day_policy: form.leap_day_policy,
+            ics_path,
+            api_tokens,
+            sessions,
         };
         return Err(Html(template.render().unwrap()));
-    }
+    };
 
     // Update user settings in database
-    match update_user_settings(
+    let updated = match update_user_settings(
         &state.db,
         user_id,
-        form.days_notice,
+        &notice_offsets,
         form.send_hour,
         &form.iana_tz,
     )
     .await
     {
+        Ok(_) => update_user_leap_day_policy(&state.db, user_id, leap_day_policy).await,
+        Err(e) => Err(e),
+    };
+    match updated {
         Ok(_) => {
             // Success - redirect to settings page with success message
             Ok(Redirect::to("/settings?success=1"))
@@ -151,9 +219,13 @@ pub async fn update_settings(
             let template = SettingsTemplate {
                 error_message: "Failed to save settings. Please try again.".to_string(),
                 success_message: String::new(),
-                days_notice: form.days_notice,
+                notice_offsets: form.notice_offsets,
                 send_hour: form.send_hour,
                 iana_tz: form.iana_tz,
+                leap_day_policy: form.leap_day_policy,
+                ics_path,
+                api_tokens,
+                sessions,
             };
             Err(Html(template.render().unwrap()))
         }
@@ -1,64 +1,78 @@
-use axum::{extract::State, http::StatusCode};
+use axum::{extract::State, http::StatusCode, response::Json};
 use axum_extra::{
     headers::{authorization::Bearer, Authorization},
     TypedHeader,
 };
-use chrono::Utc;
-use common::{surge::SurgeClient, update_user_last_digest_at};
+use common::{clock::SystemClock, ledger::get_dead_letters};
 use log::{error, info};
-use sms_sweeper::get_birthday_messages;
-use tokio::time::{sleep, Duration};
+use serde::Serialize;
+use sms_sweeper::{run_sweeper, RunReport};
 
 use super::auth::AppState;
 
 pub async fn run_sweeper_handler(
     TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
     State(state): State<AppState>,
-) -> StatusCode {
+) -> Result<Json<RunReport>, StatusCode> {
     let token = auth.token();
     if token != state.config.admin_token {
         error!("Unauthorized access attempt");
-        return StatusCode::UNAUTHORIZED;
+        return Err(StatusCode::UNAUTHORIZED);
     }
     info!("Starting birthday reminder processing via API");
-    let current_utc = Utc::now();
-    let messages = match get_birthday_messages(&state.db, current_utc).await {
-        Ok(messages) => messages,
-        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
-    };
 
-    let client = SurgeClient::new(state.config.surge_api_key.clone());
-    let mut total_sms_sent = 0;
+    let report = run_sweeper(&state.db, state.sms.as_ref(), &SystemClock)
+        .await
+        .map_err(|e| {
+            error!("Sweeper run failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
 
-    for (i, sms_message) in messages.iter().enumerate() {
-        if i > 0 {
-            sleep(Duration::from_secs(1)).await;
-            info!("Sending SMS to {}", sms_message.phone_number);
-            match client
-                .send_sms(
-                    format!("+1{}", &sms_message.phone_number),
-                    sms_message.message.clone(),
-                )
-                .await
-            {
-                Ok(_) => {
-                    info!("Successfully sent SMS to {}", sms_message.phone_number);
-                    total_sms_sent += 1;
-                    match update_user_last_digest_at(&state.db, sms_message.user_id).await {
-                        Ok(_) => {
-                            info!("Successfully updated user last digest at");
-                        }
-                        Err(e) => {
-                            error!("Failed to update user last digest at: {}", e);
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("Failed to send SMS to {}: {}", sms_message.phone_number, e);
-                }
-            }
-        }
+    info!("Total SMS sent: {}", report.sent);
+    Ok(Json(report))
+}
+
+#[derive(Serialize)]
+pub struct DeadLetter {
+    id: i64,
+    user_id: i64,
+    provider: String,
+    body: String,
+    attempts: i64,
+    error: Option<String>,
+    created_at: String,
+    updated_at: String,
+}
+
+/// Lists digests that were dead-lettered after a permanent provider error or
+/// too many failed attempts.
+pub async fn dead_letters_handler(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<DeadLetter>>, StatusCode> {
+    if auth.token() != state.config.admin_token {
+        error!("Unauthorized access attempt");
+        return Err(StatusCode::UNAUTHORIZED);
     }
-    info!("Total SMS sent: {}", total_sms_sent);
-    StatusCode::OK
+
+    let dead_letters = get_dead_letters(&state.db).await.map_err(|e| {
+        error!("Failed to load dead letters: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(
+        dead_letters
+            .into_iter()
+            .map(|message| DeadLetter {
+                id: message.id,
+                user_id: message.user_id,
+                provider: message.provider,
+                body: message.body,
+                attempts: message.attempts,
+                error: message.error,
+                created_at: message.created_at,
+                updated_at: message.updated_at,
+            })
+            .collect(),
+    ))
 }
@@ -0,0 +1,169 @@
+use askama::Template;
+use axum::{
+    extract::{Path, State},
+    response::{Html, Redirect},
+    Form,
+};
+use axum_extra::extract::cookie::CookieJar;
+use common::api_tokens::{
+    count_active_api_tokens, create_api_token, get_api_tokens_by_user_id, revoke_api_token,
+    validate_token_request, TokenScope, MAX_ACTIVE_TOKENS,
+};
+use log::{error, info};
+use serde::Deserialize;
+
+use crate::controllers::auth::{verify_session_cookie, AppState};
+
+#[derive(Template)]
+#[template(path = "api_token.html")]
+pub struct ApiTokenTemplate {
+    pub error_message: String,
+    pub name: String,
+    /// The new token, shown this once; empty when creation failed
+    pub token: String,
+}
+
+/// A token as listed on the settings page
+#[derive(Debug, Clone)]
+pub struct ApiTokenDisplay {
+    pub id: i64,
+    pub name: String,
+    /// e.g. "read, write"
+    pub scopes: String,
+    pub created_at: String,
+    /// "Never" until first used
+    pub last_used_at: String,
+    pub revoked: bool,
+}
+
+#[derive(Deserialize)]
+pub struct CreateTokenForm {
+    name: String,
+    scope_read: Option<String>,
+    scope_write: Option<String>,
+}
+
+/// The user's tokens for the settings page; empty if they can't be loaded.
+pub async fn token_displays(state: &AppState, user_id: i64) -> Vec<ApiTokenDisplay> {
+    let tokens = match get_api_tokens_by_user_id(&state.db, user_id).await {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            error!("Failed to load API tokens for user {}: {}", user_id, e);
+            return Vec::new();
+        }
+    };
+
+    tokens
+        .into_iter()
+        .map(|token| ApiTokenDisplay {
+            id: token.id,
+            name: token.name,
+            scopes: token
+                .scopes
+                .iter()
+                .map(|scope| scope.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            created_at: token.created_at,
+            last_used_at: token.last_used_at.unwrap_or_else(|| "Never".to_string()),
+            revoked: token.revoked_at.is_some(),
+        })
+        .collect()
+}
+
+/// Creates a personal access token and shows it once.
+pub async fn create_token(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Form(form): Form<CreateTokenForm>,
+) -> Result<Html<String>, Redirect> {
+    let user_id = match verify_session_cookie(&jar, &state.db).await {
+        Ok(user_id) => user_id,
+        Err(_) => return Err(Redirect::to("/login")),
+    };
+    let error_page = |message: String, name: String| {
+        let template = ApiTokenTemplate {
+            error_message: message,
+            name,
+            token: String::new(),
+        };
+        Html(template.render().unwrap())
+    };
+
+    let mut scopes = Vec::new();
+    if form.scope_read.is_some() {
+        scopes.push(TokenScope::Read);
+    }
+    if form.scope_write.is_some() {
+        scopes.push(TokenScope::Write);
+    }
+    let name = match validate_token_request(&form.name, &scopes) {
+        Ok(name) => name,
+        Err(e) => return Ok(error_page(e, form.name)),
+    };
+
+    match count_active_api_tokens(&state.db, user_id).await {
+        Ok(count) if count >= MAX_ACTIVE_TOKENS => {
+            return Ok(error_page(
+                format!(
+                    "You can have at most {} tokens. Revoke one you no longer use first.",
+                    MAX_ACTIVE_TOKENS
+                ),
+                name,
+            ));
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!("Failed to count API tokens for user {}: {}", user_id, e);
+            return Ok(error_page(
+                "Failed to create the token. Please try again.".to_string(),
+                name,
+            ));
+        }
+    }
+
+    match create_api_token(&state.db, user_id, &name, &scopes).await {
+        Ok((token, secret)) => {
+            info!("Created API token {} for user {}", token.id, user_id);
+            let template = ApiTokenTemplate {
+                error_message: String::new(),
+                name: token.name,
+                token: secret,
+            };
+            Ok(Html(template.render().unwrap()))
+        }
+        Err(e) => {
+            error!("Failed to create API token for user {}: {}", user_id, e);
+            Ok(error_page(
+                "Failed to create the token. Please try again.".to_string(),
+                name,
+            ))
+        }
+    }
+}
+
+/// Revokes a token; requests using it are refused from now on.
+pub async fn revoke_token(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Path(token_id): Path<i64>,
+) -> Redirect {
+    let Ok(user_id) = verify_session_cookie(&jar, &state.db).await else {
+        return Redirect::to("/login");
+    };
+
+    match revoke_api_token(&state.db, user_id, token_id).await {
+        Ok(true) => {
+            info!("Revoked API token {} for user {}", token_id, user_id);
+            Redirect::to("/settings?token_revoked=1")
+        }
+        Ok(false) => Redirect::to("/settings"),
+        Err(e) => {
+            error!(
+                "Failed to revoke API token {} for user {}: {}",
+                token_id, user_id, e
+            );
+            Redirect::to("/settings")
+        }
+    }
+}
@@ -1,15 +1,16 @@
 use axum::{
-    extract::State,
-    http::StatusCode,
     response::Json,
     routing::{delete, get, post},
     Router,
 };
 use common::{
-    create_reminder, create_user as db_create_user, get_all_reminders, get_all_users, init_database,
+    init_database,
+    otp::{OtpBackend, OtpKeys},
+    rate_limit::LoginLimits,
+    sealing::Sealer,
+    sms::SmsBackend,
 };
-use log;
-use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 use tokio::net::TcpListener;
 use tower_http::services::ServeDir;
 
@@ -21,57 +22,53 @@ use std::env;
 #[derive(Clone)]
 pub struct Config {
     pub database_url: String,
-    pub message_central_customer_id: String,
-    pub message_central_email: String,
-    pub message_central_password_b64: String,
-    pub jwt_secret: String,
+    /// Who sends and checks login codes
+    pub otp_backend: OtpBackend,
+    /// Hashes locally issued codes under a key derived from `JWT_SECRET`,
+    /// still checking ones hashed under previous secrets
+    pub otp_keys: OtpKeys,
+    /// Seals provider tokens under keys derived from `JWT_SECRET`, still
+    /// opening ones sealed under previous secrets
+    pub sealer: Sealer,
     pub admin_token: String,
-    pub surge_api_key: String,
+    pub sms_backend: SmsBackend,
+    /// Shared secret for verifying inbound SMS webhooks; the endpoint is
+    /// disabled when unset
+    pub inbound_sms_secret: Option<String>,
+    pub login_limits: LoginLimits,
+    /// Header the proxy in front of us puts the client's IP in, e.g.
+    /// "Fly-Client-IP". Unset means the socket's peer address is used.
+    pub client_ip_header: Option<String>,
 }
 
 impl Config {
     fn from_env() -> Result<Self, String> {
         let database_url =
             env::var("DATABASE_URL").map_err(|_| "DATABASE_URL environment variable not set")?;
-        let message_central_customer_id = env::var("MESSAGE_CENTRAL_CUSTOMER_ID")
-            .map_err(|_| "MESSAGE_CENTRAL_CUSTOMER_ID environment variable not set")?;
-        let message_central_email = env::var("MESSAGE_CENTRAL_EMAIL")
-            .map_err(|_| "MESSAGE_CENTRAL_EMAIL environment variable not set")?;
-        let message_central_password_b64 = env::var("MESSAGE_CENTRAL_PASSWORD_B64")
-            .map_err(|_| "MESSAGE_CENTRAL_PASSWORD_B64 environment variable not set")?;
-        let jwt_secret =
-            env::var("JWT_SECRET").map_err(|_| "JWT_SECRET environment variable not set")?;
+        let otp_backend = OtpBackend::from_env()?;
+        let otp_keys = OtpKeys::from_env()?;
+        let sealer = Sealer::from_env()?;
         let admin_token =
             env::var("ADMIN_TOKEN").map_err(|_| "ADMIN_TOKEN environment variable not set")?;
-        let surge_api_key =
-            env::var("SURGE_API_KEY").map_err(|_| "SURGE_API_KEY environment variable not set")?;
+        let sms_backend = SmsBackend::from_env()?;
+        let inbound_sms_secret = env::var("INBOUND_SMS_SECRET").ok();
+        let login_limits = LoginLimits::from_env()?;
+        let client_ip_header = env::var("CLIENT_IP_HEADER").ok();
 
         Ok(Config {
             database_url,
-            message_central_customer_id,
-            message_central_email,
-            message_central_password_b64,
-            jwt_secret,
+            otp_backend,
+            otp_keys,
+            sealer,
             admin_token,
-            surge_api_key,
+            sms_backend,
+            inbound_sms_secret,
+            login_limits,
+            client_ip_header,
         })
     }
 }
 
-#[derive(Serialize, Deserialize)]
-struct User {
-    id: i64,
-    phone_number: String,
-}
-
-#[derive(Serialize, Deserialize)]
-struct Reminder {
-    id: i64,
-    user_id: i64,
-    name: String,
-    birthdate: i64, // Unix timestamp in milliseconds
-}
-
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::Builder::from_default_env()
@@ -85,10 +82,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         log::error!("Failed to parse environment variables: {}", e);
         e
     })?;
+    log::info!("Sealing with key {}", config.sealer.current_key_id());
 
     let db = init_database(&config.database_url).await?;
     sqlx::migrate!("../migrations").run(&db).await?;
-    let state = AppState { db, config };
+    let sms = config.sms_backend.build();
+    let otp = config
+        .otp_backend
+        .build(db.clone(), sms.clone(), &config.otp_keys);
+    let state = AppState {
+        db,
+        config,
+        sms,
+        otp,
+    };
 
     let app = Router::new()
         .route("/", get(controllers::app::root))
@@ -104,27 +111,80 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             "/reminder",
             delete(controllers::app::delete_reminder_handler),
         )
+        .route(
+            "/import",
+            get(controllers::import::import_form).post(controllers::import::import_commit),
+        )
+        .route("/import/preview", post(controllers::import::import_preview))
         .route(
             "/settings",
             get(controllers::settings::settings_form).post(controllers::settings::update_settings),
         )
+        .route(
+            "/settings/calendar-token",
+            post(controllers::calendar::rotate_token),
+        )
+        .route(
+            "/settings/api-tokens",
+            post(controllers::tokens::create_token),
+        )
+        .route(
+            "/settings/api-tokens/{id}/revoke",
+            post(controllers::tokens::revoke_token),
+        )
+        .route(
+            "/settings/sessions/{id}/revoke",
+            post(controllers::sessions::revoke_session_handler),
+        )
+        .route(
+            "/settings/sessions/revoke-all",
+            post(controllers::sessions::revoke_all_sessions_handler),
+        )
+        .route(
+            "/calendar/{token}/birthdays.ics",
+            get(controllers::calendar::ics_feed),
+        )
+        .route(
+            "/settings/export.json",
+            get(controllers::account::export_json_download),
+        )
+        .route(
+            "/settings/export/reminders.csv",
+            get(controllers::account::export_reminders_csv),
+        )
+        .route(
+            "/settings/export/settings.csv",
+            get(controllers::account::export_settings_csv),
+        )
+        .route(
+            "/account/delete",
+            post(controllers::account::delete_account_start),
+        )
+        .route(
+            "/account/delete/confirm",
+            post(controllers::account::delete_account_confirm),
+        )
         .route(
             "/login",
             get(controllers::auth::login_page).post(controllers::auth::login),
         )
         .route("/verify-otp", post(controllers::auth::verify_otp))
         .route("/logout", get(controllers::auth::logout))
+        .route(
+            "/sms/inbound",
+            post(controllers::inbound::inbound_sms_handler),
+        )
         .route("/privacy", get(controllers::pages::privacy))
         .route("/health", get(health))
         .route(
             "/run-sweeper",
             post(controllers::sweeper::run_sweeper_handler),
         )
-        .route("/users", get(get_users).post(create_user))
         .route(
-            "/reminders",
-            get(get_reminders).post(create_reminder_endpoint),
+            "/admin/dead-letters",
+            get(controllers::sweeper::dead_letters_handler),
         )
+        .nest("/api/v1", controllers::api::router())
         .nest_service(
             "/static",
             ServeDir::new(if std::path::Path::new("static").exists() {
@@ -138,7 +198,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let listener = TcpListener::bind("0.0.0.0:8080").await?;
     log::info!("Server running on http://0.0.0.0:8080");
 
-    axum::serve(listener, app).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
@@ -149,81 +213,3 @@ async fn health() -> Json<serde_json::Value> {
         "service": "hbd-bot-web"
     }))
 }
-
-async fn get_users(State(state): State<AppState>) -> Result<Json<Vec<User>>, StatusCode> {
-    let db_users = get_all_users(&state.db)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let users: Vec<User> = db_users
-        .into_iter()
-        .map(|db_user| User {
-            id: db_user.id,
-            phone_number: db_user.phone_number,
-        })
-        .collect();
-
-    Ok(Json(users))
-}
-
-async fn create_user(
-    State(state): State<AppState>,
-    Json(payload): Json<serde_json::Value>,
-) -> Result<Json<User>, StatusCode> {
-    let phone_number = payload["phone_number"]
-        .as_str()
-        .ok_or(StatusCode::BAD_REQUEST)?;
-
-    let db_user = db_create_user(&state.db, phone_number)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let user = User {
-        id: db_user.id,
-        phone_number: db_user.phone_number,
-    };
-
-    Ok(Json(user))
-}
-
-async fn get_reminders(State(state): State<AppState>) -> Result<Json<Vec<Reminder>>, StatusCode> {
-    let db_reminders = get_all_reminders(&state.db)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let reminders: Vec<Reminder> = db_reminders
-        .into_iter()
-        .map(|db_reminder| Reminder {
-            id: db_reminder.id,
-            user_id: db_reminder.user_id,
-            name: db_reminder.name,
-            birthdate: db_reminder.birthdate,
-        })
-        .collect();
-
-    Ok(Json(reminders))
-}
-
-async fn create_reminder_endpoint(
-    State(state): State<AppState>,
-    Json(payload): Json<serde_json::Value>,
-) -> Result<Json<Reminder>, StatusCode> {
-    let user_id = payload["user_id"].as_i64().ok_or(StatusCode::BAD_REQUEST)?;
-    let name = payload["name"].as_str().ok_or(StatusCode::BAD_REQUEST)?;
-    let birthdate = payload["birthdate"]
-        .as_str()
-        .ok_or(StatusCode::BAD_REQUEST)?;
-
-    let db_reminder = create_reminder(&state.db, user_id, name, birthdate)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let reminder = Reminder {
-        id: db_reminder.id,
-        user_id: db_reminder.user_id,
-        name: db_reminder.name,
-        birthdate: db_reminder.birthdate,
-    };
-
-    Ok(Json(reminder))
-}
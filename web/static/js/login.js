@@ -102,6 +102,13 @@ document.addEventListener("DOMContentLoaded", function () {
         if (data.success) {
           currentPhone = phone;
           showOtpMode(data.validity_seconds, phone);
+        } else if (data.retry_after_seconds) {
+          // Throttled: keep the button off until it's worth trying again
+          submitBtn.textContent = "I agree";
+          showError(data.message);
+          setTimeout(function () {
+            submitBtn.disabled = false;
+          }, data.retry_after_seconds * 1000);
         } else {
           // Re-enable form on error
           submitBtn.disabled = false;
@@ -119,7 +126,6 @@ document.addEventListener("DOMContentLoaded", function () {
   }
 
   function verifyOtp() {
-    const phone = phoneInput.value.trim();
     const code = codeInput.value.trim();
 
     if (!code) {
@@ -135,7 +141,7 @@ document.addEventListener("DOMContentLoaded", function () {
       headers: {
         "Content-Type": "application/json",
       },
-      body: JSON.stringify({ phone: phone, code: code }),
+      body: JSON.stringify({ code: code }),
     })
       .then((response) => {
         // Check for HX-Redirect header